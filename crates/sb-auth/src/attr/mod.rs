@@ -1,10 +1,14 @@
 use async_trait::async_trait;
 use serde_json::Value;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use crate::model::{AttributeMap, ResourceUrn};
 use sb_types::prelude::Subject;
 
+#[cfg(feature = "attr-storage")]
+pub mod storage;
+
 #[async_trait]
 pub trait AttributeProvider: Send + Sync {
     async fn attributes_for(&self, subject: &Subject, resource: &ResourceUrn) -> AttributeMap;
@@ -22,6 +26,36 @@ impl AttributeProvider for StaticAttributeProvider {
     }
 }
 
+/// Asks each provider in turn and merges the answers in that order, so a
+/// later provider overrides the keys it shares with an earlier one.
+#[derive(Clone, Default)]
+pub struct CompositeAttributeProvider {
+    providers: Vec<Arc<dyn AttributeProvider>>,
+}
+
+impl CompositeAttributeProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, provider: Arc<dyn AttributeProvider>) -> Self {
+        self.providers.push(provider);
+        self
+    }
+}
+
+#[async_trait]
+impl AttributeProvider for CompositeAttributeProvider {
+    async fn attributes_for(&self, subject: &Subject, resource: &ResourceUrn) -> AttributeMap {
+        let mut merged = AttributeMap::default();
+        for provider in &self.providers {
+            let attrs = provider.attributes_for(subject, resource).await;
+            merged = merged.merged(&attrs);
+        }
+        merged
+    }
+}
+
 pub fn attrs_from_map(map: HashMap<String, Value>) -> AttributeMap {
     AttributeMap(Value::Object(map.into_iter().collect()))
 }
@@ -0,0 +1,345 @@
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use sb_storage::prelude::{make_record_id, Entity, Repository};
+use sb_types::prelude::{Subject, TenantId};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::AttributeProvider;
+use crate::cache::{Lookup, Lru};
+use crate::model::{AttributeMap, ResourceUrn};
+
+/// Attributes of a subject, exposed to policies as `attrs.subject.*`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SubjectAttributes {
+    pub id: String,
+    pub tenant: TenantId,
+    pub subject_id: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+    #[serde(default)]
+    pub department: Option<String>,
+    #[serde(default)]
+    pub clearance: Option<String>,
+    /// Anything else, merged in next to the fields above.
+    #[serde(default)]
+    pub extra: Map<String, Value>,
+}
+
+impl Entity for SubjectAttributes {
+    const TABLE: &'static str = "auth_subject_attrs";
+    type Key = String;
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl SubjectAttributes {
+    pub fn new(tenant: TenantId, subject_id: impl Into<String>) -> Self {
+        let subject_id = subject_id.into();
+        Self {
+            id: Self::record_id(&tenant, &subject_id),
+            tenant,
+            subject_id,
+            roles: Vec::new(),
+            groups: Vec::new(),
+            department: None,
+            clearance: None,
+            extra: Map::new(),
+        }
+    }
+
+    pub fn record_id(tenant: &TenantId, subject_id: &str) -> String {
+        make_record_id(Self::TABLE, tenant, subject_id)
+    }
+
+    fn attributes(&self) -> Value {
+        let mut attrs = self.extra.clone();
+        attrs.insert("roles".into(), json!(self.roles));
+        attrs.insert("groups".into(), json!(self.groups));
+        if let Some(department) = &self.department {
+            attrs.insert("department".into(), json!(department));
+        }
+        if let Some(clearance) = &self.clearance {
+            attrs.insert("clearance".into(), json!(clearance));
+        }
+        Value::Object(attrs)
+    }
+}
+
+/// Attributes of a resource within a tenant, exposed to policies as
+/// `attrs.resource.*`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResourceAttributes {
+    pub id: String,
+    pub tenant: TenantId,
+    pub resource: String,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub classification: Option<String>,
+    #[serde(default)]
+    pub extra: Map<String, Value>,
+}
+
+impl Entity for ResourceAttributes {
+    const TABLE: &'static str = "auth_resource_attrs";
+    type Key = String;
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl ResourceAttributes {
+    pub fn new(tenant: TenantId, resource: impl Into<String>) -> Self {
+        let resource = resource.into();
+        Self {
+            id: Self::record_id(&tenant, &resource),
+            tenant,
+            resource,
+            owner: None,
+            classification: None,
+            extra: Map::new(),
+        }
+    }
+
+    pub fn record_id(tenant: &TenantId, resource: &str) -> String {
+        make_record_id(Self::TABLE, tenant, resource)
+    }
+
+    fn attributes(&self) -> Value {
+        let mut attrs = self.extra.clone();
+        attrs.insert("tenant".into(), json!(self.tenant.0));
+        if let Some(owner) = &self.owner {
+            attrs.insert("owner".into(), json!(owner));
+        }
+        if let Some(classification) = &self.classification {
+            attrs.insert("classification".into(), json!(classification));
+        }
+        Value::Object(attrs)
+    }
+}
+
+/// Record lookups remembered for a TTL, misses included, and bounded to
+/// `capacity` entries (least recently used go first) since record ids come
+/// from caller-supplied subjects and resources. Failed reads are not
+/// remembered so the next request tries again.
+struct LookupCache {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<Lru<String, Option<Value>>>,
+}
+
+impl LookupCache {
+    fn new() -> Self {
+        Self {
+            ttl: Duration::from_secs(30),
+            capacity: 10_000,
+            entries: Mutex::default(),
+        }
+    }
+
+    fn get(&self, record_id: &str) -> Option<Option<Value>> {
+        match self.entries.lock().get(record_id) {
+            Lookup::Hit(attrs) => Some(attrs),
+            Lookup::Expired | Lookup::Miss => None,
+        }
+    }
+
+    fn put(&self, record_id: String, attrs: Option<Value>) {
+        if !self.ttl.is_zero() && self.capacity > 0 {
+            self.entries
+                .lock()
+                .insert(record_id, attrs, self.ttl, self.capacity);
+        }
+    }
+
+    fn remove(&self, record_id: &str) {
+        self.entries.lock().remove(record_id);
+    }
+}
+
+/// Cached record attributes; `None` when there is no record or it could not
+/// be read.
+async fn lookup<E: Entity, R: Repository<E> + ?Sized>(
+    repo: &R,
+    cache: &LookupCache,
+    tenant: &TenantId,
+    record_id: String,
+    attributes: fn(&E) -> Value,
+) -> Option<Value> {
+    if let Some(attrs) = cache.get(&record_id) {
+        return attrs;
+    }
+    let record = repo.get(tenant, &record_id).await.ok()?;
+    let attrs = record.as_ref().map(attributes);
+    cache.put(record_id, attrs.clone());
+    attrs
+}
+
+/// Loads [`SubjectAttributes`] for the authenticated subject from sb-storage
+/// and returns them under `subject`.
+///
+/// Subjects without a record, or whose record cannot be read, get the
+/// `default` attributes. Wrap in [`TimeoutAttributeProvider`] to bound how
+/// long a slow store may hold up authorization.
+pub struct SubjectAttributeProvider<R: Repository<SubjectAttributes>> {
+    repo: Arc<R>,
+    default: Value,
+    cache: LookupCache,
+}
+
+impl<R: Repository<SubjectAttributes>> SubjectAttributeProvider<R> {
+    pub fn new(repo: Arc<R>) -> Self {
+        Self {
+            repo,
+            default: json!({}),
+            cache: LookupCache::new(),
+        }
+    }
+
+    pub fn with_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache.ttl = ttl;
+        self
+    }
+
+    /// Most lookups kept at once; zero disables the cache.
+    pub fn with_cache_capacity(mut self, capacity: usize) -> Self {
+        self.cache.capacity = capacity;
+        self
+    }
+
+    /// Attributes (the inside of `subject`) used when there is no record.
+    pub fn with_default(mut self, default: Value) -> Self {
+        self.default = default;
+        self
+    }
+
+    /// Forgets the cached lookup after the subject's record changed.
+    pub fn invalidate(&self, tenant: &TenantId, subject_id: &str) {
+        self.cache
+            .remove(&SubjectAttributes::record_id(tenant, subject_id));
+    }
+}
+
+#[async_trait]
+impl<R: Repository<SubjectAttributes>> AttributeProvider for SubjectAttributeProvider<R> {
+    async fn attributes_for(&self, subject: &Subject, _resource: &ResourceUrn) -> AttributeMap {
+        let record_id = SubjectAttributes::record_id(&subject.tenant, &subject.subject_id.0);
+        let attrs = lookup(
+            self.repo.as_ref(),
+            &self.cache,
+            &subject.tenant,
+            record_id,
+            SubjectAttributes::attributes,
+        )
+        .await;
+        AttributeMap(json!({ "subject": attrs.unwrap_or_else(|| self.default.clone()) }))
+    }
+}
+
+/// Loads [`ResourceAttributes`] for the requested resource in the subject's
+/// tenant from sb-storage and returns them under `resource`.
+///
+/// Resources without a record, or whose record cannot be read, get the
+/// `default` attributes plus their `tenant`.
+pub struct ResourceAttributeProvider<R: Repository<ResourceAttributes>> {
+    repo: Arc<R>,
+    default: Value,
+    cache: LookupCache,
+}
+
+impl<R: Repository<ResourceAttributes>> ResourceAttributeProvider<R> {
+    pub fn new(repo: Arc<R>) -> Self {
+        Self {
+            repo,
+            default: json!({}),
+            cache: LookupCache::new(),
+        }
+    }
+
+    pub fn with_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache.ttl = ttl;
+        self
+    }
+
+    /// Most lookups kept at once; zero disables the cache.
+    pub fn with_cache_capacity(mut self, capacity: usize) -> Self {
+        self.cache.capacity = capacity;
+        self
+    }
+
+    /// Attributes (the inside of `resource`) used when there is no record.
+    pub fn with_default(mut self, default: Value) -> Self {
+        self.default = default;
+        self
+    }
+
+    /// Forgets the cached lookup after the resource's record changed.
+    pub fn invalidate(&self, tenant: &TenantId, resource: &ResourceUrn) {
+        self.cache
+            .remove(&ResourceAttributes::record_id(tenant, &resource.0));
+    }
+}
+
+#[async_trait]
+impl<R: Repository<ResourceAttributes>> AttributeProvider for ResourceAttributeProvider<R> {
+    async fn attributes_for(&self, subject: &Subject, resource: &ResourceUrn) -> AttributeMap {
+        let record_id = ResourceAttributes::record_id(&subject.tenant, &resource.0);
+        let attrs = lookup(
+            self.repo.as_ref(),
+            &self.cache,
+            &subject.tenant,
+            record_id,
+            ResourceAttributes::attributes,
+        )
+        .await;
+        let attrs = attrs.unwrap_or_else(|| {
+            let mut fallback = self.default.clone();
+            if let Value::Object(map) = &mut fallback {
+                map.insert("tenant".into(), json!(subject.tenant.0));
+            }
+            fallback
+        });
+        AttributeMap(json!({ "resource": attrs }))
+    }
+}
+
+/// Gives `inner` at most `timeout` and answers with `fallback` when it takes
+/// longer, so a slow attribute source degrades instead of stalling
+/// authorization. Needs a Tokio runtime.
+pub struct TimeoutAttributeProvider {
+    inner: Arc<dyn AttributeProvider>,
+    timeout: Duration,
+    fallback: AttributeMap,
+}
+
+impl TimeoutAttributeProvider {
+    pub fn new(inner: Arc<dyn AttributeProvider>, timeout: Duration) -> Self {
+        Self {
+            inner,
+            timeout,
+            fallback: AttributeMap::default(),
+        }
+    }
+
+    pub fn with_fallback(mut self, fallback: AttributeMap) -> Self {
+        self.fallback = fallback;
+        self
+    }
+}
+
+#[async_trait]
+impl AttributeProvider for TimeoutAttributeProvider {
+    async fn attributes_for(&self, subject: &Subject, resource: &ResourceUrn) -> AttributeMap {
+        tokio::time::timeout(self.timeout, self.inner.attributes_for(subject, resource))
+            .await
+            .unwrap_or_else(|_| self.fallback.clone())
+    }
+}
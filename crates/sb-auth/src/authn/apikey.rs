@@ -0,0 +1,436 @@
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use parking_lot::Mutex;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sb_storage::prelude::{make_record_id, Entity, Repository};
+use sb_types::prelude::{Id, Subject, SubjectKind, TenantId};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use subtle::ConstantTimeEq;
+
+use super::{Authenticator, AuthnInput};
+use crate::errors::AuthError;
+use crate::model::{Action, AuthzRequest, Decision};
+use crate::pdp::Authorizer;
+use crate::store::{now_ms, select_all, storage_error};
+
+/// Claim carrying the key's scopes on subjects authenticated by API key.
+pub const API_KEY_SCOPES_CLAIM: &str = "api_key_scopes";
+/// Claim carrying the id of the key a subject authenticated with.
+pub const API_KEY_ID_CLAIM: &str = "api_key_id";
+
+/// What a key may be used for: resources matching `resource` (exact, or a
+/// prefix ending in `*`) with one of `actions`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ApiKeyScope {
+    pub resource: String,
+    pub actions: Vec<Action>,
+}
+
+impl ApiKeyScope {
+    pub fn new(resource: impl Into<String>, actions: Vec<Action>) -> Self {
+        Self {
+            resource: resource.into(),
+            actions,
+        }
+    }
+
+    pub fn permits(&self, resource: &str, action: &Action) -> bool {
+        let resource_ok = match self.resource.strip_suffix('*') {
+            Some(prefix) => resource.starts_with(prefix),
+            None => self.resource == resource,
+        };
+        resource_ok && self.actions.contains(action)
+    }
+}
+
+/// A stored key. Only a salted SHA-256 of the secret is kept.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ApiKeyRecord {
+    pub id: String,
+    pub tenant: TenantId,
+    pub key_id: String,
+    pub subject_id: String,
+    pub subject_kind: SubjectKind,
+    pub salt: String,
+    pub hash: String,
+    pub scopes: Vec<ApiKeyScope>,
+    #[serde(default)]
+    pub label: Option<String>,
+    pub created_at_ms: i64,
+    #[serde(default)]
+    pub expires_at_ms: Option<i64>,
+    #[serde(default)]
+    pub last_used_at_ms: Option<i64>,
+    #[serde(default)]
+    pub revoked_at_ms: Option<i64>,
+    /// Successor issued by `rotate`.
+    #[serde(default)]
+    pub rotated_to: Option<String>,
+}
+
+impl Entity for ApiKeyRecord {
+    const TABLE: &'static str = "auth_api_key";
+    type Key = String;
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl ApiKeyRecord {
+    pub fn record_id(tenant: &TenantId, key_id: &str) -> String {
+        make_record_id(Self::TABLE, tenant, key_id)
+    }
+
+    fn usable_at(&self, now_ms: i64) -> Result<(), &'static str> {
+        if self.revoked_at_ms.is_some() {
+            return Err("revoked");
+        }
+        match self.expires_at_ms {
+            Some(expires) if expires <= now_ms => Err("expired"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Parameters for a new key.
+#[derive(Clone, Debug)]
+pub struct ApiKeySpec {
+    pub tenant: TenantId,
+    pub subject_id: String,
+    pub subject_kind: SubjectKind,
+    pub scopes: Vec<ApiKeyScope>,
+    pub ttl: Option<Duration>,
+    pub label: Option<String>,
+}
+
+/// A freshly issued key. `secret` is the full key string and is not
+/// recoverable later.
+#[derive(Clone, Debug)]
+pub struct IssuedApiKey {
+    pub secret: String,
+    pub record: ApiKeyRecord,
+}
+
+struct CachedRecord {
+    record: ApiKeyRecord,
+    loaded_at: Instant,
+}
+
+/// Issues, rotates and revokes API keys stored in sb-storage, and
+/// authenticates `AuthnInput::ApiKey` against them.
+///
+/// Keys look like `<prefix>.<tenant>.<key_id>.<secret>` (tenant base64url
+/// encoded), so a lookup needs no cross-tenant index. Records are cached for
+/// `cache_ttl`; revocations made through this instance apply immediately,
+/// elsewhere within that TTL. `last_used_at_ms` is written at most once per
+/// `touch_interval`.
+pub struct ApiKeyAuthenticator<R: Repository<ApiKeyRecord>> {
+    repo: Arc<R>,
+    prefix: String,
+    cache_ttl: Duration,
+    touch_interval: Duration,
+    cache: Mutex<HashMap<String, CachedRecord>>,
+}
+
+impl<R: Repository<ApiKeyRecord>> ApiKeyAuthenticator<R> {
+    pub fn new(repo: Arc<R>, prefix: impl Into<String>) -> Self {
+        Self {
+            repo,
+            prefix: prefix.into(),
+            cache_ttl: Duration::from_secs(30),
+            touch_interval: Duration::from_secs(60),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = ttl;
+        self
+    }
+
+    pub fn with_touch_interval(mut self, interval: Duration) -> Self {
+        self.touch_interval = interval;
+        self
+    }
+
+    pub async fn issue(&self, spec: ApiKeySpec) -> Result<IssuedApiKey, AuthError> {
+        let key_id = random_token(9);
+        let secret = random_token(24);
+        let salt = random_token(16);
+        let now = now_ms();
+        let record = ApiKeyRecord {
+            id: ApiKeyRecord::record_id(&spec.tenant, &key_id),
+            tenant: spec.tenant,
+            key_id,
+            subject_id: spec.subject_id,
+            subject_kind: spec.subject_kind,
+            hash: digest(&salt, &secret),
+            salt,
+            scopes: spec.scopes,
+            label: spec.label,
+            created_at_ms: now,
+            expires_at_ms: spec.ttl.map(|ttl| now + ttl.as_millis() as i64),
+            last_used_at_ms: None,
+            revoked_at_ms: None,
+            rotated_to: None,
+        };
+        let record = self
+            .repo
+            .create(&record.tenant, &record)
+            .await
+            .map_err(storage_error)?;
+        let secret = format!(
+            "{}.{}.{}.{secret}",
+            self.prefix,
+            URL_SAFE_NO_PAD.encode(record.tenant.0.as_bytes()),
+            record.key_id
+        );
+        Ok(IssuedApiKey { secret, record })
+    }
+
+    pub async fn revoke(&self, tenant: &TenantId, key_id: &str) -> Result<(), AuthError> {
+        // `patch` upserts, so check first rather than create a stray record.
+        if self
+            .repo
+            .get(tenant, &ApiKeyRecord::record_id(tenant, key_id))
+            .await
+            .map_err(storage_error)?
+            .is_none()
+        {
+            return Err(AuthError::forbidden(format!("api key {key_id} not found")));
+        }
+        self.patch(tenant, key_id, json!({ "revoked_at_ms": now_ms() }))
+            .await
+            .map(|_| ())
+    }
+
+    /// Issues a successor with the same binding and scopes. The old key keeps
+    /// working for `overlap` (never past its own expiry) so clients can
+    /// switch over.
+    pub async fn rotate(
+        &self,
+        tenant: &TenantId,
+        key_id: &str,
+        overlap: Duration,
+    ) -> Result<IssuedApiKey, AuthError> {
+        let current = self
+            .repo
+            .get(tenant, &ApiKeyRecord::record_id(tenant, key_id))
+            .await
+            .map_err(storage_error)?
+            .ok_or_else(|| AuthError::forbidden(format!("api key {key_id} not found")))?;
+        let now = now_ms();
+        current
+            .usable_at(now)
+            .map_err(|state| AuthError::forbidden(format!("api key {key_id} is {state}")))?;
+
+        let successor = self
+            .issue(ApiKeySpec {
+                tenant: tenant.clone(),
+                subject_id: current.subject_id.clone(),
+                subject_kind: current.subject_kind.clone(),
+                scopes: current.scopes.clone(),
+                ttl: current
+                    .expires_at_ms
+                    .map(|expires| Duration::from_millis((expires - current.created_at_ms) as u64)),
+                label: current.label.clone(),
+            })
+            .await?;
+        let retire_at = current
+            .expires_at_ms
+            .map_or(now + overlap.as_millis() as i64, |expires| {
+                expires.min(now + overlap.as_millis() as i64)
+            });
+        self.patch(
+            tenant,
+            key_id,
+            json!({
+                "expires_at_ms": retire_at,
+                "rotated_to": successor.record.key_id,
+            }),
+        )
+        .await?;
+        Ok(successor)
+    }
+
+    pub async fn list(&self, tenant: &TenantId) -> Result<Vec<ApiKeyRecord>, AuthError> {
+        select_all(self.repo.as_ref(), tenant, json!({})).await
+    }
+
+    async fn patch(
+        &self,
+        tenant: &TenantId,
+        key_id: &str,
+        patch: Value,
+    ) -> Result<ApiKeyRecord, AuthError> {
+        let record_id = ApiKeyRecord::record_id(tenant, key_id);
+        self.cache.lock().remove(&record_id);
+        self.repo
+            .upsert(tenant, &record_id, patch, None)
+            .await
+            .map_err(storage_error)
+    }
+
+    async fn lookup(
+        &self,
+        tenant: &TenantId,
+        key_id: &str,
+    ) -> Result<Option<ApiKeyRecord>, AuthError> {
+        let record_id = ApiKeyRecord::record_id(tenant, key_id);
+        if let Some(cached) = self.cache.lock().get(&record_id) {
+            if cached.loaded_at.elapsed() < self.cache_ttl {
+                return Ok(Some(cached.record.clone()));
+            }
+        }
+        let record = self
+            .repo
+            .get(tenant, &record_id)
+            .await
+            .map_err(storage_error)?;
+        if let Some(record) = &record {
+            self.cache.lock().insert(
+                record_id,
+                CachedRecord {
+                    record: record.clone(),
+                    loaded_at: Instant::now(),
+                },
+            );
+        }
+        Ok(record)
+    }
+
+    /// Best effort: a failed write must not fail the request.
+    async fn touch(&self, record: &ApiKeyRecord, now: i64) {
+        let interval = self.touch_interval.as_millis() as i64;
+        if record
+            .last_used_at_ms
+            .is_some_and(|last| now - last < interval)
+        {
+            return;
+        }
+        if let Ok(updated) = self
+            .patch(
+                &record.tenant,
+                &record.key_id,
+                json!({ "last_used_at_ms": now }),
+            )
+            .await
+        {
+            self.cache.lock().insert(
+                updated.id.clone(),
+                CachedRecord {
+                    record: updated,
+                    loaded_at: Instant::now(),
+                },
+            );
+        }
+    }
+
+    fn parse<'a>(&self, key: &'a str) -> Option<(TenantId, &'a str, &'a str)> {
+        let mut parts = key.split('.');
+        let (prefix, tenant, key_id, secret) =
+            (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        if prefix != self.prefix || parts.next().is_some() {
+            return None;
+        }
+        let tenant = String::from_utf8(URL_SAFE_NO_PAD.decode(tenant).ok()?).ok()?;
+        Some((TenantId(tenant), key_id, secret))
+    }
+}
+
+#[async_trait]
+impl<R: Repository<ApiKeyRecord>> Authenticator for ApiKeyAuthenticator<R> {
+    async fn authenticate(&self, input: AuthnInput) -> Result<Subject, AuthError> {
+        let AuthnInput::ApiKey(key) = input else {
+            return Err(AuthError::unauthenticated("not an api key"));
+        };
+        let (tenant, key_id, secret) = self
+            .parse(&key)
+            .ok_or_else(|| AuthError::unauthenticated("malformed api key"))?;
+
+        let record = self.lookup(&tenant, key_id).await?;
+        // Hash even for unknown ids so timing does not reveal which exist.
+        let (salt, expected) = record.as_ref().map_or(("", ""), |record| {
+            (record.salt.as_str(), record.hash.as_str())
+        });
+        let matches: bool = digest(salt, secret)
+            .as_bytes()
+            .ct_eq(expected.as_bytes())
+            .into();
+        let record = match record {
+            Some(record) if matches => record,
+            _ => return Err(AuthError::unauthenticated("api key not recognized")),
+        };
+
+        let now = now_ms();
+        record.usable_at(now).map_err(|state| {
+            AuthError::unauthenticated(format!("api key {} is {state}", record.key_id))
+        })?;
+        self.touch(&record, now).await;
+
+        let mut claims = serde_json::Map::new();
+        claims.insert(API_KEY_ID_CLAIM.to_string(), json!(record.key_id));
+        claims.insert(API_KEY_SCOPES_CLAIM.to_string(), json!(record.scopes));
+        Ok(Subject {
+            kind: record.subject_kind,
+            subject_id: Id(record.subject_id),
+            tenant: record.tenant,
+            claims,
+        })
+    }
+}
+
+/// Caps another authorizer with API key scopes: subjects carrying
+/// [`API_KEY_SCOPES_CLAIM`] are denied anything their key's scopes do not
+/// cover, whatever `inner` would allow. Other subjects pass straight through.
+pub struct ApiKeyScopeAuthorizer {
+    inner: Arc<dyn Authorizer>,
+}
+
+impl ApiKeyScopeAuthorizer {
+    pub fn new(inner: Arc<dyn Authorizer>) -> Self {
+        Self { inner }
+    }
+}
+
+#[async_trait]
+impl Authorizer for ApiKeyScopeAuthorizer {
+    async fn decide(&self, request: &AuthzRequest) -> Result<Decision, AuthError> {
+        if let Some(raw) = request.subject.claims.get(API_KEY_SCOPES_CLAIM) {
+            let scopes: Vec<ApiKeyScope> = serde_json::from_value(raw.clone()).unwrap_or_default();
+            let permitted = scopes
+                .iter()
+                .any(|scope| scope.permits(&request.resource.0, &request.action));
+            if !permitted {
+                return Ok(Decision::deny("outside api key scope"));
+            }
+        }
+        self.inner.decide(request).await
+    }
+
+    fn policy_version(&self, subject: &Subject) -> Option<String> {
+        self.inner.policy_version(subject)
+    }
+}
+
+fn random_token(bytes: usize) -> String {
+    let mut buf = vec![0u8; bytes];
+    OsRng.fill_bytes(&mut buf);
+    URL_SAFE_NO_PAD.encode(buf)
+}
+
+fn digest(salt: &str, secret: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(b":");
+    hasher.update(secret.as_bytes());
+    URL_SAFE_NO_PAD.encode(hasher.finalize())
+}
@@ -0,0 +1,336 @@
+use async_trait::async_trait;
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use parking_lot::RwLock;
+use sb_errors::prelude::codes;
+use sb_types::prelude::{Id, Subject, SubjectKind, TenantId};
+use serde_json::{Map, Value};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::{Authenticator, AuthnInput};
+use crate::errors::AuthError;
+
+/// Where verification keys come from. `Url` and `File` are re-read when the
+/// cache expires or a token names an unknown `kid`, which is how rotated keys
+/// are picked up.
+#[derive(Clone, Debug)]
+pub enum JwksSource {
+    Url(String),
+    File(PathBuf),
+    Static(JwkSet),
+}
+
+/// How token claims become a [`Subject`]. Claims that are not consumed here
+/// (other than `exp`/`nbf`/`iat`) are copied into `Subject.claims`.
+#[derive(Clone, Debug)]
+pub struct ClaimMapping {
+    pub subject_claim: String,
+    pub tenant_claim: String,
+    /// Claim holding `user`/`service`/`agent`; `default_kind` when absent.
+    pub kind_claim: Option<String>,
+    pub default_kind: SubjectKind,
+    /// Tenant for tokens without a tenant claim; such tokens are rejected
+    /// when unset.
+    pub default_tenant: Option<String>,
+}
+
+impl Default for ClaimMapping {
+    fn default() -> Self {
+        Self {
+            subject_claim: "sub".to_string(),
+            tenant_claim: "tenant".to_string(),
+            kind_claim: Some("subject_kind".to_string()),
+            default_kind: SubjectKind::User,
+            default_tenant: None,
+        }
+    }
+}
+
+impl ClaimMapping {
+    /// Builds the subject; the error is a detail for `unauthenticated`.
+    pub(crate) fn subject(&self, mut claims: Map<String, Value>) -> Result<Subject, String> {
+        let subject_id = take_string(&mut claims, &self.subject_claim)?
+            .ok_or_else(|| missing_claim(&self.subject_claim))?;
+        let tenant = take_string(&mut claims, &self.tenant_claim)?
+            .or_else(|| self.default_tenant.clone())
+            .ok_or_else(|| missing_claim(&self.tenant_claim))?;
+        let kind = match &self.kind_claim {
+            Some(claim) => match take_string(&mut claims, claim)? {
+                Some(raw) => parse_kind(&raw)?,
+                None => self.default_kind.clone(),
+            },
+            None => self.default_kind.clone(),
+        };
+        for timing in ["exp", "nbf", "iat"] {
+            claims.remove(timing);
+        }
+        Ok(Subject {
+            kind,
+            subject_id: Id(subject_id),
+            tenant: TenantId(tenant),
+            claims,
+        })
+    }
+}
+
+fn take_string(claims: &mut Map<String, Value>, name: &str) -> Result<Option<String>, String> {
+    match claims.remove(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(value)) => Ok(Some(value)),
+        Some(_) => Err(format!("claim `{name}` must be a string")),
+    }
+}
+
+fn missing_claim(name: &str) -> String {
+    format!("token has no `{name}` claim")
+}
+
+fn parse_kind(raw: &str) -> Result<SubjectKind, String> {
+    match raw.to_ascii_lowercase().as_str() {
+        "user" => Ok(SubjectKind::User),
+        "service" => Ok(SubjectKind::Service),
+        "agent" => Ok(SubjectKind::Agent),
+        _ => Err(format!("unknown subject kind `{raw}`")),
+    }
+}
+
+struct CachedKeys {
+    set: Arc<JwkSet>,
+    fetched_at: Instant,
+}
+
+/// Verifies signed JWTs (RS256, ES256, EdDSA, HS256) from `Bearer` and
+/// `ServiceToken` inputs against a JWKS, then maps their claims.
+///
+/// `exp` is required; `exp`/`nbf` are checked with `leeway` of clock skew,
+/// and `iss`/`aud` when issuers or audiences are configured. A key is only
+/// used with its own algorithm family, so an RSA public key can never be
+/// abused as an HS256 secret.
+pub struct JwtAuthenticator {
+    source: JwksSource,
+    issuers: Vec<String>,
+    audiences: Vec<String>,
+    algorithms: Vec<Algorithm>,
+    leeway: Duration,
+    mapping: ClaimMapping,
+    ttl: Duration,
+    min_refresh: Duration,
+    cache: RwLock<Option<CachedKeys>>,
+    http: reqwest::Client,
+}
+
+impl JwtAuthenticator {
+    pub fn new(source: JwksSource) -> Self {
+        Self {
+            source,
+            issuers: Vec::new(),
+            audiences: Vec::new(),
+            algorithms: vec![
+                Algorithm::RS256,
+                Algorithm::ES256,
+                Algorithm::EdDSA,
+                Algorithm::HS256,
+            ],
+            leeway: Duration::from_secs(60),
+            mapping: ClaimMapping::default(),
+            ttl: Duration::from_secs(300),
+            min_refresh: Duration::from_secs(30),
+            cache: RwLock::new(None),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    pub fn with_issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.issuers.push(issuer.into());
+        self
+    }
+
+    pub fn with_audience(mut self, audience: impl Into<String>) -> Self {
+        self.audiences.push(audience.into());
+        self
+    }
+
+    /// Restricts accepted `alg` headers.
+    pub fn with_algorithms(mut self, algorithms: Vec<Algorithm>) -> Self {
+        self.algorithms = algorithms;
+        self
+    }
+
+    /// Clock skew tolerated on `exp` and `nbf`.
+    pub fn with_leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    pub fn with_claim_mapping(mut self, mapping: ClaimMapping) -> Self {
+        self.mapping = mapping;
+        self
+    }
+
+    /// How long a fetched JWKS is used before it is fetched again.
+    pub fn with_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Lower bound between refetches triggered by unknown `kid`s, so forged
+    /// tokens cannot hammer the JWKS endpoint.
+    pub fn with_min_refresh(mut self, interval: Duration) -> Self {
+        self.min_refresh = interval;
+        self
+    }
+
+    async fn keys(&self, force: bool) -> Result<Arc<JwkSet>, AuthError> {
+        let stale = {
+            let cache = self.cache.read();
+            match cache.as_ref() {
+                Some(cached) if !force && cached.fetched_at.elapsed() < self.ttl => {
+                    return Ok(cached.set.clone())
+                }
+                Some(cached) if force && cached.fetched_at.elapsed() < self.min_refresh => {
+                    return Ok(cached.set.clone())
+                }
+                Some(cached) => Some(cached.set.clone()),
+                None => None,
+            }
+        };
+        match (self.fetch().await, stale) {
+            (Ok(set), _) => {
+                let set = Arc::new(set);
+                *self.cache.write() = Some(CachedKeys {
+                    set: set.clone(),
+                    fetched_at: Instant::now(),
+                });
+                Ok(set)
+            }
+            // Keep verifying with the keys we have while the source is down.
+            (Err(_), Some(stale)) => Ok(stale),
+            (Err(err), None) => Err(err),
+        }
+    }
+
+    async fn fetch(&self) -> Result<JwkSet, AuthError> {
+        match &self.source {
+            JwksSource::Static(set) => Ok(set.clone()),
+            JwksSource::File(path) => {
+                let raw = std::fs::read_to_string(path)
+                    .map_err(|err| jwks_unavailable(format!("read {}: {err}", path.display())))?;
+                serde_json::from_str(&raw)
+                    .map_err(|err| jwks_unavailable(format!("parse {}: {err}", path.display())))
+            }
+            JwksSource::Url(url) => {
+                let response = self
+                    .http
+                    .get(url)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(|err| jwks_unavailable(format!("fetch {url}: {err}")))?;
+                response
+                    .json()
+                    .await
+                    .map_err(|err| jwks_unavailable(format!("parse {url}: {err}")))
+            }
+        }
+    }
+
+    fn validation(&self, algorithm: Algorithm) -> Validation {
+        let mut validation = Validation::new(algorithm);
+        validation.leeway = self.leeway.as_secs();
+        validation.validate_nbf = true;
+        // jsonwebtoken only checks `iss`/`aud` when the token carries them.
+        let mut required = vec!["exp"];
+        if !self.issuers.is_empty() {
+            required.push("iss");
+        }
+        if !self.audiences.is_empty() {
+            required.push("aud");
+        }
+        validation.set_required_spec_claims(&required);
+        if !self.issuers.is_empty() {
+            validation.set_issuer(&self.issuers);
+        }
+        if self.audiences.is_empty() {
+            validation.validate_aud = false;
+        } else {
+            validation.set_audience(&self.audiences);
+        }
+        validation
+    }
+}
+
+fn jwks_unavailable(detail: String) -> AuthError {
+    AuthError(
+        AuthError::builder(codes::PROVIDER_UNAVAILABLE)
+            .user_msg("Authentication is temporarily unavailable.")
+            .dev_msg(format!("jwks: {detail}"))
+            .build(),
+    )
+}
+
+/// The key named by `kid`, or without one the first key usable with
+/// `algorithm`.
+fn select_key<'a>(set: &'a JwkSet, kid: Option<&str>, algorithm: Algorithm) -> Option<&'a Jwk> {
+    let fits = |jwk: &&Jwk| {
+        jwk_family_matches(jwk, algorithm)
+            && jwk.common.key_algorithm.is_none_or(|declared| {
+                declared.to_string().parse::<Algorithm>().ok() == Some(algorithm)
+            })
+    };
+    match kid {
+        Some(kid) => set.find(kid).filter(fits),
+        None => set.keys.iter().find(fits),
+    }
+}
+
+fn jwk_family_matches(jwk: &Jwk, algorithm: Algorithm) -> bool {
+    use jsonwebtoken::jwk::AlgorithmParameters as Params;
+    matches!(
+        (&jwk.algorithm, algorithm),
+        (Params::RSA(_), Algorithm::RS256)
+            | (Params::EllipticCurve(_), Algorithm::ES256)
+            | (Params::OctetKeyPair(_), Algorithm::EdDSA)
+            | (Params::OctetKey(_), Algorithm::HS256)
+    )
+}
+
+#[async_trait]
+impl Authenticator for JwtAuthenticator {
+    async fn authenticate(&self, input: AuthnInput) -> Result<Subject, AuthError> {
+        let token = match input {
+            AuthnInput::Bearer(token) | AuthnInput::ServiceToken(token) => token,
+            AuthnInput::ApiKey(_) => {
+                return Err(AuthError::unauthenticated("api keys are not JWTs"))
+            }
+        };
+        let header = decode_header(&token)
+            .map_err(|err| AuthError::unauthenticated(format!("malformed token: {err}")))?;
+        if !self.algorithms.contains(&header.alg) {
+            return Err(AuthError::unauthenticated(format!(
+                "algorithm {:?} not accepted",
+                header.alg
+            )));
+        }
+        let kid = header.kid.as_deref();
+
+        let mut keys = self.keys(false).await?;
+        if select_key(&keys, kid, header.alg).is_none() {
+            keys = self.keys(true).await?;
+        }
+        let jwk = select_key(&keys, kid, header.alg).ok_or_else(|| {
+            AuthError::unauthenticated(format!(
+                "no verification key for kid {}",
+                kid.unwrap_or("<none>")
+            ))
+        })?;
+        let key = DecodingKey::from_jwk(jwk)
+            .map_err(|err| AuthError::unauthenticated(format!("unusable jwk: {err}")))?;
+        let data = decode::<Map<String, Value>>(&token, &key, &self.validation(header.alg))
+            .map_err(|err| AuthError::unauthenticated(format!("token rejected: {err}")))?;
+        self.mapping
+            .subject(data.claims)
+            .map_err(AuthError::unauthenticated)
+    }
+}
@@ -6,6 +6,11 @@ use sb_types::prelude::{Id, Subject, SubjectKind, TenantId};
 
 use crate::errors::AuthError;
 
+#[cfg(feature = "authn-apikey")]
+pub mod apikey;
+#[cfg(feature = "authn-jwt")]
+pub mod jwt;
+
 #[derive(Clone, Debug)]
 pub enum AuthnInput {
     Bearer(String),
@@ -1,47 +1,323 @@
 use async_trait::async_trait;
 use parking_lot::Mutex;
-use std::collections::HashMap;
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use crate::model::{Decision, DecisionKey};
 
+#[cfg(feature = "cache-redis")]
+pub mod redis;
+
 #[async_trait]
 pub trait DecisionCache: Send + Sync {
     async fn get(&self, key: &DecisionKey) -> Option<Decision>;
     async fn put(&self, key: DecisionKey, decision: &Decision);
     async fn invalidate(&self, key: &DecisionKey);
+
+    /// Drops every decision cached for `tenant`. Caches that cannot
+    /// enumerate their keys ignore it.
+    async fn invalidate_tenant(&self, _tenant: &str) {}
+
+    /// Drops every decision cached for one subject of `tenant`.
+    async fn invalidate_subject(&self, _tenant: &str, _subject_id: &str) {}
+
+    fn stats(&self) -> CacheStats {
+        CacheStats::default()
+    }
+}
+
+/// Counters since the cache was created; `size` is the current entry count
+/// where the cache knows it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub inserts: u64,
+    pub evictions: u64,
+    pub expirations: u64,
+    pub invalidations: u64,
+    pub size: usize,
+}
+
+impl CacheStats {
+    pub fn hit_ratio(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Counters {
+    pub(crate) hits: AtomicU64,
+    pub(crate) misses: AtomicU64,
+    pub(crate) inserts: AtomicU64,
+    pub(crate) evictions: AtomicU64,
+    pub(crate) expirations: AtomicU64,
+    pub(crate) invalidations: AtomicU64,
+}
+
+impl Counters {
+    pub(crate) fn bump(counter: &AtomicU64, by: u64) {
+        counter.fetch_add(by, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self, size: usize) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            inserts: self.inserts.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            expirations: self.expirations.load(Ordering::Relaxed),
+            invalidations: self.invalidations.load(Ordering::Relaxed),
+            size,
+        }
+    }
+}
+
+/// How long `decision` may be cached: allows for their `cache_ttl_ms`,
+/// denies for at most `negative_ttl` (and for `negative_ttl` when they carry
+/// no TTL of their own). `None` means do not cache.
+pub fn cache_ttl(decision: &Decision, negative_ttl: Duration) -> Option<Duration> {
+    let own = Duration::from_millis(u64::from(decision.cache_ttl_ms));
+    let ttl = match (decision.allow, decision.cache_ttl_ms) {
+        (true, _) => own,
+        (false, 0) => negative_ttl,
+        (false, _) => own.min(negative_ttl),
+    };
+    (!ttl.is_zero()).then_some(ttl)
+}
+
+struct Slot<V> {
+    value: V,
+    expires_at: Instant,
+    tick: u64,
+}
+
+pub(crate) enum Lookup<V> {
+    Hit(V),
+    Expired,
+    Miss,
+}
+
+/// Entries with a TTL, evicted least recently used first once `capacity`
+/// is reached.
+pub(crate) struct Lru<K, V> {
+    entries: HashMap<K, Slot<V>>,
+    order: BTreeMap<u64, K>,
+    tick: u64,
+}
+
+impl<K, V> Default for Lru<K, V> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Lru<K, V> {
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Live entries count as used; expired ones are dropped.
+    pub(crate) fn get<Q>(&mut self, key: &Q) -> Lookup<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let found = self
+            .entries
+            .get(key)
+            .map(|slot| (slot.expires_at > Instant::now(), slot.value.clone()));
+        match found {
+            Some((true, value)) => {
+                self.touch(key);
+                Lookup::Hit(value)
+            }
+            Some((false, _)) => {
+                self.remove(key);
+                Lookup::Expired
+            }
+            None => Lookup::Miss,
+        }
+    }
+
+    /// Inserts or replaces `key`, returning how many entries were evicted
+    /// to stay within `capacity`.
+    pub(crate) fn insert(&mut self, key: K, value: V, ttl: Duration, capacity: usize) -> u64 {
+        self.remove(&key);
+        let mut evicted = 0;
+        while self.entries.len() >= capacity.max(1) {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+            evicted += 1;
+        }
+        self.tick += 1;
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(
+            key,
+            Slot {
+                value,
+                expires_at: Instant::now() + ttl,
+                tick: self.tick,
+            },
+        );
+        evicted
+    }
+
+    pub(crate) fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.entries.remove(key) {
+            Some(slot) => {
+                self.order.remove(&slot.tick);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn remove_where(&mut self, matches: impl Fn(&K) -> bool) -> u64 {
+        let doomed: Vec<K> = self
+            .entries
+            .keys()
+            .filter(|key| matches(key))
+            .cloned()
+            .collect();
+        for key in &doomed {
+            self.remove(key);
+        }
+        doomed.len() as u64
+    }
+
+    fn touch<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.tick += 1;
+        let Some(slot) = self.entries.get_mut(key) else {
+            return;
+        };
+        let stale = std::mem::replace(&mut slot.tick, self.tick);
+        if let Some(owned) = self.order.remove(&stale) {
+            self.order.insert(self.tick, owned);
+        }
+    }
 }
 
-#[derive(Clone, Default)]
+/// In-process decision cache bounded by entry count (least recently used
+/// entries go first) and by TTL.
+///
+/// Keys carry the authorizer's policy version, so decisions made under an
+/// older policy are simply never looked up again and age out.
+#[derive(Clone)]
 pub struct MemoryDecisionCache {
-    inner: Arc<Mutex<HashMap<DecisionKey, (Decision, Instant, u32)>>>,
+    inner: Arc<Mutex<Lru<DecisionKey, Decision>>>,
+    capacity: usize,
+    negative_ttl: Duration,
+    counters: Arc<Counters>,
+}
+
+impl Default for MemoryDecisionCache {
+    fn default() -> Self {
+        Self::new(10_000)
+    }
+}
+
+impl MemoryDecisionCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::default(),
+            capacity: capacity.max(1),
+            negative_ttl: Duration::from_secs(1),
+            counters: Arc::default(),
+        }
+    }
+
+    /// Upper bound for caching denies; zero disables negative caching.
+    pub fn with_negative_ttl(mut self, ttl: Duration) -> Self {
+        self.negative_ttl = ttl;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 #[async_trait]
 impl DecisionCache for MemoryDecisionCache {
     async fn get(&self, key: &DecisionKey) -> Option<Decision> {
-        let mut guard = self.inner.lock();
-        if let Some((decision, instant, ttl)) = guard.get(key) {
-            if *ttl == 0 {
-                return Some(decision.clone());
+        let found = self.inner.lock().get(key);
+        match found {
+            Lookup::Hit(decision) => {
+                Counters::bump(&self.counters.hits, 1);
+                Some(decision)
             }
-            if instant.elapsed() <= Duration::from_millis(*ttl as u64) {
-                return Some(decision.clone());
+            Lookup::Expired => {
+                Counters::bump(&self.counters.expirations, 1);
+                Counters::bump(&self.counters.misses, 1);
+                None
+            }
+            Lookup::Miss => {
+                Counters::bump(&self.counters.misses, 1);
+                None
             }
         }
-        guard.remove(key);
-        None
     }
 
     async fn put(&self, key: DecisionKey, decision: &Decision) {
-        let ttl = decision.cache_ttl_ms;
-        self.inner
+        let Some(ttl) = cache_ttl(decision, self.negative_ttl) else {
+            return;
+        };
+        let evicted = self
+            .inner
             .lock()
-            .insert(key, (decision.clone(), Instant::now(), ttl));
+            .insert(key, decision.clone(), ttl, self.capacity);
+        Counters::bump(&self.counters.evictions, evicted);
+        Counters::bump(&self.counters.inserts, 1);
     }
 
     async fn invalidate(&self, key: &DecisionKey) {
-        self.inner.lock().remove(key);
+        if self.inner.lock().remove(key) {
+            Counters::bump(&self.counters.invalidations, 1);
+        }
+    }
+
+    async fn invalidate_tenant(&self, tenant: &str) {
+        let removed = self.inner.lock().remove_where(|key| key.tenant == tenant);
+        Counters::bump(&self.counters.invalidations, removed);
+    }
+
+    async fn invalidate_subject(&self, tenant: &str, subject_id: &str) {
+        let removed = self
+            .inner
+            .lock()
+            .remove_where(|key| key.tenant == tenant && key.subject_id == subject_id);
+        Counters::bump(&self.counters.invalidations, removed);
+    }
+
+    fn stats(&self) -> CacheStats {
+        self.counters.snapshot(self.len())
     }
 }
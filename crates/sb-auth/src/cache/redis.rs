@@ -0,0 +1,283 @@
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use parking_lot::Mutex;
+use redis::aio::MultiplexedConnection;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use super::{cache_ttl, CacheStats, Counters, DecisionCache, Lookup, Lru};
+use crate::errors::AuthError;
+use crate::model::{Decision, DecisionKey};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RedisDecisionCacheConfig {
+    /// Redis connection string (e.g. redis://127.0.0.1:6379/)
+    pub url: String,
+    /// Prefix for every cached decision key.
+    pub key_prefix: String,
+    /// Pub/sub channel carrying invalidated keys between nodes.
+    pub channel: String,
+    /// Upper bound on how long a node serves a decision from process memory
+    /// without asking Redis; also bounds staleness if pub/sub is interrupted.
+    pub local_ttl_ms: u64,
+    /// Most decisions a node keeps in process memory; the least recently
+    /// used go first.
+    pub local_capacity: usize,
+    /// Upper bound for caching denies, see [`cache_ttl`]; zero disables
+    /// negative caching.
+    pub negative_ttl_ms: u64,
+    pub timeout_ms: u64,
+}
+
+impl Default for RedisDecisionCacheConfig {
+    fn default() -> Self {
+        Self {
+            url: "redis://127.0.0.1:6379/".into(),
+            key_prefix: "sb:auth:decision".into(),
+            channel: "sb.auth.decision.invalidate".into(),
+            local_ttl_ms: 5_000,
+            local_capacity: 10_000,
+            negative_ttl_ms: 1_000,
+            timeout_ms: 500,
+        }
+    }
+}
+
+type LocalEntries = Arc<Mutex<Lru<String, Decision>>>;
+
+/// Decisions shared through Redis with a short-lived copy in each process.
+///
+/// Entries expire in Redis after their [`cache_ttl`]. `invalidate` deletes
+/// the Redis entry and publishes the key, and every node subscribed to the
+/// channel drops its local copy; bulk invalidation publishes a key prefix
+/// ending in `*` instead. Redis failures degrade to cache misses.
+pub struct RedisDecisionCache {
+    conn: MultiplexedConnection,
+    prefix: String,
+    channel: String,
+    local_ttl: Duration,
+    local_capacity: usize,
+    negative_ttl: Duration,
+    timeout: Duration,
+    local: LocalEntries,
+    counters: Arc<Counters>,
+    subscriber: JoinHandle<()>,
+}
+
+impl RedisDecisionCache {
+    /// Connects and starts listening for invalidations; needs a Tokio runtime.
+    pub async fn connect(config: RedisDecisionCacheConfig) -> Result<Self, AuthError> {
+        let timeout = Duration::from_millis(config.timeout_ms);
+        let client = redis::Client::open(config.url)
+            .map_err(|err| AuthError::redis_unavailable(format!("client create failed: {err}")))?;
+        let connect = async {
+            let conn = client.get_multiplexed_tokio_connection().await?;
+            let mut pubsub = client.get_async_connection().await?.into_pubsub();
+            pubsub.subscribe(&config.channel).await?;
+            Ok::<_, redis::RedisError>((conn, pubsub))
+        };
+        let (conn, pubsub) = tokio::time::timeout(timeout, connect)
+            .await
+            .map_err(|_| AuthError::redis_unavailable("connect timed out".to_string()))?
+            .map_err(|err| AuthError::redis_unavailable(format!("connect failed: {err}")))?;
+
+        let local = LocalEntries::default();
+        let evict = local.clone();
+        let subscriber = tokio::spawn(async move {
+            let mut messages = pubsub.into_on_message();
+            while let Some(message) = messages.next().await {
+                let Ok(key) = message.get_payload::<String>() else {
+                    continue;
+                };
+                match key.strip_suffix('*') {
+                    Some(prefix) => {
+                        evict.lock().remove_where(|key| key.starts_with(prefix));
+                    }
+                    None => {
+                        evict.lock().remove(&key);
+                    }
+                }
+            }
+        });
+        Ok(Self {
+            conn,
+            prefix: config.key_prefix,
+            channel: config.channel,
+            local_ttl: Duration::from_millis(config.local_ttl_ms),
+            local_capacity: config.local_capacity.max(1),
+            negative_ttl: Duration::from_millis(config.negative_ttl_ms),
+            timeout,
+            local,
+            counters: Arc::default(),
+            subscriber,
+        })
+    }
+
+    /// Tenant and subject lead the key so bulk invalidation is a prefix.
+    fn redis_key(&self, key: &DecisionKey) -> String {
+        format!(
+            "{}{}:{:?}:{:x}",
+            self.subject_prefix(&key.tenant, &key.subject_id),
+            key.resource.0,
+            key.action,
+            key.attrs_hash
+        )
+    }
+
+    fn tenant_prefix(&self, tenant: &str) -> String {
+        format!("{}:{}:", self.prefix, escape(tenant))
+    }
+
+    fn subject_prefix(&self, tenant: &str, subject_id: &str) -> String {
+        format!("{}{}:", self.tenant_prefix(tenant), escape(subject_id))
+    }
+
+    fn remember(&self, key: String, decision: &Decision, ttl: Duration) {
+        let evicted = self.local.lock().insert(
+            key,
+            decision.clone(),
+            ttl.min(self.local_ttl),
+            self.local_capacity,
+        );
+        Counters::bump(&self.counters.evictions, evicted);
+    }
+
+    /// Deletes every key under `prefix` and tells the other nodes.
+    async fn invalidate_prefix(&self, prefix: String) {
+        self.local
+            .lock()
+            .remove_where(|key| key.starts_with(&prefix));
+        let pattern = format!("{}*", glob_escape(&prefix));
+        let mut conn = self.conn.clone();
+        let mut cursor = 0u64;
+        loop {
+            let mut scan = redis::cmd("SCAN");
+            scan.arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(500);
+            let page = scan.query_async::<_, (u64, Vec<String>)>(&mut conn);
+            let Some((next, keys)) = self.bounded(page).await else {
+                break;
+            };
+            if !keys.is_empty() {
+                Counters::bump(&self.counters.invalidations, keys.len() as u64);
+                self.bounded(conn.del::<_, ()>(keys)).await;
+            }
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        self.bounded(conn.publish::<_, _, ()>(&self.channel, format!("{prefix}*")))
+            .await;
+    }
+
+    async fn bounded<T>(
+        &self,
+        op: impl std::future::Future<Output = redis::RedisResult<T>>,
+    ) -> Option<T> {
+        tokio::time::timeout(self.timeout, op).await.ok()?.ok()
+    }
+}
+
+impl Drop for RedisDecisionCache {
+    fn drop(&mut self) {
+        self.subscriber.abort();
+    }
+}
+
+#[async_trait]
+impl DecisionCache for RedisDecisionCache {
+    async fn get(&self, key: &DecisionKey) -> Option<Decision> {
+        let redis_key = self.redis_key(key);
+        if let Lookup::Hit(decision) = self.local.lock().get(redis_key.as_str()) {
+            Counters::bump(&self.counters.hits, 1);
+            return Some(decision);
+        }
+        let mut conn = self.conn.clone();
+        let mut pipe = redis::pipe();
+        pipe.get(&redis_key).pttl(&redis_key);
+        let fetch = pipe.query_async::<_, (Option<String>, i64)>(&mut conn);
+        let found = self
+            .bounded(fetch)
+            .await
+            .and_then(|(raw, pttl)| Some((serde_json::from_str::<Decision>(&raw?).ok()?, pttl)));
+        let Some((decision, pttl)) = found else {
+            Counters::bump(&self.counters.misses, 1);
+            return None;
+        };
+        Counters::bump(&self.counters.hits, 1);
+        let remaining = Duration::from_millis(pttl.max(0) as u64);
+        self.remember(redis_key, &decision, remaining);
+        Some(decision)
+    }
+
+    async fn put(&self, key: DecisionKey, decision: &Decision) {
+        let Ok(body) = serde_json::to_string(decision) else {
+            return;
+        };
+        let Some(ttl) = cache_ttl(decision, self.negative_ttl) else {
+            return;
+        };
+        let redis_key = self.redis_key(&key);
+        let mut conn = self.conn.clone();
+        let ttl_ms = ttl.as_millis().max(1) as u64;
+        let stored = self
+            .bounded(conn.pset_ex::<_, _, ()>(&redis_key, body, ttl_ms))
+            .await;
+        if stored.is_some() {
+            Counters::bump(&self.counters.inserts, 1);
+            self.remember(redis_key, decision, ttl);
+        }
+    }
+
+    async fn invalidate(&self, key: &DecisionKey) {
+        let redis_key = self.redis_key(key);
+        self.local.lock().remove(&redis_key);
+        let mut conn = self.conn.clone();
+        if let Some(removed) = self.bounded(conn.del::<_, u64>(&redis_key)).await {
+            Counters::bump(&self.counters.invalidations, removed);
+        }
+        self.bounded(conn.publish::<_, _, ()>(&self.channel, &redis_key))
+            .await;
+    }
+
+    async fn invalidate_tenant(&self, tenant: &str) {
+        self.invalidate_prefix(self.tenant_prefix(tenant)).await;
+    }
+
+    async fn invalidate_subject(&self, tenant: &str, subject_id: &str) {
+        self.invalidate_prefix(self.subject_prefix(tenant, subject_id))
+            .await;
+    }
+
+    /// `size` counts this node's local copies only.
+    fn stats(&self) -> CacheStats {
+        self.counters.snapshot(self.local.lock().len())
+    }
+}
+
+/// Keeps ids containing `:` or a trailing `*` from reaching into another
+/// tenant's or subject's key space.
+fn escape(id: &str) -> String {
+    id.replace('%', "%25")
+        .replace(':', "%3A")
+        .replace('*', "%2A")
+}
+
+fn glob_escape(raw: &str) -> String {
+    let mut escaped = String::with_capacity(raw.len());
+    for ch in raw.chars() {
+        if matches!(ch, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
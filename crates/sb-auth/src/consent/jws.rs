@@ -0,0 +1,393 @@
+use async_trait::async_trait;
+use jsonwebtoken::{
+    decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use parking_lot::Mutex;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sb_storage::prelude::{make_record_id, Entity, Repository};
+use sb_types::prelude::{Consent, Scope, TenantId, Timestamp};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::{consent_covers, ConsentVerifier};
+use crate::errors::AuthError;
+use crate::model::AuthzRequest;
+use crate::store::{now_ms, select_all, storage_error};
+
+/// JWS `typ` of consent tokens, so access tokens signed with the same key
+/// are never accepted as consent.
+pub const CONSENT_TOKEN_TYPE: &str = "consent+jwt";
+
+/// Payload of a consent token. Times are in seconds, as in any JWT.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ConsentClaims {
+    /// Consent id, the ledger key.
+    pub jti: String,
+    pub iss: String,
+    pub sub: String,
+    pub tenant: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub purpose: Option<String>,
+    pub scopes: Vec<Scope>,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+impl ConsentClaims {
+    pub fn consent(&self) -> Consent {
+        Consent {
+            scopes: self.scopes.clone(),
+            expires_at: Some(Timestamp(self.exp * 1000)),
+            purpose: self.purpose.clone(),
+        }
+    }
+}
+
+/// A granted consent as kept in the ledger. The token itself is not stored.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConsentRecord {
+    pub id: String,
+    pub tenant: TenantId,
+    pub consent_id: String,
+    pub subject_id: String,
+    #[serde(default)]
+    pub purpose: Option<String>,
+    pub scopes: Vec<Scope>,
+    pub issued_at_ms: i64,
+    pub expires_at_ms: i64,
+    #[serde(default)]
+    pub revoked_at_ms: Option<i64>,
+    #[serde(default)]
+    pub revoked_reason: Option<String>,
+}
+
+impl Entity for ConsentRecord {
+    const TABLE: &'static str = "auth_consent";
+    type Key = String;
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl ConsentRecord {
+    pub fn record_id(tenant: &TenantId, consent_id: &str) -> String {
+        make_record_id(Self::TABLE, tenant, consent_id)
+    }
+
+    pub fn is_active_at(&self, now_ms: i64) -> bool {
+        self.revoked_at_ms.is_none() && now_ms < self.expires_at_ms
+    }
+}
+
+/// What a subject consents to.
+#[derive(Clone, Debug)]
+pub struct ConsentGrant {
+    pub tenant: TenantId,
+    pub subject_id: String,
+    pub scopes: Vec<Scope>,
+    pub purpose: Option<String>,
+    pub ttl: Duration,
+}
+
+/// A freshly signed consent token and its ledger entry.
+#[derive(Clone, Debug)]
+pub struct IssuedConsent {
+    pub token: String,
+    pub record: ConsentRecord,
+}
+
+struct CachedConsent {
+    record: Option<ConsentRecord>,
+    loaded_at: Instant,
+}
+
+/// Consents granted per tenant and subject, stored in sb-storage.
+///
+/// Lookups are cached for `cache_ttl`; revocations made through this ledger
+/// apply immediately, elsewhere within that TTL.
+pub struct ConsentLedger<R: Repository<ConsentRecord>> {
+    repo: Arc<R>,
+    cache_ttl: Duration,
+    cache: Mutex<HashMap<String, CachedConsent>>,
+}
+
+impl<R: Repository<ConsentRecord>> ConsentLedger<R> {
+    pub fn new(repo: Arc<R>) -> Self {
+        Self {
+            repo,
+            cache_ttl: Duration::from_secs(5),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = ttl;
+        self
+    }
+
+    pub async fn record(&self, record: &ConsentRecord) -> Result<ConsentRecord, AuthError> {
+        self.cache.lock().remove(&record.id);
+        self.repo
+            .create(&record.tenant, record)
+            .await
+            .map_err(storage_error)
+    }
+
+    pub async fn get(
+        &self,
+        tenant: &TenantId,
+        consent_id: &str,
+    ) -> Result<Option<ConsentRecord>, AuthError> {
+        let record_id = ConsentRecord::record_id(tenant, consent_id);
+        if let Some(cached) = self.cache.lock().get(&record_id) {
+            if cached.loaded_at.elapsed() < self.cache_ttl {
+                return Ok(cached.record.clone());
+            }
+        }
+        let record = self
+            .repo
+            .get(tenant, &record_id)
+            .await
+            .map_err(storage_error)?;
+        self.cache.lock().insert(
+            record_id,
+            CachedConsent {
+                record: record.clone(),
+                loaded_at: Instant::now(),
+            },
+        );
+        Ok(record)
+    }
+
+    pub async fn revoke(
+        &self,
+        tenant: &TenantId,
+        consent_id: &str,
+        reason: Option<String>,
+    ) -> Result<ConsentRecord, AuthError> {
+        let record_id = ConsentRecord::record_id(tenant, consent_id);
+        if self
+            .repo
+            .get(tenant, &record_id)
+            .await
+            .map_err(storage_error)?
+            .is_none()
+        {
+            return Err(AuthError::forbidden(format!(
+                "consent {consent_id} not found"
+            )));
+        }
+        self.cache.lock().remove(&record_id);
+        self.repo
+            .upsert(
+                tenant,
+                &record_id,
+                json!({ "revoked_at_ms": now_ms(), "revoked_reason": reason }),
+                None,
+            )
+            .await
+            .map_err(storage_error)
+    }
+
+    /// Every consent the subject has granted, including revoked and expired
+    /// ones, oldest first.
+    pub async fn list(
+        &self,
+        tenant: &TenantId,
+        subject_id: &str,
+    ) -> Result<Vec<ConsentRecord>, AuthError> {
+        let mut records = select_all(
+            self.repo.as_ref(),
+            tenant,
+            json!({ "subject_id": subject_id }),
+        )
+        .await?;
+        records.sort_by_key(|record| record.issued_at_ms);
+        Ok(records)
+    }
+}
+
+/// Signs consent tokens with the consent service key and records every grant
+/// in the ledger.
+pub struct ConsentIssuer<R: Repository<ConsentRecord>> {
+    ledger: Arc<ConsentLedger<R>>,
+    issuer: String,
+    kid: String,
+    algorithm: Algorithm,
+    key: EncodingKey,
+}
+
+impl<R: Repository<ConsentRecord>> ConsentIssuer<R> {
+    pub fn new(
+        ledger: Arc<ConsentLedger<R>>,
+        issuer: impl Into<String>,
+        kid: impl Into<String>,
+        algorithm: Algorithm,
+        key: EncodingKey,
+    ) -> Self {
+        Self {
+            ledger,
+            issuer: issuer.into(),
+            kid: kid.into(),
+            algorithm,
+            key,
+        }
+    }
+
+    pub async fn issue(&self, grant: ConsentGrant) -> Result<IssuedConsent, AuthError> {
+        let now = now_ms();
+        let claims = ConsentClaims {
+            jti: consent_id(),
+            iss: self.issuer.clone(),
+            sub: grant.subject_id,
+            tenant: grant.tenant.0.clone(),
+            purpose: grant.purpose,
+            scopes: grant.scopes,
+            iat: now / 1000,
+            exp: (now + grant.ttl.as_millis() as i64) / 1000,
+        };
+        let mut header = Header::new(self.algorithm);
+        header.kid = Some(self.kid.clone());
+        header.typ = Some(CONSENT_TOKEN_TYPE.to_string());
+        let token = encode(&header, &claims, &self.key)
+            .map_err(|err| AuthError::forbidden(format!("consent signing failed: {err}")))?;
+
+        let record = self
+            .ledger
+            .record(&ConsentRecord {
+                id: ConsentRecord::record_id(&grant.tenant, &claims.jti),
+                tenant: grant.tenant,
+                consent_id: claims.jti,
+                subject_id: claims.sub,
+                purpose: claims.purpose,
+                scopes: claims.scopes,
+                issued_at_ms: now,
+                expires_at_ms: claims.exp * 1000,
+                revoked_at_ms: None,
+                revoked_reason: None,
+            })
+            .await?;
+        Ok(IssuedConsent { token, record })
+    }
+}
+
+/// Accepts consent only as tokens signed by a known consent service key.
+///
+/// A token must carry [`CONSENT_TOKEN_TYPE`], verify against the key named by
+/// its `kid`, come from the configured issuer, be unexpired, be bound to the
+/// request's subject and tenant, be present and unrevoked in the ledger, and
+/// have a scope covering the request. Unsigned consents are rejected.
+pub struct JwsConsentVerifier<R: Repository<ConsentRecord>> {
+    ledger: Arc<ConsentLedger<R>>,
+    issuer: String,
+    keys: HashMap<String, (Algorithm, DecodingKey)>,
+    leeway: Duration,
+}
+
+impl<R: Repository<ConsentRecord>> JwsConsentVerifier<R> {
+    pub fn new(ledger: Arc<ConsentLedger<R>>, issuer: impl Into<String>) -> Self {
+        Self {
+            ledger,
+            issuer: issuer.into(),
+            keys: HashMap::new(),
+            leeway: Duration::from_secs(30),
+        }
+    }
+
+    /// Adds a verification key; keep the previous key while rotating so
+    /// outstanding tokens stay valid.
+    pub fn with_key(
+        mut self,
+        kid: impl Into<String>,
+        algorithm: Algorithm,
+        key: DecodingKey,
+    ) -> Self {
+        self.keys.insert(kid.into(), (algorithm, key));
+        self
+    }
+
+    pub fn with_leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    /// Checks signature, issuer and expiry; the error is a detail for
+    /// `forbidden`.
+    fn decode(&self, token: &str) -> Result<ConsentClaims, String> {
+        let header = decode_header(token).map_err(|err| format!("malformed: {err}"))?;
+        if header.typ.as_deref() != Some(CONSENT_TOKEN_TYPE) {
+            return Err(format!("type must be `{CONSENT_TOKEN_TYPE}`"));
+        }
+        let kid = header.kid.ok_or("has no `kid`")?;
+        let (algorithm, key) = self
+            .keys
+            .get(&kid)
+            .ok_or_else(|| format!("signed with unknown key `{kid}`"))?;
+        if header.alg != *algorithm {
+            return Err(format!("key `{kid}` does not sign with {:?}", header.alg));
+        }
+        let mut validation = Validation::new(*algorithm);
+        validation.set_required_spec_claims(&["exp", "iss", "sub"]);
+        validation.set_issuer(&[&self.issuer]);
+        validation.validate_aud = false;
+        validation.leeway = self.leeway.as_secs();
+        decode::<ConsentClaims>(token, key, &validation)
+            .map(|data| data.claims)
+            .map_err(|err| format!("rejected: {err}"))
+    }
+}
+
+#[async_trait]
+impl<R: Repository<ConsentRecord>> ConsentVerifier for JwsConsentVerifier<R> {
+    async fn verify(&self, _consent: &Consent, _request: &AuthzRequest) -> Result<bool, AuthError> {
+        Ok(false)
+    }
+
+    async fn verify_token(
+        &self,
+        token: &str,
+        request: &AuthzRequest,
+    ) -> Result<Consent, AuthError> {
+        let claims = self
+            .decode(token)
+            .map_err(|detail| AuthError::forbidden(format!("consent token {detail}")))?;
+        if claims.tenant != request.subject.tenant.0 || claims.sub != request.subject.subject_id.0 {
+            return Err(AuthError::forbidden(
+                "consent token was granted to another subject",
+            ));
+        }
+        let tenant = TenantId(claims.tenant.clone());
+        let state = match self.ledger.get(&tenant, &claims.jti).await? {
+            Some(record) if record.subject_id != claims.sub => Some("not in the ledger"),
+            Some(record) if record.revoked_at_ms.is_some() => Some("revoked"),
+            Some(record) if !record.is_active_at(now_ms()) => Some("expired"),
+            Some(_) => None,
+            None => Some("not in the ledger"),
+        };
+        if let Some(state) = state {
+            return Err(AuthError::forbidden(format!(
+                "consent {} is {state}",
+                claims.jti
+            )));
+        }
+        let consent = claims.consent();
+        if !consent_covers(&consent, request) {
+            return Err(AuthError::forbidden(format!(
+                "consent {} does not cover {:?} on {}",
+                claims.jti, request.action, request.resource.0
+            )));
+        }
+        Ok(consent)
+    }
+}
+
+fn consent_id() -> String {
+    let mut buf = [0u8; 16];
+    OsRng.fill_bytes(&mut buf);
+    buf.iter().map(|byte| format!("{byte:02x}")).collect()
+}
@@ -1,12 +1,46 @@
 use async_trait::async_trait;
-use sb_types::prelude::Consent;
+use sb_types::prelude::{Consent, Scope};
 
 use crate::errors::AuthError;
-use crate::model::AuthzRequest;
+use crate::model::{Action, AuthzRequest};
+
+#[cfg(feature = "consent-jws")]
+pub mod jws;
 
 #[async_trait]
 pub trait ConsentVerifier: Send + Sync {
     async fn verify(&self, consent: &Consent, request: &AuthzRequest) -> Result<bool, AuthError>;
+
+    /// Checks a signed consent token for `request` and returns the consent it
+    /// carries. Verifiers that only understand plain consents reject tokens.
+    async fn verify_token(
+        &self,
+        _token: &str,
+        _request: &AuthzRequest,
+    ) -> Result<Consent, AuthError> {
+        Err(AuthError::forbidden(
+            "signed consent tokens are not supported",
+        ))
+    }
+}
+
+/// Whether `scope` covers `action` on `resource`. Resources match exactly or
+/// by a prefix ending in `*`; action `*` matches every action.
+pub fn scope_covers(scope: &Scope, resource: &str, action: &Action) -> bool {
+    let resource_ok = match scope.resource.strip_suffix('*') {
+        Some(prefix) => resource.starts_with(prefix),
+        None => scope.resource == resource,
+    };
+    resource_ok
+        && (scope.action == "*" || scope.action.eq_ignore_ascii_case(&format_action(action)))
+}
+
+/// Whether any scope of `consent` covers the request.
+pub fn consent_covers(consent: &Consent, request: &AuthzRequest) -> bool {
+    consent
+        .scopes
+        .iter()
+        .any(|scope| scope_covers(scope, &request.resource.0, &request.action))
 }
 
 #[derive(Default)]
@@ -24,25 +58,18 @@ impl ConsentVerifier for BasicConsentVerifier {
                 return Ok(false);
             }
         }
-        for scope in &consent.scopes {
-            if scope.resource == request.resource.0
-                && scope.action == format_action(&request.action)
-            {
-                return Ok(true);
-            }
-        }
-        Ok(false)
+        Ok(consent_covers(consent, request))
     }
 }
 
-fn format_action(action: &crate::model::Action) -> String {
+fn format_action(action: &Action) -> String {
     match action {
-        crate::model::Action::Read => "read",
-        crate::model::Action::Write => "write",
-        crate::model::Action::Invoke => "invoke",
-        crate::model::Action::List => "list",
-        crate::model::Action::Admin => "admin",
-        crate::model::Action::Configure => "configure",
+        Action::Read => "read",
+        Action::Write => "write",
+        Action::Invoke => "invoke",
+        Action::List => "list",
+        Action::Admin => "admin",
+        Action::Configure => "configure",
     }
     .to_string()
 }
@@ -47,6 +47,17 @@ impl AuthError {
         )
     }
 
+    /// The Redis server behind a quota store or decision cache failed.
+    #[cfg(any(feature = "quota-redis", feature = "cache-redis"))]
+    pub(crate) fn redis_unavailable(detail: impl Into<String>) -> Self {
+        Self(
+            ErrorBuilder::new(codes::PROVIDER_UNAVAILABLE)
+                .user_msg("Authorization backend is temporarily unavailable.")
+                .dev_msg(format!("redis: {}", detail.into()))
+                .build(),
+        )
+    }
+
     pub fn policy_deny(reason: impl Into<String>) -> Self {
         Self(
             ErrorBuilder::new(codes::POLICY_DENY_TOOL)
@@ -7,11 +7,11 @@ use crate::consent::ConsentVerifier;
 use crate::errors::AuthError;
 use crate::events::wrap_decision;
 use crate::model::{
-    hash_attrs, Action, AuthzRequest, Decision, DecisionKey, QuotaKey, ResourceUrn,
+    hash_decision_attrs, Action, AuthzRequest, Decision, DecisionKey, QuotaKey, ResourceUrn,
 };
 use crate::observe::decision_labels;
 use crate::pdp::Authorizer;
-use crate::quota::{QuotaOutcome, QuotaStore};
+use crate::quota::QuotaStore;
 
 use sb_types::prelude::Consent;
 use sb_types::prelude::Subject;
@@ -23,6 +23,9 @@ pub struct AuthContext {
     pub action: Action,
     pub attrs: serde_json::Value,
     pub consent: Option<Consent>,
+    /// Signed consent token; when set it takes the place of `consent` once
+    /// [`ConsentVerifier::verify_token`] accepts it.
+    pub consent_token: Option<String>,
     pub correlation_id: Option<String>,
     pub cost: i64,
 }
@@ -76,14 +79,13 @@ impl AuthFacade {
             subject_id: subject.subject_id.0.clone(),
             resource: ctx.resource.clone(),
             action: ctx.action.clone(),
-            attrs_hash: hash_attrs(&merged_attrs.0),
+            attrs_hash: hash_decision_attrs(
+                &merged_attrs.0,
+                self.authorizer.policy_version(&subject).as_deref(),
+            ),
         };
 
-        if let Some(decision) = self.cache.get(&decision_key).await {
-            return Ok(AuthResult { subject, decision });
-        }
-
-        let request = AuthzRequest {
+        let mut request = AuthzRequest {
             subject: subject.clone(),
             resource: ctx.resource.clone(),
             action: ctx.action.clone(),
@@ -92,15 +94,22 @@ impl AuthFacade {
             correlation_id: ctx.correlation_id.clone(),
         };
 
-        let decision = self.authorizer.decide(&request).await?;
-        if decision.allow {
-            if let Some(consent) = &request.consent {
-                if !self.consent.verify(consent, &request).await? {
-                    return Err(AuthError::policy_deny("consent invalid"));
-                }
+        // Consent is checked on every call, cached decision or not, so a
+        // revoked or expired consent stops working right away.
+        if let Some(token) = &ctx.consent_token {
+            request.consent = Some(self.consent.verify_token(token, &request).await?);
+        } else if let Some(consent) = &request.consent {
+            if !self.consent.verify(consent, &request).await? {
+                return Err(AuthError::policy_deny("consent invalid"));
             }
         }
 
+        if let Some(decision) = self.cache.get(&decision_key).await {
+            return Ok(AuthResult { subject, decision });
+        }
+
+        let decision = self.authorizer.decide(&request).await?;
+
         if decision.allow {
             let quota_key = QuotaKey {
                 tenant: subject.tenant.0.clone(),
@@ -108,16 +117,12 @@ impl AuthFacade {
                 resource: ctx.resource.clone(),
                 action: ctx.action.clone(),
             };
-            match self.quota.check_and_consume(&quota_key, ctx.cost).await? {
-                QuotaOutcome::Allowed => {}
-                QuotaOutcome::RateLimited => return Err(AuthError::rate_limited()),
-                QuotaOutcome::BudgetExceeded => return Err(AuthError::budget_exceeded()),
+            if let Some(err) = self.quota.check(&quota_key, ctx.cost).await?.error() {
+                return Err(err);
             }
         }
 
-        if decision.cache_ttl_ms > 0 {
-            self.cache.put(decision_key, &decision).await;
-        }
+        self.cache.put(decision_key, &decision).await;
 
         let _labels = decision_labels(
             &ctx.resource,
@@ -10,3 +10,5 @@ pub mod observe;
 pub mod pdp;
 pub mod prelude;
 pub mod quota;
+#[cfg(any(feature = "authn-apikey", feature = "consent-jws"))]
+mod store;
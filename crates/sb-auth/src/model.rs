@@ -140,6 +140,22 @@ pub fn hash_attrs(attrs: &Value) -> u64 {
     hasher.finish()
 }
 
+/// [`hash_attrs`] salted with the authorizer's policy version, so a new
+/// version means a new [`DecisionKey`]. Without a version it is the plain
+/// attribute hash.
+pub fn hash_decision_attrs(attrs: &Value, policy_version: Option<&str>) -> u64 {
+    use std::collections::hash_map::DefaultHasher;
+    let Some(version) = policy_version else {
+        return hash_attrs(attrs);
+    };
+    let mut hasher = DefaultHasher::new();
+    if let Ok(bytes) = serde_json::to_vec(attrs) {
+        hasher.write(&bytes);
+    }
+    version.hash(&mut hasher);
+    hasher.finish()
+}
+
 impl From<Value> for AttributeMap {
     fn from(value: Value) -> Self {
         AttributeMap(value)
@@ -2,12 +2,24 @@ use async_trait::async_trait;
 use serde_json::json;
 use std::collections::HashSet;
 
+use sb_types::prelude::Subject;
+
 use crate::errors::AuthError;
 use crate::model::{Action, AuthzRequest, Decision};
 
+#[cfg(feature = "pdp-policy")]
+pub mod policy;
+
 #[async_trait]
 pub trait Authorizer: Send + Sync {
     async fn decide(&self, request: &AuthzRequest) -> Result<Decision, AuthError>;
+
+    /// Identifies the policy `decide` would currently apply for `subject`, so
+    /// cached decisions stop matching once it changes. `None` for policies
+    /// that never change.
+    fn policy_version(&self, _subject: &Subject) -> Option<String> {
+        None
+    }
 }
 
 #[derive(Default)]
@@ -0,0 +1,556 @@
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use parking_lot::Mutex;
+use sb_config::errors::{self, ConfigError};
+use sb_config::model::{Checksum, KeyPath, NamespaceId, ReloadClass};
+use sb_config::schema::{FieldMeta, SchemaDoc, SchemaRegistry};
+use sb_config::switch::SnapshotSwitch;
+use sb_types::prelude::{Subject, SubjectKind};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use super::Authorizer;
+use crate::errors::AuthError;
+use crate::model::{Action, AuthzRequest, Decision, Obligation};
+
+/// Config namespace the policy is read from by default.
+pub const POLICY_NAMESPACE: &str = "authz";
+
+const COMPILED_CACHE: usize = 16;
+
+/// The policy as written in config:
+///
+/// ```yaml
+/// authz:
+///   roles:
+///     tool_user:
+///       rules:
+///         - { id: invoke-tools, resources: ["soul:tool:*"], actions: [Invoke] }
+///   bindings:
+///     - { role: tool_user, tenants: [acme], claims: { team: [core] } }
+///   rules:
+///     - id: no-prod-admin
+///       effect: deny
+///       resources: ["soul:*"]
+///       actions: [Admin]
+///       conditions: [{ path: attrs.env, op: eq, value: prod }]
+/// ```
+///
+/// Role rules apply to subjects holding the role through a binding;
+/// top-level rules apply to everyone. A matching `deny` always wins; without
+/// any matching rule the request is denied.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PolicyDocument {
+    pub roles: BTreeMap<String, Role>,
+    pub bindings: Vec<RoleBinding>,
+    pub rules: Vec<PolicyRule>,
+    /// Cache TTL for rules without their own and for the no-match deny.
+    pub default_cache_ttl_ms: u32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Role {
+    pub rules: Vec<PolicyRule>,
+}
+
+/// Grants `role` to matching subjects. Empty lists match everyone; claim
+/// filters follow feature flag targeting (array claims match on any element).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RoleBinding {
+    pub role: String,
+    #[serde(default)]
+    pub tenants: Vec<String>,
+    /// Subject ids.
+    #[serde(default)]
+    pub subjects: Vec<String>,
+    #[serde(default)]
+    pub subject_kinds: Vec<SubjectKind>,
+    #[serde(default)]
+    pub claims: BTreeMap<String, Vec<Value>>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Effect {
+    #[default]
+    Allow,
+    Deny,
+}
+
+/// Matches when the resource matches one of `resources`, the action is in
+/// `actions` (empty: any) and every condition holds.
+///
+/// Resource patterns are compared per `:` segment: `*` matches one segment,
+/// a trailing `*` everything below, and `tool*` any segment starting with
+/// `tool`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PolicyRule {
+    pub id: String,
+    #[serde(default)]
+    pub effect: Effect,
+    pub resources: Vec<String>,
+    #[serde(default)]
+    pub actions: Vec<Action>,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    #[serde(default)]
+    pub obligations: Vec<Obligation>,
+    #[serde(default)]
+    pub cache_ttl_ms: Option<u32>,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// `path` is `attrs.<a.b>` (request attributes), `claims.<a.b>` (subject
+/// claims), `subject.id`, `subject.kind` or `tenant`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Condition {
+    pub path: String,
+    pub op: ConditionOp,
+    #[serde(default)]
+    pub value: Value,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConditionOp {
+    Eq,
+    Ne,
+    In,
+    NotIn,
+    Exists,
+    Absent,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    Prefix,
+}
+
+impl PolicyDocument {
+    /// Rejects bindings to undefined roles and missing or duplicate rule ids.
+    pub fn validate(&self) -> Result<(), String> {
+        for binding in &self.bindings {
+            if !self.roles.contains_key(&binding.role) {
+                return Err(format!("binding refers to unknown role `{}`", binding.role));
+            }
+        }
+        let mut seen = Vec::new();
+        let all_rules = self
+            .rules
+            .iter()
+            .chain(self.roles.values().flat_map(|role| role.rules.iter()));
+        for rule in all_rules {
+            if rule.id.is_empty() {
+                return Err("rule without an id".to_string());
+            }
+            if seen.contains(&rule.id.as_str()) {
+                return Err(format!("duplicate rule id `{}`", rule.id));
+            }
+            seen.push(rule.id.as_str());
+        }
+        Ok(())
+    }
+
+    /// Roles bound to `subject`, in definition order.
+    pub fn roles_for(&self, subject: &Subject) -> Vec<&str> {
+        self.roles
+            .keys()
+            .map(String::as_str)
+            .filter(|role| {
+                self.bindings
+                    .iter()
+                    .any(|binding| binding.role == *role && binding_matches(binding, subject))
+            })
+            .collect()
+    }
+
+    pub fn evaluate(&self, request: &AuthzRequest) -> Decision {
+        let roles = self.roles_for(&request.subject);
+        let candidates = self
+            .rules
+            .iter()
+            .map(|rule| (None, rule))
+            .chain(roles.iter().flat_map(|role| {
+                self.roles[*role]
+                    .rules
+                    .iter()
+                    .map(move |rule| (Some(*role), rule))
+            }));
+        let matched: Vec<(Option<&str>, &PolicyRule)> = candidates
+            .filter(|(_, rule)| rule_matches(rule, request))
+            .collect();
+        let ttl = |rule: &PolicyRule| rule.cache_ttl_ms.unwrap_or(self.default_cache_ttl_ms);
+
+        if let Some((role, rule)) = matched.iter().find(|(_, rule)| rule.effect == Effect::Deny) {
+            return Decision {
+                allow: false,
+                reason: Some(
+                    rule.reason
+                        .clone()
+                        .unwrap_or_else(|| format!("denied by policy rule {}", rule.id)),
+                ),
+                obligations: rule.obligations.clone(),
+                evidence: evidence(Effect::Deny, rule, *role, &roles, &matched),
+                cache_ttl_ms: ttl(rule),
+            };
+        }
+        let Some((role, rule)) = matched.first() else {
+            return Decision {
+                evidence: json!({ "policy": "authz", "effect": "deny", "roles": roles }),
+                cache_ttl_ms: self.default_cache_ttl_ms,
+                ..Decision::deny("no policy rule matched")
+            };
+        };
+        let mut obligations: Vec<Obligation> = Vec::new();
+        for (_, allow) in &matched {
+            for obligation in &allow.obligations {
+                if !obligations.contains(obligation) {
+                    obligations.push(obligation.clone());
+                }
+            }
+        }
+        Decision {
+            allow: true,
+            reason: rule.reason.clone(),
+            obligations,
+            evidence: evidence(Effect::Allow, rule, *role, &roles, &matched),
+            cache_ttl_ms: matched.iter().map(|(_, rule)| ttl(rule)).min().unwrap_or(0),
+        }
+    }
+}
+
+fn evidence(
+    effect: Effect,
+    rule: &PolicyRule,
+    role: Option<&str>,
+    roles: &[&str],
+    matched: &[(Option<&str>, &PolicyRule)],
+) -> Value {
+    json!({
+        "policy": "authz",
+        "effect": effect,
+        "rule": rule.id,
+        "role": role,
+        "roles": roles,
+        "matched": matched.iter().map(|(_, rule)| rule.id.as_str()).collect::<Vec<_>>(),
+    })
+}
+
+fn binding_matches(binding: &RoleBinding, subject: &Subject) -> bool {
+    let tenant_ok = binding.tenants.is_empty() || binding.tenants.contains(&subject.tenant.0);
+    let subject_ok =
+        binding.subjects.is_empty() || binding.subjects.contains(&subject.subject_id.0);
+    let kind_ok = binding.subject_kinds.is_empty() || binding.subject_kinds.contains(&subject.kind);
+    let claims_ok =
+        binding
+            .claims
+            .iter()
+            .all(|(claim, accepted)| match subject.claims.get(claim) {
+                Some(Value::Array(values)) => values.iter().any(|value| accepted.contains(value)),
+                Some(value) => accepted.contains(value),
+                None => false,
+            });
+    tenant_ok && subject_ok && kind_ok && claims_ok
+}
+
+fn rule_matches(rule: &PolicyRule, request: &AuthzRequest) -> bool {
+    rule.resources
+        .iter()
+        .any(|pattern| urn_matches(pattern, &request.resource.0))
+        && (rule.actions.is_empty() || rule.actions.contains(&request.action))
+        && rule
+            .conditions
+            .iter()
+            .all(|condition| condition_holds(condition, request))
+}
+
+/// Segment-wise URN match, see [`PolicyRule`].
+pub fn urn_matches(pattern: &str, urn: &str) -> bool {
+    let pattern: Vec<&str> = pattern.split(':').collect();
+    let urn: Vec<&str> = urn.split(':').collect();
+    for (index, segment) in pattern.iter().enumerate() {
+        let last = index + 1 == pattern.len();
+        if last && *segment == "*" {
+            return urn.len() > index;
+        }
+        let Some(actual) = urn.get(index) else {
+            return false;
+        };
+        let ok = match segment.strip_suffix('*') {
+            Some(prefix) => actual.starts_with(prefix),
+            None => segment == actual,
+        };
+        if !ok {
+            return false;
+        }
+    }
+    pattern.len() == urn.len()
+}
+
+fn resolve(path: &str, request: &AuthzRequest) -> Option<Value> {
+    let lookup = |root: &Value, rest: &str| {
+        rest.split('.')
+            .try_fold(root, |cursor, segment| cursor.get(segment))
+            .cloned()
+    };
+    match path.split_once('.') {
+        Some(("attrs", rest)) => lookup(&request.attrs, rest),
+        Some(("claims", rest)) => {
+            let (claim, rest) = rest
+                .split_once('.')
+                .map_or((rest, None), |(a, b)| (a, Some(b)));
+            let value = request.subject.claims.get(claim)?;
+            match rest {
+                Some(rest) => lookup(value, rest),
+                None => Some(value.clone()),
+            }
+        }
+        Some(("subject", "id")) => Some(json!(request.subject.subject_id.0)),
+        Some(("subject", "kind")) => serde_json::to_value(&request.subject.kind).ok(),
+        None if path == "tenant" => Some(json!(request.subject.tenant.0)),
+        _ => None,
+    }
+}
+
+fn condition_holds(condition: &Condition, request: &AuthzRequest) -> bool {
+    let actual = resolve(&condition.path, request);
+    let expected = &condition.value;
+    let order = || {
+        let actual = actual.as_ref()?.as_f64()?;
+        actual.partial_cmp(&expected.as_f64()?)
+    };
+    let listed = |actual: &Value| {
+        expected
+            .as_array()
+            .is_some_and(|values| values.contains(actual))
+    };
+    match condition.op {
+        ConditionOp::Exists => actual.is_some_and(|value| !value.is_null()),
+        ConditionOp::Absent => actual.is_none_or(|value| value.is_null()),
+        ConditionOp::Eq => actual.as_ref() == Some(expected),
+        ConditionOp::Ne => actual.as_ref() != Some(expected),
+        ConditionOp::In => actual.as_ref().is_some_and(listed),
+        ConditionOp::NotIn => !actual.as_ref().is_some_and(listed),
+        ConditionOp::Gt => order() == Some(Ordering::Greater),
+        ConditionOp::Gte => matches!(order(), Some(Ordering::Greater | Ordering::Equal)),
+        ConditionOp::Lt => order() == Some(Ordering::Less),
+        ConditionOp::Lte => matches!(order(), Some(Ordering::Less | Ordering::Equal)),
+        ConditionOp::Prefix => match (actual.as_ref().and_then(Value::as_str), expected.as_str()) {
+            (Some(actual), Some(prefix)) => actual.starts_with(prefix),
+            _ => false,
+        },
+    }
+}
+
+#[derive(Clone)]
+struct CompiledPolicy {
+    version: String,
+    policy: Result<Arc<PolicyDocument>, String>,
+}
+
+/// Decides with the [`PolicyDocument`] in the current config snapshot.
+///
+/// The document is re-read whenever the snapshot changes, so reloading the
+/// namespace takes effect on the next decision. Evidence carries a digest of
+/// the policy namespace as `policy_version`, so reloads that leave the policy
+/// untouched keep the version (and cached decisions) stable. A policy that
+/// does not parse or validate denies everything rather than failing open.
+pub struct PolicyAuthorizer {
+    switch: Arc<SnapshotSwitch>,
+    namespace: String,
+    compiled: Mutex<HashMap<Checksum, CompiledPolicy>>,
+}
+
+impl PolicyAuthorizer {
+    pub fn new(switch: Arc<SnapshotSwitch>) -> Self {
+        Self {
+            switch,
+            namespace: POLICY_NAMESPACE.to_string(),
+            compiled: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    /// The policy in effect for `subject`'s tenant (canary rollouts apply),
+    /// along with its version.
+    pub fn policy(&self, subject: &Subject) -> (String, Result<Arc<PolicyDocument>, String>) {
+        let snapshot = self.switch.get_for_tenant(&subject.tenant);
+        let checksum = snapshot.checksum().clone();
+        let mut compiled = self.compiled.lock();
+        if let Some(hit) = compiled.get(&checksum) {
+            return (hit.version.clone(), hit.policy.clone());
+        }
+        let raw = snapshot.get_raw(&KeyPath(self.namespace.clone()));
+        let version = policy_digest(raw);
+        let policy = match raw {
+            None => Ok(PolicyDocument::default()),
+            Some(raw) => serde_json::from_value::<PolicyDocument>(raw.clone())
+                .map_err(|err| err.to_string())
+                .and_then(|doc| doc.validate().map(|_| doc)),
+        }
+        .map(Arc::new);
+        if compiled.len() >= COMPILED_CACHE {
+            compiled.clear();
+        }
+        compiled.insert(
+            checksum,
+            CompiledPolicy {
+                version: version.clone(),
+                policy: policy.clone(),
+            },
+        );
+        (version, policy)
+    }
+}
+
+#[async_trait]
+impl Authorizer for PolicyAuthorizer {
+    async fn decide(&self, request: &AuthzRequest) -> Result<Decision, AuthError> {
+        let (version, policy) = self.policy(&request.subject);
+        let mut decision = match policy {
+            Ok(policy) => policy.evaluate(request),
+            Err(err) => Decision {
+                evidence: json!({ "policy": "authz", "error": err }),
+                ..Decision::deny(format!("policy `{}` is invalid", self.namespace))
+            },
+        };
+        if let Value::Object(evidence) = &mut decision.evidence {
+            evidence.insert("policy_version".to_string(), json!(version));
+        }
+        Ok(decision)
+    }
+
+    /// A digest of the policy namespace the subject's tenant reads from.
+    fn policy_version(&self, subject: &Subject) -> Option<String> {
+        Some(self.policy(subject).0)
+    }
+}
+
+/// SHA-256 over the namespace value, so unrelated config changes do not
+/// move the version.
+fn policy_digest(raw: Option<&Value>) -> String {
+    let serialised = raw
+        .and_then(|value| serde_json::to_vec(value).ok())
+        .unwrap_or_default();
+    STANDARD.encode(Sha256::digest(serialised))
+}
+
+/// Registers `namespace` (normally [`POLICY_NAMESPACE`], or whatever the
+/// authorizer was given via `with_namespace`) so malformed policies are
+/// rejected at load time and the previous snapshot stays in effect.
+#[allow(clippy::result_large_err)]
+pub fn register_policy(registry: &dyn SchemaRegistry, namespace: &str) -> Result<(), ConfigError> {
+    let schema: SchemaDoc = serde_json::from_value(policy_schema())
+        .map_err(|err| errors::schema_invalid("schema", &format!("{namespace}: {err}")))?;
+    let fields = ["roles", "bindings", "rules", "default_cache_ttl_ms"]
+        .into_iter()
+        .map(|field| {
+            let meta = FieldMeta {
+                reload: ReloadClass::HotReloadSafe,
+                sensitive: false,
+                default_value: None,
+                description: None,
+            };
+            (KeyPath(field.to_string()), meta)
+        })
+        .collect();
+    registry.register_namespace(NamespaceId(namespace.to_string()), Some(schema), fields)
+}
+
+fn policy_schema() -> Value {
+    let string_list = json!({ "type": "array", "items": { "type": "string" } });
+    json!({
+        "type": "object",
+        "additionalProperties": false,
+        "properties": {
+            "default_cache_ttl_ms": { "type": "integer", "minimum": 0 },
+            "roles": {
+                "type": "object",
+                "additionalProperties": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "properties": {
+                        "rules": { "type": "array", "items": { "$ref": "#/definitions/Rule" } }
+                    }
+                }
+            },
+            "bindings": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "required": ["role"],
+                    "properties": {
+                        "role": { "type": "string" },
+                        "tenants": string_list,
+                        "subjects": string_list,
+                        "subject_kinds": {
+                            "type": "array",
+                            "items": { "enum": ["User", "Service", "Agent"] }
+                        },
+                        "claims": { "type": "object", "additionalProperties": { "type": "array" } }
+                    }
+                }
+            },
+            "rules": { "type": "array", "items": { "$ref": "#/definitions/Rule" } }
+        },
+        "definitions": {
+            "Rule": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["id", "resources"],
+                "properties": {
+                    "id": { "type": "string", "minLength": 1 },
+                    "effect": { "enum": ["allow", "deny"] },
+                    "resources": string_list,
+                    "actions": {
+                        "type": "array",
+                        "items": {
+                            "enum": ["Read", "Write", "Invoke", "List", "Admin", "Configure"]
+                        }
+                    },
+                    "conditions": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "additionalProperties": false,
+                            "required": ["path", "op"],
+                            "properties": {
+                                "path": { "type": "string" },
+                                "op": {
+                                    "enum": [
+                                        "eq", "ne", "in", "not_in", "exists", "absent",
+                                        "gt", "gte", "lt", "lte", "prefix"
+                                    ]
+                                },
+                                "value": {}
+                            }
+                        }
+                    },
+                    "obligations": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "required": ["kind"],
+                            "properties": { "kind": { "type": "string" }, "params": {} }
+                        }
+                    },
+                    "cache_ttl_ms": { "type": "integer", "minimum": 0 },
+                    "reason": { "type": "string" }
+                }
+            }
+        }
+    })
+}
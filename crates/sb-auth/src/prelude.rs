@@ -1,11 +1,38 @@
-pub use crate::attr::{AttributeProvider, StaticAttributeProvider};
+#[cfg(feature = "attr-storage")]
+pub use crate::attr::storage::{
+    ResourceAttributeProvider, ResourceAttributes, SubjectAttributeProvider, SubjectAttributes,
+    TimeoutAttributeProvider,
+};
+pub use crate::attr::{AttributeProvider, CompositeAttributeProvider, StaticAttributeProvider};
+#[cfg(feature = "authn-apikey")]
+pub use crate::authn::apikey::{
+    ApiKeyAuthenticator, ApiKeyRecord, ApiKeyScope, ApiKeyScopeAuthorizer, ApiKeySpec, IssuedApiKey,
+};
+#[cfg(feature = "authn-jwt")]
+pub use crate::authn::jwt::{ClaimMapping, JwksSource, JwtAuthenticator};
 pub use crate::authn::{subject_from_claims, Authenticator, AuthnInput, StaticTokenAuthenticator};
-pub use crate::cache::{DecisionCache, MemoryDecisionCache};
-pub use crate::consent::{BasicConsentVerifier, ConsentVerifier};
+#[cfg(feature = "cache-redis")]
+pub use crate::cache::redis::{RedisDecisionCache, RedisDecisionCacheConfig};
+pub use crate::cache::{cache_ttl, CacheStats, DecisionCache, MemoryDecisionCache};
+#[cfg(feature = "consent-jws")]
+pub use crate::consent::jws::{
+    ConsentClaims, ConsentGrant, ConsentIssuer, ConsentLedger, ConsentRecord, IssuedConsent,
+    JwsConsentVerifier, CONSENT_TOKEN_TYPE,
+};
+pub use crate::consent::{consent_covers, scope_covers, BasicConsentVerifier, ConsentVerifier};
 pub use crate::errors::AuthError;
 pub use crate::facade::{AuthContext, AuthFacade, AuthResult};
 pub use crate::model::{
     Action, AuthzRequest, Decision, DecisionKey, Obligation, QuotaKey, ResourceUrn,
 };
+#[cfg(feature = "pdp-policy")]
+pub use crate::pdp::policy::{
+    register_policy, Condition, ConditionOp, Effect, PolicyAuthorizer, PolicyDocument, PolicyRule,
+    Role, RoleBinding, POLICY_NAMESPACE,
+};
 pub use crate::pdp::{AllowAllAuthorizer, Authorizer, StaticPolicyAuthorizer};
-pub use crate::quota::{MemoryQuotaStore, QuotaOutcome, QuotaStore};
+#[cfg(feature = "quota-redis")]
+pub use crate::quota::redis::{RedisQuotaConfig, RedisQuotaStore};
+#[cfg(feature = "quota-window")]
+pub use crate::quota::window::{BudgetPeriod, QuotaLimit, QuotaRule, WindowedQuotaStore};
+pub use crate::quota::{MemoryQuotaStore, QuotaDecision, QuotaOutcome, QuotaStore};
@@ -1,11 +1,18 @@
 use async_trait::async_trait;
 use parking_lot::Mutex;
+use sb_errors::prelude::BackoffHint;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 use crate::errors::AuthError;
 use crate::model::QuotaKey;
 
+#[cfg(feature = "quota-redis")]
+pub mod redis;
+#[cfg(feature = "quota-window")]
+pub mod window;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum QuotaOutcome {
     Allowed,
@@ -13,10 +20,90 @@ pub enum QuotaOutcome {
     BudgetExceeded,
 }
 
+/// A quota check with the detail callers need to back off.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuotaDecision {
+    pub outcome: QuotaOutcome,
+    /// When the same cost would fit again; `None` when unknown or never.
+    pub retry_after: Option<Duration>,
+    /// Units left after this check, when the store tracks them.
+    pub remaining: Option<i64>,
+}
+
+impl QuotaDecision {
+    pub fn allowed(remaining: Option<i64>) -> Self {
+        Self {
+            outcome: QuotaOutcome::Allowed,
+            retry_after: None,
+            remaining,
+        }
+    }
+
+    pub fn denied(outcome: QuotaOutcome, retry_after: Option<Duration>, remaining: i64) -> Self {
+        Self {
+            outcome,
+            retry_after,
+            remaining: Some(remaining.max(0)),
+        }
+    }
+
+    pub fn backoff_hint(&self) -> Option<BackoffHint> {
+        self.retry_after.map(|after| {
+            let ms = after.as_millis().max(1) as u64;
+            BackoffHint {
+                initial_ms: ms,
+                max_ms: ms,
+            }
+        })
+    }
+
+    /// The error for a denied check, carrying the backoff hint; `None` when
+    /// allowed.
+    pub fn error(&self) -> Option<AuthError> {
+        let mut err = match self.outcome {
+            QuotaOutcome::Allowed => return None,
+            QuotaOutcome::RateLimited => AuthError::rate_limited(),
+            QuotaOutcome::BudgetExceeded => AuthError::budget_exceeded(),
+        };
+        err.0.backoff_hint = self.backoff_hint();
+        Some(err)
+    }
+}
+
+impl From<QuotaOutcome> for QuotaDecision {
+    fn from(outcome: QuotaOutcome) -> Self {
+        Self {
+            outcome,
+            retry_after: None,
+            remaining: None,
+        }
+    }
+}
+
 #[async_trait]
 pub trait QuotaStore: Send + Sync {
     async fn check_and_consume(&self, key: &QuotaKey, cost: i64)
         -> Result<QuotaOutcome, AuthError>;
+
+    /// `check_and_consume` plus retry-after and remaining units for stores
+    /// that know them.
+    async fn check(&self, key: &QuotaKey, cost: i64) -> Result<QuotaDecision, AuthError> {
+        self.check_and_consume(key, cost)
+            .await
+            .map(QuotaDecision::from)
+    }
+
+    /// Returns units consumed at `taken_at`, e.g. when a reservation turns
+    /// out to be larger than the actual usage. Budgets only take back units
+    /// from a period that is still running. Stores without refunds ignore it.
+    async fn refund(
+        &self,
+        _key: &QuotaKey,
+        _amount: i64,
+        _taken_at: SystemTime,
+    ) -> Result<(), AuthError> {
+        Ok(())
+    }
 }
 
 #[derive(Clone, Default)]
@@ -56,4 +143,16 @@ impl QuotaStore for MemoryQuotaStore {
         *used += cost;
         Ok(QuotaOutcome::Allowed)
     }
+
+    async fn refund(
+        &self,
+        key: &QuotaKey,
+        amount: i64,
+        _taken_at: SystemTime,
+    ) -> Result<(), AuthError> {
+        if let Some(entry) = self.limits.lock().get_mut(key) {
+            entry.1 = (entry.1 - amount).max(0);
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,295 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use redis::aio::MultiplexedConnection;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use super::window::{best_rule, window, QuotaLimit, QuotaRule};
+use super::{QuotaDecision, QuotaOutcome, QuotaStore};
+use crate::errors::AuthError;
+use crate::model::QuotaKey;
+
+/// Checks every limit of a key and consumes from all of them only when all
+/// have room. Buckets refill against the Redis server clock so gateways with
+/// skewed clocks agree.
+///
+/// KEYS: one per limit. ARGV: cost, then four values per limit:
+/// `bucket, capacity, refill_per_sec, ttl_ms` or
+/// `budget, limit, retry_after_ms, ttl_ms`.
+/// Returns `{outcome, retry_after_ms (-1: none), remaining}`.
+const CHECK_SCRIPT: &str = r"
+local cost = tonumber(ARGV[1])
+local clock = redis.call('TIME')
+local now = tonumber(clock[1]) * 1000 + math.floor(tonumber(clock[2]) / 1000)
+local current = {}
+local denied, retry, left = nil, -1, 0
+for i, key in ipairs(KEYS) do
+  local base = 1 + (i - 1) * 4
+  local kind = ARGV[base + 1]
+  local a, b = tonumber(ARGV[base + 2]), tonumber(ARGV[base + 3])
+  if kind == 'bucket' then
+    local state = redis.call('HMGET', key, 'tokens', 'ts')
+    local tokens = tonumber(state[1]) or a
+    local ts = tonumber(state[2]) or now
+    tokens = math.min(a, tokens + math.max(0, now - ts) / 1000 * b)
+    current[i] = tokens
+    if tokens < cost and denied ~= 'budget_exceeded' then
+      denied, retry, left = 'rate_limited', -1, math.floor(tokens)
+      if cost <= a and b > 0 then
+        retry = math.ceil((cost - tokens) / b * 1000)
+      end
+    end
+  else
+    local used = tonumber(redis.call('GET', key)) or 0
+    current[i] = used
+    if used + cost > a then
+      denied, retry, left = 'budget_exceeded', -1, a - used
+      if cost <= a then
+        retry = b
+      end
+    end
+  end
+end
+if denied then
+  return {denied, retry, left}
+end
+local remaining = nil
+for i, key in ipairs(KEYS) do
+  local base = 1 + (i - 1) * 4
+  local kind = ARGV[base + 1]
+  local a, ttl = tonumber(ARGV[base + 2]), tonumber(ARGV[base + 4])
+  local after
+  if kind == 'bucket' then
+    after = current[i] - cost
+    redis.call('HSET', key, 'tokens', tostring(after), 'ts', now)
+    after = math.floor(after)
+  else
+    after = a - redis.call('INCRBY', key, cost)
+  end
+  redis.call('PEXPIRE', key, ttl)
+  if remaining == nil or after < remaining then
+    remaining = after
+  end
+end
+return {'allowed', -1, remaining or 0}
+";
+
+/// Credits units back to the limits that still exist. ARGV: amount, then
+/// `kind, capacity_or_limit` per limit.
+const REFUND_SCRIPT: &str = r"
+local amount = tonumber(ARGV[1])
+for i, key in ipairs(KEYS) do
+  local kind = ARGV[(i - 1) * 2 + 2]
+  local cap = tonumber(ARGV[(i - 1) * 2 + 3])
+  if kind == 'bucket' then
+    local tokens = tonumber(redis.call('HGET', key, 'tokens'))
+    if tokens then
+      redis.call('HSET', key, 'tokens', tostring(math.min(cap, tokens + amount)))
+    end
+  else
+    local used = tonumber(redis.call('GET', key))
+    if used then
+      redis.call('SET', key, math.max(0, used - amount), 'KEEPTTL')
+    end
+  end
+end
+return 1
+";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RedisQuotaConfig {
+    /// Redis connection string (e.g. redis://127.0.0.1:6379/)
+    pub url: String,
+    /// Prefix for every counter key.
+    pub key_prefix: String,
+    pub timeout_ms: u64,
+}
+
+impl Default for RedisQuotaConfig {
+    fn default() -> Self {
+        Self {
+            url: "redis://127.0.0.1:6379/".into(),
+            key_prefix: "sb:auth:quota".into(),
+            timeout_ms: 2_000,
+        }
+    }
+}
+
+/// [`QuotaRule`]s enforced in Redis, shared by every gateway that points at
+/// the same server. Same semantics as
+/// [`WindowedQuotaStore`](super::window::WindowedQuotaStore); budget periods
+/// are keyed by their start, so a new period starts from zero and old
+/// counters expire on their own.
+#[derive(Clone)]
+pub struct RedisQuotaStore {
+    conn: MultiplexedConnection,
+    rules: Arc<Vec<QuotaRule>>,
+    prefix: String,
+    timeout: Duration,
+    check: Arc<redis::Script>,
+    refund: Arc<redis::Script>,
+}
+
+impl RedisQuotaStore {
+    pub async fn connect(
+        config: RedisQuotaConfig,
+        rules: Vec<QuotaRule>,
+    ) -> Result<Self, AuthError> {
+        let timeout = Duration::from_millis(config.timeout_ms);
+        let client = redis::Client::open(config.url)
+            .map_err(|err| AuthError::redis_unavailable(format!("client create failed: {err}")))?;
+        let conn = tokio::time::timeout(timeout, client.get_multiplexed_tokio_connection())
+            .await
+            .map_err(|_| AuthError::redis_unavailable("connect timed out".to_string()))?
+            .map_err(|err| AuthError::redis_unavailable(format!("connect failed: {err}")))?;
+        Ok(Self {
+            conn,
+            rules: Arc::new(rules),
+            prefix: config.key_prefix,
+            timeout,
+            check: Arc::new(redis::Script::new(CHECK_SCRIPT)),
+            refund: Arc::new(redis::Script::new(REFUND_SCRIPT)),
+        })
+    }
+
+    /// Counter keys share a hash tag per quota key, so the scripts stay on
+    /// one Redis Cluster slot. Budget keys name the period containing `at`.
+    fn limit_keys(
+        &self,
+        key: &QuotaKey,
+        index: usize,
+        rule: &QuotaRule,
+        at: DateTime<Utc>,
+    ) -> Vec<String> {
+        let tag = format!(
+            "{}|{}|{}|{:?}",
+            key.tenant, key.subject_id, key.resource.0, key.action
+        );
+        rule.limits
+            .iter()
+            .enumerate()
+            .map(|(limit_index, limit)| {
+                let base = format!("{}:{{{tag}}}:{index}:{limit_index}", self.prefix);
+                match limit {
+                    QuotaLimit::TokenBucket { .. } => base,
+                    QuotaLimit::Budget {
+                        period, timezone, ..
+                    } => {
+                        let start = window(*period, *timezone, at).0;
+                        format!("{base}:{}", start.format("%Y%m%dT%H"))
+                    }
+                }
+            })
+            .collect()
+    }
+
+    async fn run(
+        &self,
+        invocation: redis::ScriptInvocation<'_>,
+    ) -> Result<redis::Value, AuthError> {
+        let mut conn = self.conn.clone();
+        tokio::time::timeout(
+            self.timeout,
+            invocation.invoke_async::<_, redis::Value>(&mut conn),
+        )
+        .await
+        .map_err(|_| AuthError::redis_unavailable("script timed out".to_string()))?
+        .map_err(|err| AuthError::redis_unavailable(format!("script failed: {err}")))
+    }
+}
+
+#[async_trait]
+impl QuotaStore for RedisQuotaStore {
+    async fn check_and_consume(
+        &self,
+        key: &QuotaKey,
+        cost: i64,
+    ) -> Result<QuotaOutcome, AuthError> {
+        self.check(key, cost).await.map(|decision| decision.outcome)
+    }
+
+    async fn check(&self, key: &QuotaKey, cost: i64) -> Result<QuotaDecision, AuthError> {
+        let Some((index, rule)) = best_rule(&self.rules, key) else {
+            return Ok(QuotaDecision::allowed(None));
+        };
+        let now = Utc::now();
+        let mut invocation = self.check.prepare_invoke();
+        invocation.arg(cost);
+        for (limit_key, limit) in self
+            .limit_keys(key, index, rule, now)
+            .iter()
+            .zip(&rule.limits)
+        {
+            invocation.key(limit_key);
+            match limit {
+                QuotaLimit::TokenBucket {
+                    capacity,
+                    refill_per_sec,
+                } => {
+                    let full_ms = if *refill_per_sec > 0.0 {
+                        (*capacity as f64 / refill_per_sec * 1000.0).ceil() as i64
+                    } else {
+                        i64::from(i32::MAX)
+                    };
+                    invocation
+                        .arg("bucket")
+                        .arg(capacity)
+                        .arg(refill_per_sec)
+                        .arg(full_ms + 1_000);
+                }
+                QuotaLimit::Budget {
+                    limit,
+                    period,
+                    timezone,
+                } => {
+                    let reset = window(*period, *timezone, now).1;
+                    let retry_ms = (reset - now).num_milliseconds().max(1);
+                    invocation
+                        .arg("budget")
+                        .arg(limit)
+                        .arg(retry_ms)
+                        .arg(retry_ms + 60_000);
+                }
+            }
+        }
+        let reply = self.run(invocation).await?;
+        let (outcome, retry_ms, remaining): (String, i64, i64) = redis::from_redis_value(&reply)
+            .map_err(|err| {
+                AuthError::redis_unavailable(format!("unexpected script reply: {err}"))
+            })?;
+        let retry_after = (retry_ms >= 0).then(|| Duration::from_millis(retry_ms as u64));
+        Ok(match outcome.as_str() {
+            "allowed" => QuotaDecision::allowed(Some(remaining)),
+            "rate_limited" => {
+                QuotaDecision::denied(QuotaOutcome::RateLimited, retry_after, remaining)
+            }
+            _ => QuotaDecision::denied(QuotaOutcome::BudgetExceeded, retry_after, remaining),
+        })
+    }
+
+    async fn refund(
+        &self,
+        key: &QuotaKey,
+        amount: i64,
+        taken_at: SystemTime,
+    ) -> Result<(), AuthError> {
+        let Some((index, rule)) = best_rule(&self.rules, key) else {
+            return Ok(());
+        };
+        let mut invocation = self.refund.prepare_invoke();
+        invocation.arg(amount);
+        // The budget key of the period the units came from; once that period
+        // has expired there is nothing left to credit.
+        let keys = self.limit_keys(key, index, rule, taken_at.into());
+        for (limit_key, limit) in keys.iter().zip(&rule.limits) {
+            invocation.key(limit_key);
+            match limit {
+                QuotaLimit::TokenBucket { capacity, .. } => invocation.arg("bucket").arg(capacity),
+                QuotaLimit::Budget { limit, .. } => invocation.arg("budget").arg(limit),
+            };
+        }
+        self.run(invocation).await.map(|_| ())
+    }
+}
@@ -0,0 +1,477 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Days, Months, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use super::{QuotaDecision, QuotaOutcome, QuotaStore};
+use crate::errors::AuthError;
+use crate::model::{Action, QuotaKey};
+
+/// One limit of a [`QuotaRule`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum QuotaLimit {
+    /// Up to `capacity` units at once, refilled continuously at
+    /// `refill_per_sec`. Running dry is `RateLimited`.
+    TokenBucket { capacity: i64, refill_per_sec: f64 },
+    /// `limit` units per calendar `period` in `timezone`, reset at the start
+    /// of each period. Running out is `BudgetExceeded`.
+    Budget {
+        limit: i64,
+        period: BudgetPeriod,
+        #[serde(default = "utc")]
+        timezone: Tz,
+    },
+}
+
+fn utc() -> Tz {
+    Tz::UTC
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetPeriod {
+    Hourly,
+    Daily,
+    /// Weeks start on Monday.
+    Weekly,
+    Monthly,
+}
+
+/// Limits for the quota keys a rule matches. `tenant` and `subject_id` are
+/// exact or `*`; `resource` is exact, `*`, or a prefix ending in `*`; no
+/// `action` matches every action.
+///
+/// A key uses the most specific matching rule (tenant, then subject, then
+/// resource, then action), so a `*` rule acts as the inherited default.
+/// Counters are still kept per concrete key.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct QuotaRule {
+    #[serde(default = "any")]
+    pub tenant: String,
+    #[serde(default = "any")]
+    pub subject_id: String,
+    #[serde(default = "any")]
+    pub resource: String,
+    #[serde(default)]
+    pub action: Option<Action>,
+    pub limits: Vec<QuotaLimit>,
+}
+
+fn any() -> String {
+    "*".to_string()
+}
+
+impl QuotaRule {
+    /// A rule matching every key; narrow it with the `for_*` methods.
+    pub fn new(limits: Vec<QuotaLimit>) -> Self {
+        Self {
+            tenant: any(),
+            subject_id: any(),
+            resource: any(),
+            action: None,
+            limits,
+        }
+    }
+
+    pub fn for_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = tenant.into();
+        self
+    }
+
+    pub fn for_subject(mut self, subject_id: impl Into<String>) -> Self {
+        self.subject_id = subject_id.into();
+        self
+    }
+
+    pub fn for_resource(mut self, resource: impl Into<String>) -> Self {
+        self.resource = resource.into();
+        self
+    }
+
+    pub fn for_action(mut self, action: Action) -> Self {
+        self.action = Some(action);
+        self
+    }
+
+    /// `None` when the rule does not apply, otherwise a rank where larger
+    /// means more specific.
+    fn specificity(&self, key: &QuotaKey) -> Option<(bool, bool, (bool, usize), bool)> {
+        let exact = |pattern: &str, value: &str| match pattern {
+            "*" => Some(false),
+            _ if pattern == value => Some(true),
+            _ => None,
+        };
+        let tenant = exact(&self.tenant, &key.tenant)?;
+        let subject = exact(&self.subject_id, &key.subject_id)?;
+        let resource = match self.resource.strip_suffix('*') {
+            Some(prefix) if key.resource.0.starts_with(prefix) => (false, prefix.len()),
+            Some(_) => return None,
+            None if self.resource == key.resource.0 => (true, self.resource.len()),
+            None => return None,
+        };
+        let action = match &self.action {
+            Some(action) if *action == key.action => true,
+            Some(_) => return None,
+            None => false,
+        };
+        Some((tenant, subject, resource, action))
+    }
+}
+
+/// The most specific rule for `key` and its index; earlier rules win ties.
+pub(crate) fn best_rule<'a>(
+    rules: &'a [QuotaRule],
+    key: &QuotaKey,
+) -> Option<(usize, &'a QuotaRule)> {
+    let mut best: Option<(usize, &QuotaRule, _)> = None;
+    for (index, rule) in rules.iter().enumerate() {
+        let Some(rank) = rule.specificity(key) else {
+            continue;
+        };
+        if best.as_ref().is_none_or(|(_, _, best)| rank > *best) {
+            best = Some((index, rule, rank));
+        }
+    }
+    best.map(|(index, rule, _)| (index, rule))
+}
+
+#[derive(Clone, Debug)]
+enum LimitState {
+    Bucket { tokens: f64, at_ms: i64 },
+    Budget { window: NaiveDateTime, used: i64 },
+}
+
+/// Map size below which idle entries are never pruned.
+const MIN_PRUNE_AT: usize = 1024;
+
+/// Per key and rule index, one state per limit of that rule.
+#[derive(Default)]
+struct LimitStates {
+    entries: HashMap<(QuotaKey, usize), Vec<LimitState>>,
+    /// Size at which idle entries are next dropped; doubles with the live
+    /// entries so pruning stays amortised.
+    prune_at: usize,
+}
+
+/// Token buckets and calendar budgets kept in memory.
+///
+/// Every limit of the matching rule must have room for the cost; otherwise
+/// nothing is consumed and the decision says when to retry. A budget that is
+/// out wins over a bucket that is empty. Keys without a matching rule are
+/// unlimited.
+#[derive(Clone, Default)]
+pub struct WindowedQuotaStore {
+    rules: Arc<Vec<QuotaRule>>,
+    state: Arc<Mutex<LimitStates>>,
+}
+
+impl WindowedQuotaStore {
+    pub fn new(rules: Vec<QuotaRule>) -> Self {
+        Self {
+            rules: Arc::new(rules),
+            state: Arc::default(),
+        }
+    }
+
+    pub fn rule_for(&self, key: &QuotaKey) -> Option<&QuotaRule> {
+        self.matching(key).map(|(_, rule)| rule)
+    }
+
+    fn matching(&self, key: &QuotaKey) -> Option<(usize, &QuotaRule)> {
+        best_rule(&self.rules, key)
+    }
+
+    pub fn check_at(&self, key: &QuotaKey, cost: i64, now: DateTime<Utc>) -> QuotaDecision {
+        let Some((index, rule)) = self.matching(key) else {
+            return QuotaDecision::allowed(None);
+        };
+        let mut state = self.state.lock();
+        if state.entries.len() >= state.prune_at {
+            self.prune(&mut state, now);
+        }
+        let limits = state
+            .entries
+            .entry((key.clone(), index))
+            .or_insert_with(|| {
+                rule.limits
+                    .iter()
+                    .map(|limit| initial(limit, now))
+                    .collect()
+            });
+        for (limit, state) in rule.limits.iter().zip(limits.iter_mut()) {
+            advance(limit, state, now);
+        }
+
+        let mut denied: Option<QuotaDecision> = None;
+        for (limit, state) in rule.limits.iter().zip(limits.iter()) {
+            let Some(decision) = shortfall(limit, state, cost, now) else {
+                continue;
+            };
+            let worse = denied.as_ref().is_none_or(|current| {
+                decision.outcome == QuotaOutcome::BudgetExceeded
+                    && current.outcome == QuotaOutcome::RateLimited
+            });
+            if worse {
+                denied = Some(decision);
+            }
+        }
+        if let Some(denied) = denied {
+            return denied;
+        }
+
+        let mut remaining = i64::MAX;
+        for (limit, state) in rule.limits.iter().zip(limits.iter_mut()) {
+            match (limit, state) {
+                (QuotaLimit::TokenBucket { .. }, LimitState::Bucket { tokens, .. }) => {
+                    *tokens -= cost as f64;
+                    remaining = remaining.min(tokens.floor() as i64);
+                }
+                (QuotaLimit::Budget { limit, .. }, LimitState::Budget { used, .. }) => {
+                    *used += cost;
+                    remaining = remaining.min(limit - *used);
+                }
+                _ => {}
+            }
+        }
+        QuotaDecision::allowed(Some(remaining))
+    }
+
+    /// Credits `amount` units taken at `taken_at` back: buckets up to their
+    /// capacity, budgets only while the period they were taken from runs.
+    pub fn refund_at(
+        &self,
+        key: &QuotaKey,
+        amount: i64,
+        taken_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) {
+        let Some((index, rule)) = self.matching(key) else {
+            return;
+        };
+        let mut state = self.state.lock();
+        let Some(limits) = state.entries.get_mut(&(key.clone(), index)) else {
+            return;
+        };
+        for (limit, state) in rule.limits.iter().zip(limits.iter_mut()) {
+            advance(limit, state, now);
+            match (limit, state) {
+                (QuotaLimit::TokenBucket { capacity, .. }, LimitState::Bucket { tokens, .. }) => {
+                    *tokens = (*tokens + amount as f64).min(*capacity as f64);
+                }
+                // Units from an earlier period were reset along with it.
+                (
+                    QuotaLimit::Budget {
+                        period, timezone, ..
+                    },
+                    LimitState::Budget {
+                        window: start,
+                        used,
+                    },
+                ) if window(*period, *timezone, taken_at).0 == *start => {
+                    *used = (*used - amount).max(0);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Drops entries that are back at their initial state (full buckets,
+    /// budgets with nothing used this period); recreating them is lossless.
+    fn prune(&self, states: &mut LimitStates, now: DateTime<Utc>) {
+        states.entries.retain(|(_, index), limits| {
+            let Some(rule) = self.rules.get(*index) else {
+                return false;
+            };
+            rule.limits
+                .iter()
+                .zip(limits.iter_mut())
+                .any(|(limit, state)| {
+                    advance(limit, state, now);
+                    !idle(limit, state)
+                })
+        });
+        states.prune_at = (states.entries.len() * 2).max(MIN_PRUNE_AT);
+    }
+}
+
+fn idle(limit: &QuotaLimit, state: &LimitState) -> bool {
+    match (limit, state) {
+        (QuotaLimit::TokenBucket { capacity, .. }, LimitState::Bucket { tokens, .. }) => {
+            *tokens >= *capacity as f64
+        }
+        (QuotaLimit::Budget { .. }, LimitState::Budget { used, .. }) => *used == 0,
+        _ => true,
+    }
+}
+
+fn initial(limit: &QuotaLimit, now: DateTime<Utc>) -> LimitState {
+    match limit {
+        QuotaLimit::TokenBucket { capacity, .. } => LimitState::Bucket {
+            tokens: *capacity as f64,
+            at_ms: now.timestamp_millis(),
+        },
+        QuotaLimit::Budget {
+            period, timezone, ..
+        } => LimitState::Budget {
+            window: window(*period, *timezone, now).0,
+            used: 0,
+        },
+    }
+}
+
+/// Refills buckets and resets budgets whose period is over.
+fn advance(limit: &QuotaLimit, state: &mut LimitState, now: DateTime<Utc>) {
+    match (limit, state) {
+        (
+            QuotaLimit::TokenBucket {
+                capacity,
+                refill_per_sec,
+            },
+            LimitState::Bucket { tokens, at_ms },
+        ) => {
+            let now_ms = now.timestamp_millis();
+            let elapsed = (now_ms - *at_ms).max(0) as f64 / 1000.0;
+            *tokens = (*tokens + elapsed * refill_per_sec).min(*capacity as f64);
+            *at_ms = now_ms.max(*at_ms);
+        }
+        (
+            QuotaLimit::Budget {
+                period, timezone, ..
+            },
+            LimitState::Budget {
+                window: start,
+                used,
+            },
+        ) => {
+            let current = window(*period, *timezone, now).0;
+            if current != *start {
+                *start = current;
+                *used = 0;
+            }
+        }
+        (limit, state) => *state = initial(limit, now),
+    }
+}
+
+fn shortfall(
+    limit: &QuotaLimit,
+    state: &LimitState,
+    cost: i64,
+    now: DateTime<Utc>,
+) -> Option<QuotaDecision> {
+    match (limit, state) {
+        (
+            QuotaLimit::TokenBucket {
+                capacity,
+                refill_per_sec,
+            },
+            LimitState::Bucket { tokens, .. },
+        ) => {
+            let missing = cost as f64 - tokens;
+            if missing <= 0.0 {
+                return None;
+            }
+            // A cost above capacity never fits, so there is nothing to wait for.
+            let retry_after = (cost <= *capacity && *refill_per_sec > 0.0)
+                .then(|| Duration::from_secs_f64(missing / refill_per_sec));
+            Some(QuotaDecision::denied(
+                QuotaOutcome::RateLimited,
+                retry_after,
+                tokens.floor() as i64,
+            ))
+        }
+        (
+            QuotaLimit::Budget {
+                limit,
+                period,
+                timezone,
+            },
+            LimitState::Budget { used, .. },
+        ) => {
+            if used + cost <= *limit {
+                return None;
+            }
+            let reset = window(*period, *timezone, now).1;
+            let retry_after = (cost <= *limit).then(|| (reset - now).to_std().unwrap_or_default());
+            Some(QuotaDecision::denied(
+                QuotaOutcome::BudgetExceeded,
+                retry_after,
+                limit - used,
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Local start of the period containing `now`, and the instant it ends.
+pub(crate) fn window(
+    period: BudgetPeriod,
+    tz: Tz,
+    now: DateTime<Utc>,
+) -> (NaiveDateTime, DateTime<Utc>) {
+    let local = now.with_timezone(&tz).naive_local();
+    let date = local.date();
+    let midnight = |date: NaiveDate| date.and_hms_opt(0, 0, 0).unwrap_or_default();
+    let (start, end) = match period {
+        BudgetPeriod::Hourly => {
+            let start = date.and_hms_opt(local.hour(), 0, 0).unwrap_or_default();
+            (start, start + chrono::Duration::hours(1))
+        }
+        BudgetPeriod::Daily => {
+            let start = midnight(date);
+            (start, start + Days::new(1))
+        }
+        BudgetPeriod::Weekly => {
+            let start =
+                midnight(date - Days::new(u64::from(date.weekday().num_days_from_monday())));
+            (start, start + Days::new(7))
+        }
+        BudgetPeriod::Monthly => {
+            let start = midnight(date.with_day(1).unwrap_or(date));
+            (start, start + Months::new(1))
+        }
+    };
+    (start, to_utc(tz, end))
+}
+
+/// Local times skipped by a DST change resolve to the next valid hour.
+fn to_utc(tz: Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    let mut candidate = local;
+    for _ in 0..4 {
+        if let Some(at) = tz.from_local_datetime(&candidate).earliest() {
+            return at.with_timezone(&Utc);
+        }
+        candidate += chrono::Duration::hours(1);
+    }
+    Utc.from_utc_datetime(&local)
+}
+
+#[async_trait]
+impl QuotaStore for WindowedQuotaStore {
+    async fn check_and_consume(
+        &self,
+        key: &QuotaKey,
+        cost: i64,
+    ) -> Result<QuotaOutcome, AuthError> {
+        Ok(self.check_at(key, cost, Utc::now()).outcome)
+    }
+
+    async fn check(&self, key: &QuotaKey, cost: i64) -> Result<QuotaDecision, AuthError> {
+        Ok(self.check_at(key, cost, Utc::now()))
+    }
+
+    async fn refund(
+        &self,
+        key: &QuotaKey,
+        amount: i64,
+        taken_at: SystemTime,
+    ) -> Result<(), AuthError> {
+        self.refund_at(key, amount, taken_at.into(), Utc::now());
+        Ok(())
+    }
+}
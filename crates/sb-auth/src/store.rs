@@ -0,0 +1,60 @@
+//! Helpers for the records sb-auth keeps in sb-storage (API keys, consents).
+
+use sb_storage::prelude::{Entity, Repository, Sort, StorageError};
+use sb_types::prelude::TenantId;
+use serde_json::Value;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::errors::AuthError;
+
+const LIST_PAGE_SIZE: usize = 100;
+
+/// Every record of `tenant` matching `filter`, read a page at a time.
+pub(crate) async fn select_all<T, R>(
+    repo: &R,
+    tenant: &TenantId,
+    filter: Value,
+) -> Result<Vec<T>, AuthError>
+where
+    T: Entity,
+    R: Repository<T> + ?Sized,
+{
+    let mut records = Vec::new();
+    let mut cursor = None;
+    loop {
+        // Cursors continue after the last id, so page in id order.
+        let page = repo
+            .select(
+                tenant,
+                filter.clone(),
+                Some(by_id()),
+                LIST_PAGE_SIZE,
+                cursor,
+            )
+            .await
+            .map_err(storage_error)?;
+        records.extend(page.items);
+        match page.next {
+            Some(next) => cursor = Some(next),
+            None => return Ok(records),
+        }
+    }
+}
+
+pub(crate) fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+pub(crate) fn storage_error(err: StorageError) -> AuthError {
+    AuthError(err.into_inner())
+}
+
+fn by_id() -> Vec<Sort> {
+    vec![Sort {
+        field: "id".into(),
+        asc: true,
+    }]
+}
@@ -0,0 +1,178 @@
+#![cfg(all(feature = "quota-redis", feature = "cache-redis"))]
+
+//! Runs against a real server: `REDIS_URL=redis://127.0.0.1:6379/ cargo test
+//! -p sb-auth --features quota-redis,cache-redis --test redis_e2e`. Skipped
+//! when `REDIS_URL` is unset.
+
+use sb_auth::prelude::*;
+use std::env;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn redis_url() -> Option<String> {
+    env::var("REDIS_URL").ok()
+}
+
+/// Fresh key namespace per run so reruns do not see old counters.
+fn run_prefix(label: &str) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    format!("sb-auth-test:{label}:{nanos}")
+}
+
+#[tokio::test]
+async fn redis_quota_is_shared_between_gateways() {
+    let Some(url) = redis_url() else {
+        eprintln!("REDIS_URL not set; skipping");
+        return;
+    };
+    let config = RedisQuotaConfig {
+        url,
+        key_prefix: run_prefix("quota"),
+        ..RedisQuotaConfig::default()
+    };
+    let rules = vec![
+        QuotaRule::new(vec![QuotaLimit::TokenBucket {
+            capacity: 3,
+            refill_per_sec: 0.5,
+        }])
+        .for_resource("soul:tool:*"),
+        QuotaRule::new(vec![QuotaLimit::Budget {
+            limit: 2,
+            period: BudgetPeriod::Daily,
+            timezone: chrono_tz::UTC,
+        }])
+        .for_resource("soul:llm:*"),
+    ];
+    let gateway_a = RedisQuotaStore::connect(config.clone(), rules.clone())
+        .await
+        .unwrap();
+    let gateway_b = RedisQuotaStore::connect(config, rules).await.unwrap();
+    let key = |resource: &str| QuotaKey {
+        tenant: "tenantA".into(),
+        subject_id: "user1".into(),
+        resource: ResourceUrn(resource.into()),
+        action: Action::Invoke,
+    };
+
+    let tool = key("soul:tool:browser");
+    assert_eq!(gateway_a.check(&tool, 2).await.unwrap().remaining, Some(1));
+    assert_eq!(
+        gateway_b.check(&tool, 1).await.unwrap().outcome,
+        QuotaOutcome::Allowed
+    );
+    let limited = gateway_a.check(&tool, 1).await.unwrap();
+    assert_eq!(limited.outcome, QuotaOutcome::RateLimited);
+    let retry = limited.retry_after.unwrap();
+    assert!(retry > Duration::from_millis(1500) && retry <= Duration::from_secs(2));
+    gateway_b.refund(&tool, 1, SystemTime::now()).await.unwrap();
+    assert_eq!(
+        gateway_a.check(&tool, 1).await.unwrap().outcome,
+        QuotaOutcome::Allowed
+    );
+
+    let llm = key("soul:llm:chat");
+    assert_eq!(
+        gateway_a.check_and_consume(&llm, 2).await.unwrap(),
+        QuotaOutcome::Allowed
+    );
+    let exceeded = gateway_b.check(&llm, 1).await.unwrap();
+    assert_eq!(exceeded.outcome, QuotaOutcome::BudgetExceeded);
+    assert!(exceeded.retry_after.unwrap() <= Duration::from_secs(24 * 3600));
+    assert!(exceeded
+        .error()
+        .unwrap()
+        .into_inner()
+        .backoff_hint
+        .is_some());
+
+    let unlimited = key("soul:doc:readme");
+    assert_eq!(
+        gateway_b.check(&unlimited, 1_000).await.unwrap().outcome,
+        QuotaOutcome::Allowed
+    );
+}
+
+#[tokio::test]
+async fn redis_decision_cache_invalidates_every_node() {
+    let Some(url) = redis_url() else {
+        eprintln!("REDIS_URL not set; skipping");
+        return;
+    };
+    let prefix = run_prefix("decision");
+    let config = RedisDecisionCacheConfig {
+        url,
+        key_prefix: prefix.clone(),
+        channel: format!("{prefix}:invalidate"),
+        ..RedisDecisionCacheConfig::default()
+    };
+    let node_a = RedisDecisionCache::connect(config.clone()).await.unwrap();
+    let node_b = RedisDecisionCache::connect(config.clone()).await.unwrap();
+    let key = DecisionKey {
+        tenant: "tenantA".into(),
+        subject_id: "user1".into(),
+        resource: ResourceUrn("soul:tool:browser".into()),
+        action: Action::Invoke,
+        attrs_hash: 7,
+    };
+    let decision = Decision {
+        cache_ttl_ms: 60_000,
+        ..Decision::allow_default()
+    };
+
+    node_a.put(key.clone(), &decision).await;
+    assert_eq!(node_b.get(&key).await, Some(decision.clone()));
+
+    node_a.invalidate(&key).await;
+    let mut evicted = false;
+    for _ in 0..50 {
+        if node_b.get(&key).await.is_none() {
+            evicted = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(evicted, "node b still serves an invalidated decision");
+
+    let short = Decision {
+        cache_ttl_ms: 100,
+        ..Decision::allow_default()
+    };
+    node_b.put(key.clone(), &short).await;
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert_eq!(node_a.get(&key).await, None);
+
+    node_a.put(key.clone(), &decision).await;
+    assert!(node_b.get(&key).await.is_some());
+    node_a.invalidate_tenant("tenantA").await;
+    let mut evicted = false;
+    for _ in 0..50 {
+        if node_b.get(&key).await.is_none() {
+            evicted = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(
+        evicted,
+        "node b still serves a decision of an invalidated tenant"
+    );
+    assert!(node_a.stats().invalidations >= 2);
+
+    // Local copies are capped per node; the least recently used goes first.
+    let bounded = RedisDecisionCache::connect(RedisDecisionCacheConfig {
+        local_capacity: 1,
+        ..config
+    })
+    .await
+    .unwrap();
+    let other = DecisionKey {
+        attrs_hash: 8,
+        ..key.clone()
+    };
+    bounded.put(key.clone(), &decision).await;
+    bounded.put(other, &decision).await;
+    let stats = bounded.stats();
+    assert_eq!((stats.size, stats.evictions), (1, 1));
+}
@@ -1,5 +1,6 @@
 use crate::model::{ConfigMap, KeyPath, NamespaceId};
 use crate::snapshot::ConfigSnapshot;
+use crate::tenant::TenantView;
 
 pub fn namespace_view(snapshot: &ConfigSnapshot, namespace: &NamespaceId) -> Option<ConfigMap> {
     snapshot
@@ -10,6 +11,17 @@ pub fn namespace_view(snapshot: &ConfigSnapshot, namespace: &NamespaceId) -> Opt
         })
 }
 
+/// `namespace_view` with the tenant's overlay applied.
+pub fn tenant_namespace_view(view: &TenantView<'_>, namespace: &NamespaceId) -> Option<ConfigMap> {
+    view.get_raw(&KeyPath(namespace.0.clone()))
+        .and_then(|value| match value.into_owned() {
+            serde_json::Value::Object(map) => Some(map),
+            _ => None,
+        })
+}
+
+/// The raw boolean at `key`; targeted flags go through
+/// [`FlagEngine`](crate::flags::FlagEngine).
 pub fn feature_flag(snapshot: &ConfigSnapshot, key: &KeyPath) -> Option<bool> {
     snapshot.get::<bool>(key)
 }
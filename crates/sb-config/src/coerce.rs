@@ -0,0 +1,218 @@
+use serde_json::Value;
+
+use crate::errors::{schema_invalid, ConfigError};
+use crate::model::{ConfigMap, KeyPath, NamespaceId};
+use crate::schema::{child_schema, variants, SchemaRegistry};
+
+/// Converts the string leaves of a string-only source (env, CLI) into the
+/// types declared by each namespace's `SchemaDoc`.
+///
+/// Keys without a schema, or whose schema accepts strings, are left as-is.
+/// Every failure is reported together, each naming the source and the key.
+#[allow(clippy::result_large_err)]
+pub fn coerce_strings(
+    source_id: &str,
+    map: &mut ConfigMap,
+    registry: &dyn SchemaRegistry,
+) -> Result<(), ConfigError> {
+    let mut failures = Vec::new();
+    for (namespace, value) in map.iter_mut() {
+        let Some(schema) = namespace_schema(registry, namespace) else {
+            continue;
+        };
+        coerce_node(value, &schema, &schema, namespace, &mut |key, msg| {
+            failures.push(format!("{source_id}: {key}: {msg}"))
+        });
+    }
+    into_result(failures)
+}
+
+/// Like [`coerce_strings`], but only for the string leaves at `keys` (dotted,
+/// namespace first), e.g. the values a file produced through interpolation.
+/// Every other value keeps the type it was written with.
+#[allow(clippy::result_large_err)]
+pub fn coerce_keys(
+    source_id: &str,
+    map: &mut ConfigMap,
+    keys: &[KeyPath],
+    registry: &dyn SchemaRegistry,
+) -> Result<(), ConfigError> {
+    let mut failures = Vec::new();
+    for KeyPath(key) in keys {
+        let mut segments = key.split('.');
+        let Some(namespace) = segments.next() else {
+            continue;
+        };
+        let (Some(schema), Some(value)) = (
+            namespace_schema(registry, namespace),
+            map.get_mut(namespace),
+        ) else {
+            continue;
+        };
+        let segments: Vec<&str> = segments.collect();
+        let Some((leaf, leaf_schema)) = locate(value, &schema, &schema, &segments) else {
+            continue;
+        };
+        let Value::String(raw) = &*leaf else {
+            continue;
+        };
+        match coerce_str(raw, leaf_schema, &schema) {
+            Ok(Some(coerced)) => *leaf = coerced,
+            Ok(None) => {}
+            Err(msg) => failures.push(format!("{source_id}: {key}: {msg}")),
+        }
+    }
+    into_result(failures)
+}
+
+fn namespace_schema(registry: &dyn SchemaRegistry, namespace: &str) -> Option<Value> {
+    registry
+        .get_namespace(&NamespaceId(namespace.to_string()))
+        .and_then(|view| view.schema)
+        .and_then(|schema| serde_json::to_value(schema).ok())
+}
+
+/// Follows `segments` through `value` and its schema together.
+fn locate<'a>(
+    value: &'a mut Value,
+    schema: &'a Value,
+    root: &'a Value,
+    segments: &[&str],
+) -> Option<(&'a mut Value, &'a Value)> {
+    match segments.split_first() {
+        None => Some((value, schema)),
+        Some((segment, rest)) => {
+            let child = value.get_mut(*segment)?;
+            let child_schema = child_schema(schema, root, segment)?;
+            locate(child, child_schema, root, rest)
+        }
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn into_result(failures: Vec<String>) -> Result<(), ConfigError> {
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(schema_invalid("coerce", &failures.join("; ")))
+    }
+}
+
+fn coerce_node(
+    value: &mut Value,
+    schema: &Value,
+    root: &Value,
+    path: &str,
+    fail: &mut dyn FnMut(&str, String),
+) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                if let Some(child_schema) = child_schema(schema, root, key) {
+                    coerce_node(child, child_schema, root, &format!("{path}.{key}"), fail);
+                }
+            }
+        }
+        Value::String(raw) => match coerce_str(raw, schema, root) {
+            Ok(Some(coerced)) => *value = coerced,
+            Ok(None) => {}
+            Err(msg) => fail(path, msg),
+        },
+        _ => {}
+    }
+}
+
+/// Coerces `raw` to the type `schema` declares. `Ok(None)` means the string
+/// should stay a string (no type information, or strings are accepted).
+pub fn coerce_str(raw: &str, schema: &Value, root: &Value) -> Result<Option<Value>, String> {
+    let types = declared_types(schema, root);
+    if types.is_empty() || types.contains(&"string") {
+        return Ok(None);
+    }
+    let trimmed = raw.trim();
+    for ty in &types {
+        let coerced = match *ty {
+            "null" if trimmed.is_empty() || trimmed == "null" => Some(Value::Null),
+            "boolean" => parse_bool(trimmed).map(Value::Bool),
+            "integer" => trimmed
+                .parse::<i64>()
+                .map(Value::from)
+                .or_else(|_| trimmed.parse::<u64>().map(Value::from))
+                .ok(),
+            "number" => trimmed
+                .parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite())
+                .map(Value::from),
+            "array" => return coerce_array(trimmed, schema, root).map(Some),
+            "object" => match serde_json::from_str::<Value>(trimmed) {
+                Ok(obj @ Value::Object(_)) => Some(obj),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(coerced) = coerced {
+            return Ok(Some(coerced));
+        }
+    }
+    Err(format!("expected {}, got {raw:?}", types.join(" | ")))
+}
+
+fn parse_bool(raw: &str) -> Option<bool> {
+    match raw.to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Some(true),
+        "false" | "0" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Arrays come either as a JSON literal or as a comma-separated list whose
+/// items are coerced against `items`.
+fn coerce_array(raw: &str, schema: &Value, root: &Value) -> Result<Value, String> {
+    if raw.starts_with('[') {
+        return match serde_json::from_str::<Value>(raw) {
+            Ok(array @ Value::Array(_)) => Ok(array),
+            Ok(_) | Err(_) => Err(format!("expected a JSON array, got {raw:?}")),
+        };
+    }
+    if raw.is_empty() {
+        return Ok(Value::Array(Vec::new()));
+    }
+    let items = variants(schema, root)
+        .into_iter()
+        .find_map(|variant| variant.get("items"));
+    let mut out = Vec::new();
+    for (idx, part) in raw.split(',').map(str::trim).enumerate() {
+        let item_schema = match items {
+            Some(Value::Array(tuple)) => tuple.get(idx),
+            other => other,
+        };
+        let item = match item_schema {
+            Some(item_schema) => coerce_str(part, item_schema, root)
+                .map_err(|msg| format!("item {idx}: {msg}"))?
+                .unwrap_or_else(|| Value::String(part.to_string())),
+            None => Value::String(part.to_string()),
+        };
+        out.push(item);
+    }
+    Ok(Value::Array(out))
+}
+
+fn declared_types<'a>(schema: &'a Value, root: &'a Value) -> Vec<&'a str> {
+    let mut types = Vec::new();
+    for variant in variants(schema, root) {
+        match variant.get("type") {
+            Some(Value::String(ty)) => types.push(ty.as_str()),
+            Some(Value::Array(list)) => types.extend(list.iter().filter_map(Value::as_str)),
+            _ => {
+                if variant.get("enum").is_some() || variant.get("const").is_some() {
+                    types.push("string");
+                } else if variant.get("properties").is_some() {
+                    types.push("object");
+                }
+            }
+        }
+    }
+    types.dedup();
+    types
+}
@@ -0,0 +1,467 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use sb_types::prelude::{Subject, SubjectKind};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::errors::{self, ConfigError};
+use crate::model::{Checksum, KeyPath, NamespaceId};
+use crate::schema::{SchemaDoc, SchemaRegistry};
+use crate::switch::SnapshotSwitch;
+use crate::tenant::TenantView;
+
+/// Namespace holding flag definitions, one key per flag.
+pub const FLAGS_NAMESPACE: &str = "flags";
+
+// Parsed flag sets kept by `FlagEngine`, keyed by effective checksum.
+const FLAG_SET_CACHE: usize = 64;
+
+/// A flag as written in config. `flags.<name>: true` is shorthand for an
+/// enabled boolean flag without rules.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+enum FlagSpec {
+    Toggle(bool),
+    Full(FlagDefinition),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FlagDefinition {
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+    /// Named values; boolean flags may omit them and get `on`/`off`.
+    #[serde(default)]
+    pub variants: BTreeMap<String, Value>,
+    /// Served when no rule matches.
+    #[serde(default = "on_variant")]
+    pub fallthrough: String,
+    /// Served while the flag is disabled.
+    #[serde(default = "off_variant")]
+    pub off_variant: String,
+    /// Checked in order; the first match decides.
+    #[serde(default)]
+    pub rules: Vec<FlagRule>,
+}
+
+/// Targeting conditions (all must hold; empty lists match everyone) and the
+/// outcome: a fixed variant or a percentage rollout.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FlagRule {
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub tenants: Vec<String>,
+    #[serde(default)]
+    pub subject_kinds: Vec<SubjectKind>,
+    /// Claim name to accepted values; array claims match on any element.
+    #[serde(default)]
+    pub claims: BTreeMap<String, Vec<Value>>,
+    #[serde(default)]
+    pub window: Option<TimeWindow>,
+    #[serde(default)]
+    pub variant: Option<String>,
+    #[serde(default)]
+    pub rollout: Option<Rollout>,
+}
+
+/// Half-open `[start, end)`; either bound may be left out.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TimeWindow {
+    #[serde(default)]
+    pub start: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub end: Option<DateTime<Utc>>,
+}
+
+/// Splits subjects into stable buckets. Shares that add up to less than 100
+/// let the remaining subjects fall through to the next rule.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rollout {
+    #[serde(default)]
+    pub bucket_by: BucketBy,
+    pub variants: Vec<WeightedVariant>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BucketBy {
+    #[default]
+    Subject,
+    Tenant,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WeightedVariant {
+    pub variant: String,
+    pub percent: f64,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+fn on_variant() -> String {
+    "on".to_string()
+}
+
+fn off_variant() -> String {
+    "off".to_string()
+}
+
+/// Why a flag evaluated the way it did.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EvalReason {
+    /// No such flag; the caller's default was returned.
+    FlagMissing,
+    Disabled,
+    RuleMatch {
+        rule: usize,
+        id: Option<String>,
+    },
+    /// `bucket` is in `0..10_000` (hundredths of a percent).
+    Rollout {
+        rule: usize,
+        id: Option<String>,
+        bucket: u32,
+    },
+    Fallthrough,
+    /// The definition is unusable; the caller's default was returned.
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Evaluation {
+    pub flag: String,
+    pub value: Value,
+    pub variant: Option<String>,
+    pub reason: EvalReason,
+}
+
+/// Flag definitions read from one snapshot. Definitions that fail to parse
+/// evaluate to the caller's default with an `Error` reason.
+#[derive(Debug, Default)]
+pub struct FlagSet {
+    flags: HashMap<String, Result<FlagDefinition, String>>,
+}
+
+impl FlagSet {
+    pub fn from_view(view: &TenantView<'_>) -> Self {
+        let Some(raw) = view.get_raw(&KeyPath(FLAGS_NAMESPACE.to_string())) else {
+            return Self::default();
+        };
+        let Value::Object(map) = raw.into_owned() else {
+            return Self::default();
+        };
+        let flags = map
+            .into_iter()
+            .map(|(name, spec)| {
+                let parsed = serde_json::from_value(spec)
+                    .map_err(|err| err.to_string())
+                    .map(|spec| match spec {
+                        FlagSpec::Toggle(enabled) => FlagDefinition {
+                            enabled,
+                            variants: BTreeMap::new(),
+                            fallthrough: on_variant(),
+                            off_variant: off_variant(),
+                            rules: Vec::new(),
+                        },
+                        FlagSpec::Full(definition) => definition,
+                    });
+                (name, parsed)
+            })
+            .collect();
+        Self { flags }
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.flags.keys().map(String::as_str).collect()
+    }
+
+    pub fn evaluate(
+        &self,
+        flag: &str,
+        subject: &Subject,
+        default: Value,
+        now: DateTime<Utc>,
+    ) -> Evaluation {
+        let outcome = match self.flags.get(flag) {
+            None => Err(EvalReason::FlagMissing),
+            Some(Err(message)) => Err(EvalReason::Error {
+                message: message.clone(),
+            }),
+            Some(Ok(definition)) => {
+                decide(flag, definition, subject, now).and_then(|(variant, reason)| {
+                    match variant_value(definition, &variant) {
+                        Some(value) => Ok((variant, value, reason)),
+                        None => Err(EvalReason::Error {
+                            message: format!("unknown variant `{variant}`"),
+                        }),
+                    }
+                })
+            }
+        };
+        match outcome {
+            Ok((variant, value, reason)) => Evaluation {
+                flag: flag.to_string(),
+                value,
+                variant: Some(variant),
+                reason,
+            },
+            Err(reason) => Evaluation {
+                flag: flag.to_string(),
+                value: default,
+                variant: None,
+                reason,
+            },
+        }
+    }
+}
+
+fn decide(
+    flag: &str,
+    definition: &FlagDefinition,
+    subject: &Subject,
+    now: DateTime<Utc>,
+) -> Result<(String, EvalReason), EvalReason> {
+    if !definition.enabled {
+        return Ok((definition.off_variant.clone(), EvalReason::Disabled));
+    }
+    for (index, rule) in definition.rules.iter().enumerate() {
+        if !rule_targets(rule, subject, now) {
+            continue;
+        }
+        match (&rule.variant, &rule.rollout) {
+            (Some(variant), None) => {
+                let reason = EvalReason::RuleMatch {
+                    rule: index,
+                    id: rule.id.clone(),
+                };
+                return Ok((variant.clone(), reason));
+            }
+            (None, Some(rollout)) => {
+                let bucket = bucket(flag, rollout.bucket_by, subject);
+                if let Some(variant) = pick(rollout, bucket) {
+                    let reason = EvalReason::Rollout {
+                        rule: index,
+                        id: rule.id.clone(),
+                        bucket,
+                    };
+                    return Ok((variant.to_string(), reason));
+                }
+            }
+            _ => {
+                return Err(EvalReason::Error {
+                    message: format!("rule {index} needs exactly one of `variant` or `rollout`"),
+                })
+            }
+        }
+    }
+    Ok((definition.fallthrough.clone(), EvalReason::Fallthrough))
+}
+
+fn rule_targets(rule: &FlagRule, subject: &Subject, now: DateTime<Utc>) -> bool {
+    let in_window = rule.window.as_ref().is_none_or(|window| {
+        window.start.is_none_or(|start| now >= start) && window.end.is_none_or(|end| now < end)
+    });
+    let tenant_ok = rule.tenants.is_empty() || rule.tenants.contains(&subject.tenant.0);
+    let kind_ok = rule.subject_kinds.is_empty() || rule.subject_kinds.contains(&subject.kind);
+    let claims_ok = rule
+        .claims
+        .iter()
+        .all(|(claim, accepted)| match subject.claims.get(claim) {
+            Some(Value::Array(values)) => values.iter().any(|value| accepted.contains(value)),
+            Some(value) => accepted.contains(value),
+            None => false,
+        });
+    in_window && tenant_ok && kind_ok && claims_ok
+}
+
+/// Stable bucket in `0..10_000`, salted with the flag name so rollouts of
+/// different flags do not pick the same subjects.
+fn bucket(flag: &str, by: BucketBy, subject: &Subject) -> u32 {
+    let unit = match by {
+        BucketBy::Subject => format!("{}/{}", subject.tenant.0, subject.subject_id.0),
+        BucketBy::Tenant => subject.tenant.0.clone(),
+    };
+    let digest = Sha256::digest(format!("{flag}:{unit}").as_bytes());
+    let mut head = [0u8; 8];
+    head.copy_from_slice(&digest[..8]);
+    (u64::from_be_bytes(head) % 10_000) as u32
+}
+
+fn pick(rollout: &Rollout, bucket: u32) -> Option<&str> {
+    let mut upper = 0.0;
+    for weighted in &rollout.variants {
+        upper += weighted.percent * 100.0;
+        if f64::from(bucket) < upper {
+            return Some(&weighted.variant);
+        }
+    }
+    None
+}
+
+fn variant_value(definition: &FlagDefinition, variant: &str) -> Option<Value> {
+    if let Some(value) = definition.variants.get(variant) {
+        return Some(value.clone());
+    }
+    match (definition.variants.is_empty(), variant) {
+        (true, "on") => Some(Value::Bool(true)),
+        (true, "off") => Some(Value::Bool(false)),
+        _ => None,
+    }
+}
+
+/// Evaluates flags against the live snapshot of a [`SnapshotSwitch`], so flag
+/// edits arrive through the normal reload path. Each subject sees its
+/// tenant's canary and overlay.
+pub struct FlagEngine {
+    switch: Arc<SnapshotSwitch>,
+    sets: Mutex<HashMap<Checksum, Arc<FlagSet>>>,
+}
+
+impl FlagEngine {
+    pub fn new(switch: Arc<SnapshotSwitch>) -> Self {
+        Self {
+            switch,
+            sets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn evaluate(&self, flag: &str, subject: &Subject, default: Value) -> Evaluation {
+        self.evaluate_at(flag, subject, default, Utc::now())
+    }
+
+    pub fn evaluate_at(
+        &self,
+        flag: &str,
+        subject: &Subject,
+        default: Value,
+        now: DateTime<Utc>,
+    ) -> Evaluation {
+        self.flag_set(subject).evaluate(flag, subject, default, now)
+    }
+
+    /// Boolean flags; anything that is not `true` counts as off.
+    pub fn is_enabled(&self, flag: &str, subject: &Subject) -> bool {
+        self.evaluate(flag, subject, Value::Bool(false)).value == Value::Bool(true)
+    }
+
+    /// The flag's value as `T`, or `default` when it is missing or does not
+    /// deserialize.
+    pub fn variation<T: DeserializeOwned>(&self, flag: &str, subject: &Subject, default: T) -> T {
+        serde_json::from_value(self.evaluate(flag, subject, Value::Null).value).unwrap_or(default)
+    }
+
+    fn flag_set(&self, subject: &Subject) -> Arc<FlagSet> {
+        let snapshot = self.switch.get_for_tenant(&subject.tenant);
+        let view = snapshot.for_tenant(&subject.tenant);
+        let mut sets = self.sets.lock();
+        if let Some(set) = sets.get(view.checksum()) {
+            return set.clone();
+        }
+        if sets.len() >= FLAG_SET_CACHE {
+            sets.clear();
+        }
+        let set = Arc::new(FlagSet::from_view(&view));
+        sets.insert(view.checksum().clone(), set.clone());
+        set
+    }
+}
+
+/// Registers the `flags` namespace so flag definitions are accepted and
+/// checked against their schema on every load and reload.
+#[allow(clippy::result_large_err)]
+pub fn register_flags(registry: &dyn SchemaRegistry) -> Result<(), ConfigError> {
+    let schema: SchemaDoc = serde_json::from_value(flags_schema())
+        .map_err(|err| errors::schema_invalid("schema", &format!("flags: {err}")))?;
+    registry.register_namespace(
+        NamespaceId(FLAGS_NAMESPACE.to_string()),
+        Some(schema),
+        HashMap::new(),
+    )
+}
+
+fn flags_schema() -> Value {
+    let string_list = json!({ "type": "array", "items": { "type": "string" } });
+    json!({
+        "type": "object",
+        "additionalProperties": { "$ref": "#/definitions/Flag" },
+        "definitions": {
+            "Flag": {
+                "oneOf": [
+                    { "type": "boolean" },
+                    {
+                        "type": "object",
+                        "additionalProperties": false,
+                        "properties": {
+                            "enabled": { "type": "boolean" },
+                            "variants": { "type": "object" },
+                            "fallthrough": { "type": "string" },
+                            "off_variant": { "type": "string" },
+                            "rules": { "type": "array", "items": { "$ref": "#/definitions/Rule" } }
+                        }
+                    }
+                ]
+            },
+            "Rule": {
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "id": { "type": "string" },
+                    "tenants": string_list,
+                    "subject_kinds": {
+                        "type": "array",
+                        "items": { "enum": ["User", "Service", "Agent"] }
+                    },
+                    "claims": { "type": "object", "additionalProperties": { "type": "array" } },
+                    "window": {
+                        "type": "object",
+                        "additionalProperties": false,
+                        "properties": {
+                            "start": { "type": "string" },
+                            "end": { "type": "string" }
+                        }
+                    },
+                    "variant": { "type": "string" },
+                    "rollout": {
+                        "type": "object",
+                        "additionalProperties": false,
+                        "required": ["variants"],
+                        "properties": {
+                            "bucket_by": { "enum": ["subject", "tenant"] },
+                            "variants": {
+                                "type": "array",
+                                "items": {
+                                    "type": "object",
+                                    "additionalProperties": false,
+                                    "required": ["variant", "percent"],
+                                    "properties": {
+                                        "variant": { "type": "string" },
+                                        "percent": { "type": "number", "minimum": 0, "maximum": 100 }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                },
+                "oneOf": [
+                    { "required": ["variant"], "not": { "required": ["rollout"] } },
+                    { "required": ["rollout"], "not": { "required": ["variant"] } }
+                ]
+            }
+        }
+    })
+}
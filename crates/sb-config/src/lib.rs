@@ -1,6 +1,8 @@
 pub mod access;
+pub mod coerce;
 pub mod errors;
 pub mod events;
+pub mod flags;
 pub mod loader;
 pub mod model;
 pub mod observe;
@@ -10,5 +12,6 @@ pub mod secrets;
 pub mod snapshot;
 pub mod source;
 pub mod switch;
+pub mod tenant;
 pub mod validate;
 pub mod watch;
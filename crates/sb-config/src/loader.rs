@@ -1,22 +1,25 @@
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine as _;
 use sb_errors::prelude::codes;
-use sb_types::prelude::Timestamp;
+use sb_types::prelude::{TenantId, Timestamp};
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::errors::ConfigError;
-use crate::events::ConfigUpdateEvent;
+use crate::coerce::{coerce_keys, coerce_strings};
+use crate::errors::{self, ConfigError};
+use crate::events::{ConfigErrorEvent, ConfigUpdateEvent};
+use crate::flags::FLAGS_NAMESPACE;
 use crate::model::{
-    Checksum, ConfigMap, ConfigValue, KeyPath, NamespaceId, ReloadClass, SnapshotMetadata,
-    SnapshotVersion,
+    Checksum, ConfigMap, ConfigValue, KeyPath, KeyProvenance, NamespaceId, ProvenanceEntry,
+    ReloadClass, SnapshotMetadata, SnapshotVersion, ValueOrigin,
 };
-use crate::schema::SchemaRegistry;
-use crate::secrets::SecretResolver;
+use crate::schema::{is_open_map, node_at, FieldMeta, SchemaRegistry};
+use crate::secrets::{reference_keys, SecretResolver};
 use crate::snapshot::ConfigSnapshot;
-use crate::source::{merge_maps, merge_value, Source};
-use crate::validate::Validator;
+use crate::source::{merge_maps, merge_value, Source, SourceSnapshot};
+use crate::tenant::{TenantOverlay, TenantOverlayMap, TenantOverlays};
+use crate::validate::{validate_schemas, Validator};
 
 #[derive(Clone)]
 pub struct Loader {
@@ -36,16 +39,52 @@ impl Loader {
         &self,
         previous: Option<&ConfigSnapshot>,
     ) -> Result<(ConfigSnapshot, ConfigUpdateEvent), ConfigError> {
+        let (snapshot, event, _) = self.load_snapshot(previous, None).await?;
+        Ok((snapshot, event))
+    }
+
+    /// Like [`load_with_prev`](Self::load_with_prev), with each tenant's
+    /// overlay from `overlays` validated and attached to the snapshot.
+    /// Tenants whose overlay is rejected keep their overlay from `previous`
+    /// (or get none) and are listed in the returned failures.
+    pub async fn load_with_overlays(
+        &self,
+        previous: Option<&ConfigSnapshot>,
+        overlays: &TenantOverlays,
+    ) -> Result<(ConfigSnapshot, ConfigUpdateEvent, Vec<ConfigErrorEvent>), ConfigError> {
+        self.load_snapshot(previous, Some(overlays)).await
+    }
+
+    pub(crate) async fn load_snapshot(
+        &self,
+        previous: Option<&ConfigSnapshot>,
+        overlays: Option<&TenantOverlays>,
+    ) -> Result<(ConfigSnapshot, ConfigUpdateEvent, Vec<ConfigErrorEvent>), ConfigError> {
         let mut merged = ConfigMap::new();
+        let mut provenance = KeyProvenance::new();
 
         for source in &self.sources {
-            let snap = source.load().await?;
+            let mut snap = source.load().await?;
+            if source.string_typed() {
+                coerce_strings(source.id(), &mut snap.map, self.schema_registry.as_ref())?;
+            } else if !snap.interpolated.is_empty() {
+                coerce_keys(
+                    source.id(),
+                    &mut snap.map,
+                    &snap.interpolated,
+                    self.schema_registry.as_ref(),
+                )?;
+            }
+            record_origins(&mut provenance, source.id(), &snap);
             merge_maps(&mut merged, &snap.map);
         }
 
         apply_defaults(&mut merged, self.schema_registry.as_ref());
+        record_defaults(&mut provenance, &merged);
         enforce_schema(&merged, self.schema_registry.as_ref())?;
+        validate_schemas(&merged, self.schema_registry.as_ref(), &provenance)?;
 
+        let mut secret_keys = reference_keys(&merged);
         let mut working = merged.clone();
         for resolver in &self.secrets {
             resolver.resolve(&mut working).await?;
@@ -60,6 +99,21 @@ impl Loader {
             .unwrap_or_default()
             .as_millis() as i64;
 
+        let (tenants, failures) = match overlays {
+            Some(overlays) => {
+                self.load_overlays(
+                    overlays,
+                    previous,
+                    &merged,
+                    &working,
+                    &provenance,
+                    &mut secret_keys,
+                )
+                .await?
+            }
+            None => (TenantOverlayMap::new(), Vec::new()),
+        };
+
         let checksum = compute_checksum(&working);
         let reload_summary = build_reload_summary(self.schema_registry.as_ref());
 
@@ -68,9 +122,12 @@ impl Loader {
             checksum: checksum.clone(),
             issued_at_epoch_ms: issued_at_ms,
             reload_summary,
+            sensitive_keys: build_sensitive_keys(self.schema_registry.as_ref(), secret_keys),
         };
 
-        let snapshot = ConfigSnapshot::new(working, metadata.clone());
+        let snapshot = ConfigSnapshot::new(working, metadata.clone())
+            .with_provenance(provenance)
+            .with_tenant_overlays(tenants);
         let changed = compute_changed_keys(previous, &snapshot);
 
         let event = ConfigUpdateEvent {
@@ -81,7 +138,161 @@ impl Loader {
             issued_at: Timestamp(issued_at_ms),
         };
 
-        Ok((snapshot, event))
+        Ok((snapshot, event, failures))
+    }
+
+    /// Checks every overlay against the whitelist and, merged over the
+    /// global tree, against the namespace schemas; then resolves its secrets
+    /// and fingerprints the tenant's effective tree.
+    ///
+    /// A tenant whose overlay fails keeps the overlay it had in `previous`
+    /// (or none) and is reported in the returned failures, so one bad
+    /// overlay never blocks the global reload.
+    async fn load_overlays(
+        &self,
+        overlays: &TenantOverlays,
+        previous: Option<&ConfigSnapshot>,
+        merged: &ConfigMap,
+        working: &ConfigMap,
+        provenance: &KeyProvenance,
+        secret_keys: &mut Vec<String>,
+    ) -> Result<(TenantOverlayMap, Vec<ConfigErrorEvent>), ConfigError> {
+        let store = overlays.store();
+        let mut tenants = TenantOverlayMap::new();
+        let mut failures = Vec::new();
+        for (tenant, overlay) in store.load_all().await? {
+            secret_keys.extend(reference_keys(&overlay));
+            let loaded = async {
+                let mut leaves = HashMap::new();
+                flatten_map(&overlay, "", &mut leaves);
+                let mut refused: Vec<&String> = leaves
+                    .keys()
+                    .filter(|key| !overlays.is_allowed(key))
+                    .collect();
+                if !refused.is_empty() {
+                    refused.sort();
+                    let keys: Vec<&str> = refused.iter().map(|key| key.as_str()).collect();
+                    return Err(errors::schema_invalid(
+                        "tenant",
+                        &format!("keys not open to tenant overrides: {}", keys.join(", ")),
+                    ));
+                }
+
+                let mut candidate = merged.clone();
+                merge_maps(&mut candidate, &overlay);
+                let mut tenant_provenance = provenance.clone();
+                for (key, value) in leaves {
+                    tenant_provenance
+                        .entry(KeyPath(key))
+                        .or_default()
+                        .push(ValueOrigin {
+                            source: format!("tenant:{}", tenant.0),
+                            path: Some(store.id().to_string()),
+                            layer: Some("tenant".to_string()),
+                            version: None,
+                            value,
+                        });
+                }
+                enforce_schema(&candidate, self.schema_registry.as_ref())?;
+                validate_schemas(
+                    &candidate,
+                    self.schema_registry.as_ref(),
+                    &tenant_provenance,
+                )?;
+
+                let mut resolved = overlay;
+                for resolver in &self.secrets {
+                    resolver.resolve(&mut resolved).await?;
+                }
+                let mut effective = working.clone();
+                merge_maps(&mut effective, &resolved);
+                self.validator
+                    .validate(&NamespaceId("root".to_string()), &effective)
+                    .await?;
+                Ok(TenantOverlay {
+                    checksum: compute_checksum(&effective),
+                    data: resolved,
+                })
+            }
+            .await;
+
+            match loaded {
+                Ok(loaded) => {
+                    tenants.insert(tenant, loaded);
+                }
+                Err(err) => {
+                    let err = for_tenant(&tenant, err);
+                    failures.push(ConfigErrorEvent {
+                        phase: "tenant".to_string(),
+                        message: err.0.message_dev.clone().unwrap_or_else(|| err.to_string()),
+                    });
+                    let kept = previous.and_then(|snap| snap.tenant_overlay(&tenant));
+                    if let Some(kept) = kept {
+                        let mut effective = working.clone();
+                        merge_maps(&mut effective, &kept.data);
+                        let kept = TenantOverlay {
+                            checksum: compute_checksum(&effective),
+                            data: kept.data.clone(),
+                        };
+                        tenants.insert(tenant, kept);
+                    }
+                }
+            }
+        }
+        Ok((tenants, failures))
+    }
+}
+
+fn for_tenant(tenant: &TenantId, mut err: ConfigError) -> ConfigError {
+    let detail = err.0.message_dev.take().unwrap_or_default();
+    err.0.message_dev = Some(format!("tenant {}: {detail}", tenant.0));
+    err
+}
+
+/// Appends each leaf of `snap` to its key's override chain, layer by layer.
+fn record_origins(provenance: &mut KeyProvenance, id: &str, snap: &SourceSnapshot) {
+    let entries = &snap.provenance.0;
+    let layers: Vec<(Option<&ProvenanceEntry>, &ConfigMap)> = if snap.layers.is_empty() {
+        vec![(entries.last(), &snap.map)]
+    } else {
+        snap.layers
+            .iter()
+            .enumerate()
+            .map(|(idx, layer)| (entries.get(idx), layer))
+            .collect()
+    };
+    for (entry, layer) in layers {
+        let mut leaves = HashMap::new();
+        flatten_map(layer, "", &mut leaves);
+        for (key, value) in leaves {
+            let origin = ValueOrigin {
+                source: id.to_string(),
+                path: entry
+                    .map(|entry| entry.source.clone())
+                    .filter(|path| path != id),
+                layer: entry.and_then(|entry| entry.layer.clone()),
+                version: entry.and_then(|entry| entry.version.clone()),
+                value,
+            };
+            provenance.entry(KeyPath(key)).or_default().push(origin);
+        }
+    }
+}
+
+/// Leaves nobody set were filled in by `apply_defaults`.
+fn record_defaults(provenance: &mut KeyProvenance, merged: &ConfigMap) {
+    let mut leaves = HashMap::new();
+    flatten_map(merged, "", &mut leaves);
+    for (key, value) in leaves {
+        provenance.entry(KeyPath(key)).or_insert_with(|| {
+            vec![ValueOrigin {
+                source: "default".to_string(),
+                path: None,
+                layer: Some("schema".to_string()),
+                version: None,
+                value,
+            }]
+        });
     }
 }
 
@@ -100,9 +311,11 @@ fn apply_defaults(map: &mut ConfigMap, registry: &dyn SchemaRegistry) {
     }
 }
 
+#[allow(clippy::result_large_err)]
 fn enforce_schema(map: &ConfigMap, registry: &dyn SchemaRegistry) -> Result<(), ConfigError> {
     let mut flattened = Vec::new();
     collect_keys("", map, &mut flattened);
+    let mut schemas: HashMap<String, Option<serde_json::Value>> = HashMap::new();
     for key in flattened {
         let (namespace, relative) = split_namespace(&key)?;
         let ns_id = NamespaceId(namespace.to_string());
@@ -120,7 +333,15 @@ fn enforce_schema(map: &ConfigMap, registry: &dyn SchemaRegistry) -> Result<(),
         }
 
         let field_key = KeyPath(relative.to_string());
-        if !view.fields.contains_key(&field_key) {
+        if view.fields.contains_key(&field_key) {
+            continue;
+        }
+        let schema = schemas.entry(namespace.to_string()).or_insert_with(|| {
+            view.schema
+                .as_ref()
+                .and_then(|doc| serde_json::to_value(doc).ok())
+        });
+        if !under_open_map(namespace, relative, &view.fields, schema.as_ref()) {
             return Err(ConfigError::from(
                 ConfigError::builder(codes::SCHEMA_VALIDATION_FAILED)
                     .user_msg("Configuration contains unknown key.")
@@ -132,6 +353,28 @@ fn enforce_schema(map: &ConfigMap, registry: &dyn SchemaRegistry) -> Result<(),
     Ok(())
 }
 
+/// Keys below a registered map-typed field, or anywhere in the map-typed
+/// flags namespace, are free-form; the namespace schema still validates their
+/// values.
+fn under_open_map(
+    namespace: &str,
+    relative: &str,
+    fields: &HashMap<KeyPath, FieldMeta>,
+    schema: Option<&serde_json::Value>,
+) -> bool {
+    let Some(root) = schema else {
+        return false;
+    };
+    let mut path = relative;
+    while let Some(idx) = path.rfind('.') {
+        path = &path[..idx];
+        if fields.contains_key(&KeyPath(path.to_string())) {
+            return node_at(root, path).is_some_and(|node| is_open_map(node, root));
+        }
+    }
+    namespace == FLAGS_NAMESPACE && is_open_map(root, root)
+}
+
 fn compute_checksum(map: &ConfigMap) -> Checksum {
     use sha2::{Digest, Sha256};
 
@@ -182,6 +425,7 @@ fn collect_keys(prefix: &str, map: &ConfigMap, out: &mut Vec<String>) {
     }
 }
 
+#[allow(clippy::result_large_err)]
 fn split_namespace(path: &str) -> Result<(&str, &str), ConfigError> {
     let mut parts = path.splitn(2, '.');
     let ns = parts.next().unwrap_or("");
@@ -210,6 +454,24 @@ fn build_reload_summary(registry: &dyn SchemaRegistry) -> HashMap<KeyPath, Reloa
     summary
 }
 
+/// Registered sensitive fields plus every key that held a secret reference.
+fn build_sensitive_keys(
+    registry: &dyn SchemaRegistry,
+    secret_keys: Vec<String>,
+) -> HashSet<KeyPath> {
+    let mut keys: HashSet<KeyPath> = secret_keys.into_iter().map(KeyPath).collect();
+    for ns in registry.namespaces() {
+        if let Some(view) = registry.get_namespace(&ns) {
+            for (key, meta) in view.fields {
+                if meta.sensitive {
+                    keys.insert(KeyPath(compose_full_path(&ns, &key)));
+                }
+            }
+        }
+    }
+    keys
+}
+
 fn flatten_snapshot(snapshot: &ConfigSnapshot) -> HashMap<String, ConfigValue> {
     let mut map = HashMap::new();
     if let ConfigValue::Object(root) = snapshot.root_value() {
@@ -234,22 +496,44 @@ fn flatten_map(value: &ConfigMap, prefix: &str, out: &mut HashMap<String, Config
     }
 }
 
+/// Keys whose value changed in the global tree or in any tenant overlay, so
+/// that an edit to an overlay alone still counts as a change.
 fn compute_changed_keys(prev: Option<&ConfigSnapshot>, current: &ConfigSnapshot) -> Vec<KeyPath> {
     let current_flat = flatten_snapshot(current);
     let prev_flat = prev.map(flatten_snapshot).unwrap_or_default();
+    let mut changed = HashSet::new();
+    diff_flat(&prev_flat, &current_flat, &mut changed);
 
-    let mut keys = HashSet::new();
-    keys.extend(current_flat.keys().cloned());
-    keys.extend(prev_flat.keys().cloned());
-
-    let mut changed = Vec::new();
-    for key in keys {
-        match (prev_flat.get(&key), current_flat.get(&key)) {
-            (Some(a), Some(b)) if a == b => {}
-            (None, None) => {}
-            _ => changed.push(KeyPath(key)),
-        }
+    let mut tenants: HashSet<&TenantId> = current.overlay_tenants().into_iter().collect();
+    tenants.extend(
+        prev.map(ConfigSnapshot::overlay_tenants)
+            .unwrap_or_default(),
+    );
+    for tenant in tenants {
+        let flatten_overlay = |snapshot: &ConfigSnapshot| {
+            let mut out = HashMap::new();
+            if let Some(overlay) = snapshot.tenant_overlay(tenant) {
+                flatten_map(&overlay.data, "", &mut out);
+            }
+            out
+        };
+        let prev_overlay = prev.map(flatten_overlay).unwrap_or_default();
+        diff_flat(&prev_overlay, &flatten_overlay(current), &mut changed);
     }
+
+    let mut changed: Vec<KeyPath> = changed.into_iter().map(KeyPath).collect();
     changed.sort_by(|a, b| a.0.cmp(&b.0));
     changed
 }
+
+fn diff_flat(
+    prev: &HashMap<String, ConfigValue>,
+    current: &HashMap<String, ConfigValue>,
+    changed: &mut HashSet<String>,
+) {
+    for key in prev.keys().chain(current.keys()) {
+        if prev.get(key) != current.get(key) {
+            changed.insert(key.clone());
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct NamespaceId(pub String);
@@ -39,10 +39,38 @@ pub struct ProvenanceEntry {
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Provenance(pub Vec<ProvenanceEntry>);
 
+/// One layer that supplied a value for a key.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ValueOrigin {
+    /// Source id (`file`, `env`, `cli`, ...) or `default` for schema defaults.
+    pub source: String,
+    /// File path or other locator within the source, when it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    pub value: ConfigValue,
+}
+
+impl ValueOrigin {
+    /// `source` or `source:path`, as used in error messages.
+    pub fn label(&self) -> String {
+        match &self.path {
+            Some(path) => format!("{}:{}", self.source, path),
+            None => self.source.clone(),
+        }
+    }
+}
+
+/// Override chain per leaf key, oldest layer first.
+pub type KeyProvenance = HashMap<KeyPath, Vec<ValueOrigin>>;
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub struct SnapshotVersion(pub String);
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
 pub struct Checksum(pub String);
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
@@ -51,4 +79,8 @@ pub struct SnapshotMetadata {
     pub checksum: Checksum,
     pub issued_at_epoch_ms: i64,
     pub reload_summary: HashMap<KeyPath, ReloadClass>,
+    /// Registered keys marked `FieldMeta.sensitive`; everything below them is
+    /// sensitive too.
+    #[serde(default)]
+    pub sensitive_keys: HashSet<KeyPath>,
 }
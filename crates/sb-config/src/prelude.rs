@@ -1,9 +1,43 @@
-pub use crate::access::{feature_flag, namespace_view};
+pub use crate::access::{feature_flag, namespace_view, tenant_namespace_view};
 pub use crate::errors::ConfigError;
+pub use crate::events::{ConfigErrorEvent, ConfigUpdateEvent};
+pub use crate::flags::{
+    register_flags, BucketBy, EvalReason, Evaluation, FlagDefinition, FlagEngine, FlagRule,
+    FlagSet, Rollout, TimeWindow, WeightedVariant, FLAGS_NAMESPACE,
+};
 pub use crate::loader::Loader;
-pub use crate::model::{Checksum, KeyPath, NamespaceId, ReloadClass, SnapshotVersion};
-pub use crate::schema::{FieldMeta, InMemorySchemaRegistry, SchemaRegistry};
-pub use crate::secrets::{NoopSecretResolver, SecretResolver};
-pub use crate::snapshot::ConfigSnapshot;
+pub use crate::model::{
+    Checksum, KeyPath, KeyProvenance, NamespaceId, ReloadClass, SnapshotVersion, ValueOrigin,
+};
+#[cfg(feature = "schema_json")]
+pub use crate::schema::{register_config, ConfigNamespace};
+pub use crate::schema::{FieldAnnotation, FieldMeta, InMemorySchemaRegistry, SchemaRegistry};
+#[cfg(feature = "secrets_file")]
+pub use crate::secrets::file::EncryptedFileProvider;
+#[cfg(feature = "secrets_vault")]
+pub use crate::secrets::vault::{KvVersion, VaultKvProvider};
+pub use crate::secrets::{
+    NoopSecretResolver, ReferenceResolver, SecretProvider, SecretRef, SecretResolver, SecretValue,
+};
+pub use crate::snapshot::{ConfigSnapshot, Explanation, REDACTED};
+#[cfg(feature = "remote_consul")]
+pub use crate::source::consul::ConsulSource;
+#[cfg(feature = "remote_etcd")]
+pub use crate::source::etcd::EtcdSource;
+#[cfg(feature = "remote_git")]
+pub use crate::source::git::{GitCredentials, GitSource};
 pub use crate::source::{cli::CliArgsSource, env::EnvSource, file::FileSource, Source};
-pub use crate::validate::{BasicValidator, Validator};
+pub use crate::switch::{HealthOutcome, SnapshotRecord, SnapshotSwitch};
+#[cfg(feature = "tenant_storage")]
+pub use crate::tenant::storage::{StorageOverlayStore, TenantOverlayDoc};
+pub use crate::tenant::{FileOverlayStore, OverlayStore, TenantOverlays, TenantView};
+pub use crate::validate::{BasicValidator, SchemaViolation, Validator};
+#[cfg(feature = "watch_fs")]
+pub use crate::watch::fs::{FsWatcher, WatchBackend};
+#[cfg(any(
+    feature = "remote_consul",
+    feature = "remote_etcd",
+    feature = "remote_git"
+))]
+pub use crate::watch::remote::{ChangeFeed, RemoteWatcher};
+pub use crate::watch::{ConfigChange, NoopWatcher, ReloadOutcome, Reloader, Watcher};
@@ -29,6 +29,7 @@ pub struct NamespaceView {
 }
 
 pub trait SchemaRegistry: Send + Sync {
+    #[allow(clippy::result_large_err)]
     fn register_namespace(
         &self,
         namespace: NamespaceId,
@@ -86,3 +87,197 @@ impl SchemaRegistry for InMemorySchemaRegistry {
         guard.get(ns).and_then(|view| view.fields.get(key)).cloned()
     }
 }
+
+/// Per-field annotations for [`ConfigNamespace`]. They apply to the key and
+/// everything below it unless a deeper annotation overrides them.
+#[derive(Clone, Debug)]
+pub struct FieldAnnotation {
+    pub key: &'static str,
+    pub reload: Option<ReloadClass>,
+    pub sensitive: bool,
+}
+
+impl FieldAnnotation {
+    pub fn new(key: &'static str) -> Self {
+        Self {
+            key,
+            reload: None,
+            sensitive: false,
+        }
+    }
+
+    pub fn reload(mut self, class: ReloadClass) -> Self {
+        self.reload = Some(class);
+        self
+    }
+
+    pub fn sensitive(mut self) -> Self {
+        self.sensitive = true;
+        self
+    }
+}
+
+/// A Rust config struct that owns a namespace. Its `JsonSchema` becomes the
+/// namespace `SchemaDoc`; doc comments and serde defaults become field
+/// descriptions and defaults.
+#[cfg(feature = "schema_json")]
+pub trait ConfigNamespace: schemars::JsonSchema {
+    const NAMESPACE: &'static str;
+    /// Reload class of fields without an annotation.
+    const RELOAD: ReloadClass = ReloadClass::HotReloadSafe;
+
+    fn annotations() -> Vec<FieldAnnotation> {
+        Vec::new()
+    }
+}
+
+/// Registers `T`'s schema and one `FieldMeta` per (nested) property.
+#[cfg(feature = "schema_json")]
+#[allow(clippy::result_large_err)]
+pub fn register_config<T: ConfigNamespace>(
+    registry: &dyn SchemaRegistry,
+) -> Result<(), ConfigError> {
+    let schema = schemars::schema_for!(T);
+    let root = serde_json::to_value(&schema)
+        .map_err(|err| crate::errors::schema_invalid("schema", &err.to_string()))?;
+    let mut fields = HashMap::new();
+    collect_fields(&root, &root, "", &mut fields);
+
+    let annotations = T::annotations();
+    if let Some(unknown) = annotations
+        .iter()
+        .find(|note| !fields.contains_key(&KeyPath(note.key.to_string())))
+    {
+        return Err(crate::errors::schema_invalid(
+            "schema",
+            &format!(
+                "{}: annotated key `{}` is not a field",
+                T::NAMESPACE,
+                unknown.key
+            ),
+        ));
+    }
+    for (key, meta) in fields.iter_mut() {
+        meta.reload = T::RELOAD;
+        // Shallowest first, so deeper annotations win.
+        let mut applicable: Vec<&FieldAnnotation> = annotations
+            .iter()
+            .filter(|note| is_self_or_ancestor(note.key, &key.0))
+            .collect();
+        applicable.sort_by_key(|note| note.key.len());
+        for note in applicable {
+            if let Some(class) = note.reload {
+                meta.reload = class;
+            }
+            meta.sensitive |= note.sensitive;
+        }
+    }
+
+    registry.register_namespace(NamespaceId(T::NAMESPACE.to_string()), Some(schema), fields)
+}
+
+#[cfg(feature = "schema_json")]
+fn is_self_or_ancestor(candidate: &str, key: &str) -> bool {
+    key == candidate
+        || key
+            .strip_prefix(candidate)
+            .is_some_and(|rest| rest.starts_with('.'))
+}
+
+#[cfg(feature = "schema_json")]
+fn collect_fields(node: &Value, root: &Value, prefix: &str, out: &mut HashMap<KeyPath, FieldMeta>) {
+    for variant in variants(node, root) {
+        let Some(Value::Object(properties)) = variant.get("properties") else {
+            continue;
+        };
+        for (name, child) in properties {
+            let key = if prefix.is_empty() {
+                name.clone()
+            } else {
+                format!("{prefix}.{name}")
+            };
+            if out.contains_key(&KeyPath(key.clone())) {
+                continue;
+            }
+            // schemars keeps `default`/`description` beside a `$ref`, so read
+            // them from the property before resolving it.
+            let lookup = |field: &str| {
+                child
+                    .get(field)
+                    .or_else(|| variants(child, root).into_iter().find_map(|v| v.get(field)))
+                    .cloned()
+            };
+            out.insert(
+                KeyPath(key.clone()),
+                FieldMeta {
+                    reload: ReloadClass::HotReloadSafe,
+                    sensitive: false,
+                    default_value: lookup("default"),
+                    description: lookup("description").and_then(|v| v.as_str().map(str::to_string)),
+                },
+            );
+            collect_fields(child, root, &key, out);
+        }
+    }
+}
+
+pub(crate) fn resolve<'a>(mut schema: &'a Value, root: &'a Value) -> &'a Value {
+    // Bounded to guard against self-referencing definitions.
+    for _ in 0..16 {
+        let Some(target) = schema
+            .get("$ref")
+            .and_then(Value::as_str)
+            .and_then(|r| r.strip_prefix('#'))
+            .and_then(|pointer| root.pointer(pointer))
+        else {
+            break;
+        };
+        schema = target;
+    }
+    schema
+}
+
+/// The schema itself plus the members of any `anyOf`/`oneOf`/`allOf`, with
+/// references resolved. schemars emits `anyOf` for `Option<Struct>` fields.
+pub(crate) fn variants<'a>(schema: &'a Value, root: &'a Value) -> Vec<&'a Value> {
+    let resolved = resolve(schema, root);
+    let mut out = vec![resolved];
+    for key in ["anyOf", "oneOf", "allOf"] {
+        if let Some(Value::Array(members)) = resolved.get(key) {
+            out.extend(members.iter().map(|member| resolve(member, root)));
+        }
+    }
+    out
+}
+
+pub(crate) fn child_schema<'a>(schema: &'a Value, root: &'a Value, key: &str) -> Option<&'a Value> {
+    variants(schema, root).into_iter().find_map(|variant| {
+        variant
+            .get("properties")
+            .and_then(|props| props.get(key))
+            .or_else(|| {
+                variant
+                    .get("additionalProperties")
+                    .filter(|v| v.is_object())
+            })
+    })
+}
+
+/// Schema node for a dotted path relative to the namespace root.
+pub(crate) fn node_at<'a>(root: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .filter(|segment| !segment.is_empty())
+        .try_fold(root, |node, segment| child_schema(node, root, segment))
+}
+
+/// Whether `node` is an object with free-form keys (a map), so keys below it
+/// cannot be registered one by one.
+pub(crate) fn is_open_map(node: &Value, root: &Value) -> bool {
+    variants(node, root).into_iter().any(|variant| {
+        variant
+            .get("additionalProperties")
+            .is_some_and(Value::is_object)
+            || (variant.get("type").and_then(Value::as_str) == Some("object")
+                && variant.get("properties").is_none())
+    })
+}
@@ -0,0 +1,159 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{self, ConfigError};
+use crate::model::ConfigMap;
+
+use super::{SecretProvider, SecretValue};
+
+const FORMAT_VERSION: u32 = 1;
+// Binds the ciphertext to this format so it cannot be replayed elsewhere.
+const AAD: &[u8] = b"sb-config/secrets/v1";
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    version: u32,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Offline secret store: a JSON object of `path -> secret` sealed with
+/// AES-256-GCM into a single file. Referenced as `secret://file/<path>#field`.
+///
+/// The file is re-read on every fetch, so rotating a secret is a matter of
+/// re-sealing the file; `ReferenceResolver` decides how often to fetch.
+pub struct EncryptedFileProvider {
+    path: PathBuf,
+    key: [u8; 32],
+}
+
+impl fmt::Debug for EncryptedFileProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptedFileProvider")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EncryptedFileProvider {
+    pub fn new(path: impl Into<PathBuf>, key: [u8; 32]) -> Self {
+        Self {
+            path: path.into(),
+            key,
+        }
+    }
+
+    /// Key given as base64, e.g. from an environment variable.
+    #[allow(clippy::result_large_err)]
+    pub fn from_base64_key(path: impl Into<PathBuf>, key: &str) -> Result<Self, ConfigError> {
+        let bytes = STANDARD
+            .decode(key.trim())
+            .map_err(|err| errors::schema_invalid("secrets", &format!("file key: {err}")))?;
+        let key: [u8; 32] = bytes.try_into().map_err(|_| {
+            errors::schema_invalid("secrets", "file key must be 32 bytes (AES-256)")
+        })?;
+        Ok(Self::new(path, key))
+    }
+
+    /// A fresh random key, base64-encoded.
+    pub fn generate_key() -> String {
+        STANDARD.encode(Aes256Gcm::generate_key(&mut OsRng))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Encrypts `secrets` and atomically replaces the store file.
+    #[allow(clippy::result_large_err)]
+    pub fn seal(&self, secrets: &ConfigMap) -> Result<(), ConfigError> {
+        let plaintext = serde_json::to_vec(secrets)
+            .map_err(|err| errors::schema_invalid("secrets", &err.to_string()))?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher()
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: &plaintext,
+                    aad: AAD,
+                },
+            )
+            .map_err(|_| errors::schema_invalid("secrets", "encryption failed"))?;
+        let envelope = Envelope {
+            version: FORMAT_VERSION,
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        };
+        let body = serde_json::to_vec_pretty(&envelope)
+            .map_err(|err| errors::schema_invalid("secrets", &err.to_string()))?;
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, body)
+            .and_then(|_| std::fs::rename(&tmp, &self.path))
+            .map_err(|err| self.io_error(err))
+    }
+
+    /// Decrypts the whole store.
+    #[allow(clippy::result_large_err)]
+    pub fn open(&self) -> Result<ConfigMap, ConfigError> {
+        let body = std::fs::read(&self.path).map_err(|err| self.io_error(err))?;
+        let envelope: Envelope = serde_json::from_slice(&body)
+            .map_err(|err| self.invalid(&format!("not a secret store: {err}")))?;
+        if envelope.version != FORMAT_VERSION {
+            return Err(self.invalid(&format!("unsupported version {}", envelope.version)));
+        }
+        let nonce = STANDARD
+            .decode(&envelope.nonce)
+            .ok()
+            .filter(|nonce| nonce.len() == 12)
+            .ok_or_else(|| self.invalid("bad nonce"))?;
+        let ciphertext = STANDARD
+            .decode(&envelope.ciphertext)
+            .map_err(|_| self.invalid("bad ciphertext encoding"))?;
+        let plaintext = self
+            .cipher()
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: AAD,
+                },
+            )
+            .map_err(|_| self.invalid("decryption failed (wrong key or tampered file)"))?;
+        serde_json::from_slice(&plaintext).map_err(|err| self.invalid(&err.to_string()))
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.key))
+    }
+
+    fn invalid(&self, detail: &str) -> ConfigError {
+        errors::schema_invalid("secrets", &format!("{}: {detail}", self.path.display()))
+    }
+
+    fn io_error(&self, err: std::io::Error) -> ConfigError {
+        errors::io_provider_unavailable("secrets", &format!("{}: {err}", self.path.display()))
+    }
+}
+
+#[async_trait]
+impl SecretProvider for EncryptedFileProvider {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    async fn fetch(&self, path: &str) -> Result<SecretValue, ConfigError> {
+        let mut store = self.open()?;
+        store
+            .remove(path)
+            .map(SecretValue::new)
+            .ok_or_else(|| self.invalid(&format!("no secret at `{path}`")))
+    }
+}
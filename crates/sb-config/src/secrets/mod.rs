@@ -1,8 +1,26 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
+use parking_lot::Mutex;
+use serde_json::Value;
 
-use crate::{errors::ConfigError, model::ConfigMap};
+use crate::{
+    errors::{self, ConfigError},
+    model::ConfigMap,
+    snapshot::REDACTED,
+};
+
+#[cfg(feature = "secrets_file")]
+pub mod file;
+#[cfg(feature = "secrets_vault")]
+pub mod vault;
+
+/// Prefix of secret references inside config values:
+/// `secret://<provider>/<path>[#field]`.
+pub const SECRET_SCHEME: &str = "secret://";
 
 #[async_trait]
 pub trait SecretResolver: Send + Sync {
@@ -17,6 +35,260 @@ pub struct NoopSecretResolver;
 #[async_trait]
 impl SecretResolver for NoopSecretResolver {}
 
-pub fn mark_sensitive(_map: &mut HashMap<String, String>) {
-    // 占位：预留敏感字段处理
+/// A parsed `secret://provider/path#field` reference.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SecretRef {
+    pub provider: String,
+    pub path: String,
+    pub field: Option<String>,
+}
+
+impl SecretRef {
+    /// `None` when `raw` is not a secret reference; an error when it is one
+    /// but malformed.
+    pub fn parse(raw: &str) -> Option<Result<Self, ConfigError>> {
+        let rest = raw.strip_prefix(SECRET_SCHEME)?;
+        let (location, field) = match rest.split_once('#') {
+            Some((location, field)) => (location, Some(field)),
+            None => (rest, None),
+        };
+        let parsed = match location.split_once('/') {
+            Some((provider, path))
+                if !provider.is_empty()
+                    && !path.is_empty()
+                    && field.is_none_or(|f| !f.is_empty()) =>
+            {
+                Ok(SecretRef {
+                    provider: provider.to_string(),
+                    path: path.to_string(),
+                    field: field.map(str::to_string),
+                })
+            }
+            _ => Err(errors::schema_invalid(
+                "secrets",
+                &format!("malformed secret reference `{raw}`"),
+            )),
+        };
+        Some(parsed)
+    }
+}
+
+impl fmt::Display for SecretRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{SECRET_SCHEME}{}/{}", self.provider, self.path)?;
+        if let Some(field) = &self.field {
+            write!(f, "#{field}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Secret material whose `Debug` never shows the value.
+#[derive(Clone)]
+pub struct SecretValue(Value);
+
+impl SecretValue {
+    pub fn new(value: Value) -> Self {
+        Self(value)
+    }
+
+    pub fn expose(&self) -> &Value {
+        &self.0
+    }
+}
+
+impl fmt::Debug for SecretValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretValue({REDACTED})")
+    }
+}
+
+/// Backend addressed by the `<provider>` part of a reference.
+#[async_trait]
+pub trait SecretProvider: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// The secret stored at `path`: a single value, or an object of fields
+    /// selected with `#field`.
+    async fn fetch(&self, path: &str) -> Result<SecretValue, ConfigError>;
+}
+
+struct CachedSecret {
+    value: SecretValue,
+    fetched_at: Instant,
+}
+
+/// Replaces `secret://` references with values from registered providers.
+///
+/// Fetched secrets are cached for `ttl`; after that the next load (e.g. a
+/// hot reload) fetches again, which is how rotated secrets are picked up.
+/// When a refresh fails, the expired value keeps being served so a flaky
+/// backend does not take configuration down with it.
+pub struct ReferenceResolver {
+    providers: HashMap<String, Arc<dyn SecretProvider>>,
+    ttl: Duration,
+    cache: Mutex<HashMap<(String, String), CachedSecret>>,
+}
+
+impl Default for ReferenceResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReferenceResolver {
+    pub fn new() -> Self {
+        Self {
+            providers: HashMap::new(),
+            ttl: Duration::from_secs(300),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_provider(mut self, provider: Arc<dyn SecretProvider>) -> Self {
+        self.providers.insert(provider.name().to_string(), provider);
+        self
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Forces the next load to refetch `path`, e.g. right after rotating it.
+    pub fn invalidate(&self, provider: &str, path: &str) {
+        self.cache
+            .lock()
+            .remove(&(provider.to_string(), path.to_string()));
+    }
+
+    pub fn invalidate_all(&self) {
+        self.cache.lock().clear();
+    }
+
+    async fn fetch(&self, key: &str, reference: &SecretRef) -> Result<SecretValue, ConfigError> {
+        let cache_key = (reference.provider.clone(), reference.path.clone());
+        let stale = {
+            let cache = self.cache.lock();
+            match cache.get(&cache_key) {
+                Some(hit) if hit.fetched_at.elapsed() < self.ttl => return Ok(hit.value.clone()),
+                Some(hit) => Some(hit.value.clone()),
+                None => None,
+            }
+        };
+        let provider = self.providers.get(&reference.provider).ok_or_else(|| {
+            errors::schema_invalid(
+                "secrets",
+                &format!("{key}: unknown secret provider `{}`", reference.provider),
+            )
+        })?;
+        match (provider.fetch(&reference.path).await, stale) {
+            (Ok(value), _) => {
+                self.cache.lock().insert(
+                    cache_key,
+                    CachedSecret {
+                        value: value.clone(),
+                        fetched_at: Instant::now(),
+                    },
+                );
+                Ok(value)
+            }
+            (Err(_), Some(stale)) => Ok(stale),
+            (Err(err), None) => {
+                let detail = err.0.message_dev.clone().unwrap_or_else(|| err.to_string());
+                Err(ConfigError::from(
+                    ConfigError::builder(err.0.code)
+                        .user_msg("Configuration secret could not be resolved.")
+                        .dev_msg(format!("secrets: {key}: {reference}: {detail}"))
+                        .build(),
+                ))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl SecretResolver for ReferenceResolver {
+    async fn resolve(&self, map: &mut ConfigMap) -> Result<(), ConfigError> {
+        for key in reference_keys(map) {
+            let Some(Value::String(raw)) = slot_mut(map, &key) else {
+                continue;
+            };
+            let reference = match SecretRef::parse(raw) {
+                Some(parsed) => parsed?,
+                None => continue,
+            };
+            let secret = self.fetch(&key, &reference).await?;
+            let value = select_field(&key, &reference, secret.expose())?;
+            if let Some(slot) = slot_mut(map, &key) {
+                *slot = value;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn slot_mut<'a>(map: &'a mut ConfigMap, key: &str) -> Option<&'a mut Value> {
+    let (parents, leaf) = match key.rsplit_once('.') {
+        Some((parents, leaf)) => (Some(parents), leaf),
+        None => (None, key),
+    };
+    let mut cursor = map;
+    for segment in parents.into_iter().flat_map(|p| p.split('.')) {
+        cursor = cursor.get_mut(segment)?.as_object_mut()?;
+    }
+    cursor.get_mut(leaf)
+}
+
+#[allow(clippy::result_large_err)]
+fn select_field(key: &str, reference: &SecretRef, secret: &Value) -> Result<Value, ConfigError> {
+    let Some(field) = &reference.field else {
+        return Ok(secret.clone());
+    };
+    secret.get(field).cloned().ok_or_else(|| {
+        errors::schema_invalid(
+            "secrets",
+            &format!("{key}: {reference} has no field `{field}`"),
+        )
+    })
+}
+
+/// Dotted keys whose value is a `secret://` reference.
+pub fn reference_keys(map: &ConfigMap) -> Vec<String> {
+    fn walk(prefix: &str, map: &ConfigMap, out: &mut Vec<String>) {
+        for (key, value) in map {
+            let path = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{prefix}.{key}")
+            };
+            match value {
+                Value::Object(child) => walk(&path, child, out),
+                Value::String(raw) if raw.starts_with(SECRET_SCHEME) => out.push(path),
+                _ => {}
+            }
+        }
+    }
+    let mut out = Vec::new();
+    walk("", map, &mut out);
+    out
+}
+
+/// Redacts entries of a flat string map (labels, log fields) whose key names
+/// a credential or whose value is a secret reference.
+pub fn mark_sensitive(map: &mut HashMap<String, String>) {
+    const MARKERS: [&str; 6] = [
+        "password",
+        "passwd",
+        "secret",
+        "token",
+        "credential",
+        "private_key",
+    ];
+    for (key, value) in map.iter_mut() {
+        let name = key.rsplit('.').next().unwrap_or(key).to_ascii_lowercase();
+        if value.starts_with(SECRET_SCHEME) || MARKERS.iter().any(|m| name.contains(m)) {
+            *value = REDACTED.to_string();
+        }
+    }
 }
@@ -0,0 +1,138 @@
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde_json::Value;
+
+use crate::errors::{self, ConfigError};
+
+use super::{SecretProvider, SecretValue};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KvVersion {
+    V1,
+    V2,
+}
+
+/// HashiCorp Vault KV secrets engine, referenced as
+/// `secret://vault/<path>#field`. Reads the latest version of the secret.
+pub struct VaultKvProvider {
+    addr: String,
+    token: String,
+    mount: String,
+    version: KvVersion,
+    namespace: Option<String>,
+    client: reqwest::Client,
+}
+
+impl std::fmt::Debug for VaultKvProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VaultKvProvider")
+            .field("addr", &self.addr)
+            .field("mount", &self.mount)
+            .field("version", &self.version)
+            .finish_non_exhaustive()
+    }
+}
+
+impl VaultKvProvider {
+    /// KV v2 mounted at `secret`, the Vault default.
+    pub fn new(addr: impl Into<String>, token: impl Into<String>) -> Self {
+        Self {
+            addr: addr.into().trim_end_matches('/').to_string(),
+            token: token.into(),
+            mount: "secret".to_string(),
+            version: KvVersion::V2,
+            namespace: None,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Uses `VAULT_ADDR` and `VAULT_TOKEN`, like the Vault CLI.
+    #[allow(clippy::result_large_err)]
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let var = |name: &str| {
+            std::env::var(name)
+                .map_err(|_| errors::schema_invalid("secrets", &format!("{name} is not set")))
+        };
+        Ok(Self::new(var("VAULT_ADDR")?, var("VAULT_TOKEN")?))
+    }
+
+    pub fn with_mount(mut self, mount: impl Into<String>) -> Self {
+        self.mount = mount.into().trim_matches('/').to_string();
+        self
+    }
+
+    pub fn with_kv_version(mut self, version: KvVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Vault Enterprise namespace, sent as `X-Vault-Namespace`.
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+
+    fn url(&self, path: &str) -> String {
+        let path = path.trim_matches('/');
+        match self.version {
+            KvVersion::V1 => format!("{}/v1/{}/{}", self.addr, self.mount, path),
+            KvVersion::V2 => format!("{}/v1/{}/data/{}", self.addr, self.mount, path),
+        }
+    }
+}
+
+#[async_trait]
+impl SecretProvider for VaultKvProvider {
+    fn name(&self) -> &str {
+        "vault"
+    }
+
+    async fn fetch(&self, path: &str) -> Result<SecretValue, ConfigError> {
+        let mut request = self
+            .client
+            .get(self.url(path))
+            .header("X-Vault-Token", &self.token);
+        if let Some(namespace) = &self.namespace {
+            request = request.header("X-Vault-Namespace", namespace);
+        }
+        let response = request.send().await.map_err(|err| {
+            errors::io_provider_unavailable("secrets", &format!("vault {path}: {err}"))
+        })?;
+        match response.status() {
+            status if status.is_success() => {}
+            StatusCode::NOT_FOUND => {
+                return Err(errors::schema_invalid(
+                    "secrets",
+                    &format!("vault {path}: not found"),
+                ))
+            }
+            StatusCode::FORBIDDEN | StatusCode::UNAUTHORIZED => {
+                return Err(errors::auth_forbidden(&format!(
+                    "secrets: vault {path}: permission denied"
+                )))
+            }
+            status => {
+                return Err(errors::io_provider_unavailable(
+                    "secrets",
+                    &format!("vault {path}: HTTP {status}"),
+                ))
+            }
+        }
+        let body: Value = response.json().await.map_err(|err| {
+            errors::io_provider_unavailable("secrets", &format!("vault {path}: {err}"))
+        })?;
+        let data = match self.version {
+            KvVersion::V1 => body.get("data"),
+            KvVersion::V2 => body.get("data").and_then(|data| data.get("data")),
+        };
+        data.filter(|data| data.is_object())
+            .cloned()
+            .map(SecretValue::new)
+            .ok_or_else(|| {
+                errors::io_provider_unavailable(
+                    "secrets",
+                    &format!("vault {path}: response has no secret data"),
+                )
+            })
+    }
+}
@@ -1,10 +1,47 @@
-use crate::model::{Checksum, ConfigMap, ConfigValue, KeyPath, SnapshotMetadata};
+use crate::model::{
+    Checksum, ConfigMap, ConfigValue, KeyPath, KeyProvenance, ReloadClass, SnapshotMetadata,
+    ValueOrigin,
+};
+use crate::tenant::{TenantOverlay, TenantOverlayMap, TenantView};
+use sb_types::prelude::TenantId;
 use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+use std::sync::Arc;
 
-#[derive(Clone, Debug)]
+/// Replacement for sensitive values in dumps and explanations.
+pub const REDACTED: &str = "****";
+
+#[derive(Clone)]
 pub struct ConfigSnapshot {
     data: ConfigValue,
     metadata: SnapshotMetadata,
+    provenance: Arc<KeyProvenance>,
+    tenants: Arc<TenantOverlayMap>,
+}
+
+// Snapshots end up in logs and panics; never print resolved secrets.
+impl fmt::Debug for ConfigSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConfigSnapshot")
+            .field("version", &self.metadata.version)
+            .field("checksum", &self.metadata.checksum)
+            .field("data", &self.redacted())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Where a key's effective value came from, for debugging endpoints.
+/// Sensitive values are already redacted.
+#[derive(Clone, Debug, Serialize)]
+pub struct Explanation {
+    pub key: KeyPath,
+    pub value: Option<ConfigValue>,
+    pub sensitive: bool,
+    pub reload: Option<ReloadClass>,
+    /// Layers that set this leaf, oldest first; the last one won unless a
+    /// later layer replaced a parent object. Empty for non-leaf keys.
+    pub chain: Vec<ValueOrigin>,
 }
 
 impl ConfigSnapshot {
@@ -12,6 +49,126 @@ impl ConfigSnapshot {
         Self {
             data: ConfigValue::Object(map),
             metadata,
+            provenance: Arc::default(),
+            tenants: Arc::default(),
+        }
+    }
+
+    pub fn with_provenance(mut self, provenance: KeyProvenance) -> Self {
+        self.provenance = Arc::new(provenance);
+        self
+    }
+
+    pub(crate) fn with_tenant_overlays(mut self, tenants: TenantOverlayMap) -> Self {
+        self.tenants = Arc::new(tenants);
+        self
+    }
+
+    /// This snapshot as seen by `tenant`, with its overlay (if any) on top.
+    pub fn for_tenant<'a>(&'a self, tenant: &'a TenantId) -> TenantView<'a> {
+        TenantView::new(self, tenant, self.tenants.get(tenant))
+    }
+
+    pub(crate) fn tenant_overlay(&self, tenant: &TenantId) -> Option<&TenantOverlay> {
+        self.tenants.get(tenant)
+    }
+
+    /// Tenants that have an overlay in this snapshot.
+    pub fn overlay_tenants(&self) -> Vec<&TenantId> {
+        self.tenants.keys().collect()
+    }
+
+    /// Override chain of a leaf key, oldest layer first. Values are raw.
+    pub fn provenance(&self, key: &KeyPath) -> &[ValueOrigin] {
+        self.provenance.get(key).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Reload class of `key` or of its closest registered ancestor, since
+    /// schema fields may hold whole objects.
+    pub fn reload_class(&self, key: &KeyPath) -> Option<ReloadClass> {
+        let summary = &self.metadata.reload_summary;
+        let mut path = key.0.as_str();
+        loop {
+            if let Some(class) = summary.get(&KeyPath(path.to_string())) {
+                return Some(*class);
+            }
+            path = &path[..path.rfind('.')?];
+        }
+    }
+
+    /// Whether `key` or any ancestor is registered as sensitive.
+    pub fn is_sensitive(&self, key: &KeyPath) -> bool {
+        let sensitive = &self.metadata.sensitive_keys;
+        let mut path = key.0.as_str();
+        loop {
+            if sensitive.contains(&KeyPath(path.to_string())) {
+                return true;
+            }
+            match path.rfind('.') {
+                Some(idx) => path = &path[..idx],
+                None => return false,
+            }
+        }
+    }
+
+    pub fn explain(&self, key: &KeyPath) -> Option<Explanation> {
+        let value = self.get_raw(key);
+        let chain = self.provenance(key);
+        if value.is_none() && chain.is_empty() {
+            return None;
+        }
+        let sensitive = self.is_sensitive(key);
+        let redact = |value: &ConfigValue| {
+            if sensitive {
+                ConfigValue::String(REDACTED.to_string())
+            } else {
+                let mut value = value.clone();
+                self.redact_below(&key.0, &mut value);
+                value
+            }
+        };
+        Some(Explanation {
+            key: key.clone(),
+            value: value.map(redact),
+            sensitive,
+            reload: self.reload_class(key),
+            chain: chain
+                .iter()
+                .map(|origin| ValueOrigin {
+                    value: redact(&origin.value),
+                    ..origin.clone()
+                })
+                .collect(),
+        })
+    }
+
+    /// The whole effective configuration with sensitive values replaced by
+    /// [`REDACTED`].
+    pub fn redacted(&self) -> ConfigValue {
+        let mut data = self.data.clone();
+        self.redact_below("", &mut data);
+        data
+    }
+
+    fn redact_below(&self, prefix: &str, value: &mut ConfigValue) {
+        let ConfigValue::Object(map) = value else {
+            return;
+        };
+        for (key, child) in map.iter_mut() {
+            let path = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{prefix}.{key}")
+            };
+            if self
+                .metadata
+                .sensitive_keys
+                .contains(&KeyPath(path.clone()))
+            {
+                *child = ConfigValue::String(REDACTED.to_string());
+            } else {
+                self.redact_below(&path, child);
+            }
         }
     }
 
@@ -18,6 +18,10 @@ impl Source for CliArgsSource {
         "cli"
     }
 
+    fn string_typed(&self) -> bool {
+        true
+    }
+
     async fn load(&self) -> Result<SourceSnapshot, errors::ConfigError> {
         let mut map = ConfigMap::new();
         for arg in &self.args {
@@ -31,6 +35,6 @@ impl Source for CliArgsSource {
         }
         let mut provenance = Provenance::default();
         provenance.0.push(provenance_entry("cli", "cli"));
-        Ok(SourceSnapshot { map, provenance })
+        Ok(SourceSnapshot::new(map, provenance))
     }
 }
@@ -0,0 +1,168 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use serde::Deserialize;
+
+use crate::errors::{self, ConfigError};
+use crate::model::{ConfigMap, Provenance, ProvenanceEntry};
+use crate::watch::remote::ChangeFeed;
+
+use super::{insert_kv, Source, SourceSnapshot};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct KvEntry {
+    key: String,
+    value: Option<String>,
+}
+
+/// Consul KV tree under `prefix`, e.g. `config/app/port` -> `app.port`.
+///
+/// Values are strings and get coerced through the namespace schema. As a
+/// `ChangeFeed` it issues blocking queries against the last seen
+/// `X-Consul-Index`.
+pub struct ConsulSource {
+    addr: String,
+    prefix: String,
+    token: Option<String>,
+    datacenter: Option<String>,
+    wait: Duration,
+    client: reqwest::Client,
+    index: AtomicU64,
+}
+
+impl ConsulSource {
+    pub fn new(addr: impl Into<String>, prefix: impl Into<String>) -> Self {
+        Self {
+            addr: addr.into().trim_end_matches('/').to_string(),
+            prefix: prefix.into().trim_start_matches('/').to_string(),
+            token: None,
+            datacenter: None,
+            wait: Duration::from_secs(30),
+            client: reqwest::Client::new(),
+            index: AtomicU64::new(0),
+        }
+    }
+
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    pub fn with_datacenter(mut self, datacenter: impl Into<String>) -> Self {
+        self.datacenter = Some(datacenter.into());
+        self
+    }
+
+    /// Longest a blocking query waits for a change before returning.
+    pub fn with_wait(mut self, wait: Duration) -> Self {
+        self.wait = wait;
+        self
+    }
+
+    /// `X-Consul-Index` of the last response.
+    pub fn index(&self) -> u64 {
+        self.index.load(Ordering::SeqCst)
+    }
+
+    async fn query(&self, block_on: Option<u64>) -> Result<(u64, Vec<KvEntry>), ConfigError> {
+        let mut request = self
+            .client
+            .get(format!("{}/v1/kv/{}", self.addr, self.prefix))
+            .query(&[("recurse", "true")]);
+        if let Some(dc) = &self.datacenter {
+            request = request.query(&[("dc", dc)]);
+        }
+        if let Some(index) = block_on {
+            request = request
+                .query(&[
+                    ("index", index.to_string()),
+                    ("wait", format!("{}ms", self.wait.as_millis())),
+                ])
+                .timeout(self.wait + Duration::from_secs(10));
+        }
+        if let Some(token) = &self.token {
+            request = request.header("X-Consul-Token", token);
+        }
+        let response = request.send().await.map_err(|err| self.unavailable(err))?;
+        let index = response
+            .headers()
+            .get("X-Consul-Index")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0);
+        match response.status() {
+            // Nothing stored under the prefix yet.
+            reqwest::StatusCode::NOT_FOUND => Ok((index, Vec::new())),
+            reqwest::StatusCode::FORBIDDEN => Err(errors::auth_forbidden(&format!(
+                "consul: {}: permission denied",
+                self.prefix
+            ))),
+            status if status.is_success() => {
+                let entries = response.json().await.map_err(|err| self.unavailable(err))?;
+                Ok((index, entries))
+            }
+            status => Err(errors::io_provider_unavailable(
+                "consul",
+                &format!("{}: HTTP {status}", self.prefix),
+            )),
+        }
+    }
+
+    fn unavailable(&self, err: reqwest::Error) -> ConfigError {
+        errors::io_provider_unavailable("consul", &format!("{}: {err}", self.prefix))
+    }
+}
+
+#[async_trait]
+impl Source for ConsulSource {
+    fn id(&self) -> &'static str {
+        "consul"
+    }
+
+    fn string_typed(&self) -> bool {
+        true
+    }
+
+    async fn load(&self) -> Result<SourceSnapshot, ConfigError> {
+        let (index, entries) = self.query(None).await?;
+        self.index.store(index, Ordering::SeqCst);
+
+        let mut map = ConfigMap::new();
+        for entry in entries {
+            let Some(encoded) = entry.value else {
+                continue;
+            };
+            let bytes = STANDARD.decode(encoded).map_err(|err| {
+                errors::schema_invalid("consul", &format!("{}: {err}", entry.key))
+            })?;
+            let value = String::from_utf8(bytes).map_err(|_| {
+                errors::schema_invalid("consul", &format!("{}: value is not UTF-8", entry.key))
+            })?;
+            let relative = entry.key.strip_prefix(&self.prefix).unwrap_or(&entry.key);
+            insert_kv(&mut map, relative, value);
+        }
+
+        let provenance = Provenance(vec![ProvenanceEntry {
+            source: format!("{}/{}", self.addr, self.prefix),
+            version: Some(index.to_string()),
+            layer: Some("consul".to_string()),
+        }]);
+        Ok(SourceSnapshot::new(map, provenance))
+    }
+}
+
+#[async_trait]
+impl ChangeFeed for ConsulSource {
+    async fn wait_for_change(&self) -> Result<bool, ConfigError> {
+        let last = self.index();
+        let (index, _) = self.query(Some(last)).await?;
+        // Consul may reset the index (e.g. after a snapshot restore); treat
+        // any movement as a change.
+        self.index.store(index, Ordering::SeqCst);
+        Ok(index != last)
+    }
+}
@@ -19,6 +19,10 @@ impl Source for EnvSource {
         "env"
     }
 
+    fn string_typed(&self) -> bool {
+        true
+    }
+
     async fn load(&self) -> Result<SourceSnapshot, errors::ConfigError> {
         let mut map = ConfigMap::new();
         let mut provenance = Provenance::default();
@@ -42,6 +46,6 @@ impl Source for EnvSource {
             merge_value(&mut map, &path, serde_json::Value::String(value));
         }
         provenance.0.push(provenance_entry("env", "env"));
-        Ok(SourceSnapshot { map, provenance })
+        Ok(SourceSnapshot::new(map, provenance))
     }
 }
@@ -0,0 +1,215 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use serde_json::{json, Value};
+
+use crate::errors::{self, ConfigError};
+use crate::model::{ConfigMap, Provenance, ProvenanceEntry};
+use crate::watch::remote::ChangeFeed;
+
+use super::{insert_kv, Source, SourceSnapshot};
+
+/// etcd v3 keys under `prefix`, read through the JSON gRPC gateway
+/// (`/v3/kv/range`, `/v3/watch`), e.g. `/config/app/port` -> `app.port`.
+///
+/// Values are strings and get coerced through the namespace schema. As a
+/// `ChangeFeed` it watches the prefix from the last loaded revision.
+pub struct EtcdSource {
+    endpoint: String,
+    prefix: String,
+    token: Option<String>,
+    wait: Duration,
+    client: reqwest::Client,
+    revision: AtomicI64,
+}
+
+impl EtcdSource {
+    pub fn new(endpoint: impl Into<String>, prefix: impl Into<String>) -> Self {
+        Self {
+            endpoint: endpoint.into().trim_end_matches('/').to_string(),
+            prefix: prefix.into(),
+            token: None,
+            wait: Duration::from_secs(30),
+            client: reqwest::Client::new(),
+            revision: AtomicI64::new(0),
+        }
+    }
+
+    /// Token from `/v3/auth/authenticate`, sent as `Authorization`.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Longest a watch waits for an event before returning.
+    pub fn with_wait(mut self, wait: Duration) -> Self {
+        self.wait = wait;
+        self
+    }
+
+    /// Store revision of the last load or observed event.
+    pub fn revision(&self) -> i64 {
+        self.revision.load(Ordering::SeqCst)
+    }
+
+    fn range(&self) -> (String, String) {
+        (
+            STANDARD.encode(self.prefix.as_bytes()),
+            STANDARD.encode(prefix_end(self.prefix.as_bytes())),
+        )
+    }
+
+    fn post(&self, path: &str, body: Value) -> reqwest::RequestBuilder {
+        let request = self
+            .client
+            .post(format!("{}{path}", self.endpoint))
+            .json(&body);
+        match &self.token {
+            Some(token) => request.header("Authorization", token),
+            None => request,
+        }
+    }
+
+    fn unavailable(&self, detail: impl std::fmt::Display) -> ConfigError {
+        errors::io_provider_unavailable("etcd", &format!("{}: {detail}", self.prefix))
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn check_status(&self, status: reqwest::StatusCode) -> Result<(), ConfigError> {
+        match status {
+            status if status.is_success() => Ok(()),
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => Err(
+                errors::auth_forbidden(&format!("etcd: {}: permission denied", self.prefix)),
+            ),
+            status => Err(self.unavailable(format!("HTTP {status}"))),
+        }
+    }
+}
+
+/// Smallest key greater than every key starting with `prefix`.
+fn prefix_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < 0xff {
+            end.push(last + 1);
+            return end;
+        }
+    }
+    // Empty (or all-0xff) prefix: the whole keyspace.
+    vec![0]
+}
+
+/// The gateway renders int64 fields as JSON strings.
+fn int64(value: Option<&Value>) -> Option<i64> {
+    match value? {
+        Value::String(raw) => raw.parse().ok(),
+        other => other.as_i64(),
+    }
+}
+
+fn decode(field: Option<&Value>) -> Option<String> {
+    let bytes = STANDARD.decode(field?.as_str()?).ok()?;
+    String::from_utf8(bytes).ok()
+}
+
+#[async_trait]
+impl Source for EtcdSource {
+    fn id(&self) -> &'static str {
+        "etcd"
+    }
+
+    fn string_typed(&self) -> bool {
+        true
+    }
+
+    async fn load(&self) -> Result<SourceSnapshot, ConfigError> {
+        let (key, range_end) = self.range();
+        let response = self
+            .post(
+                "/v3/kv/range",
+                json!({ "key": key, "range_end": range_end }),
+            )
+            .send()
+            .await
+            .map_err(|err| self.unavailable(err))?;
+        self.check_status(response.status())?;
+        let body: Value = response.json().await.map_err(|err| self.unavailable(err))?;
+        let revision = int64(body.pointer("/header/revision")).unwrap_or(0);
+        self.revision.store(revision, Ordering::SeqCst);
+
+        let mut map = ConfigMap::new();
+        for kv in body["kvs"].as_array().into_iter().flatten() {
+            let (Some(key), Some(value)) = (decode(kv.get("key")), decode(kv.get("value"))) else {
+                return Err(errors::schema_invalid(
+                    "etcd",
+                    &format!("{}: key or value is not UTF-8", self.prefix),
+                ));
+            };
+            let relative = key.strip_prefix(&self.prefix).unwrap_or(&key);
+            insert_kv(&mut map, relative, value);
+        }
+
+        let provenance = Provenance(vec![ProvenanceEntry {
+            source: format!("{}/{}", self.endpoint, self.prefix.trim_start_matches('/')),
+            version: Some(revision.to_string()),
+            layer: Some("etcd".to_string()),
+        }]);
+        Ok(SourceSnapshot::new(map, provenance))
+    }
+}
+
+#[async_trait]
+impl ChangeFeed for EtcdSource {
+    async fn wait_for_change(&self) -> Result<bool, ConfigError> {
+        let (key, range_end) = self.range();
+        let request = json!({
+            "create_request": {
+                "key": key,
+                "range_end": range_end,
+                "start_revision": (self.revision() + 1).to_string(),
+            }
+        });
+        let watch = async {
+            let mut response = self
+                .post("/v3/watch", request)
+                .send()
+                .await
+                .map_err(|err| self.unavailable(err))?;
+            self.check_status(response.status())?;
+            // The gateway streams one JSON object per line.
+            let mut buffer = Vec::new();
+            while let Some(chunk) = response
+                .chunk()
+                .await
+                .map_err(|err| self.unavailable(err))?
+            {
+                buffer.extend_from_slice(&chunk);
+                while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = buffer.drain(..=end).collect();
+                    let Ok(message) = serde_json::from_slice::<Value>(&line) else {
+                        continue;
+                    };
+                    let result = &message["result"];
+                    let has_events = result["events"].as_array().is_some_and(|e| !e.is_empty());
+                    // A cancelled watch (e.g. compacted revision) needs a
+                    // full reload to resynchronise.
+                    let cancelled = result["canceled"].as_bool().unwrap_or(false);
+                    if has_events || cancelled {
+                        if let Some(revision) = int64(result.pointer("/header/revision")) {
+                            self.revision.store(revision, Ordering::SeqCst);
+                        }
+                        return Ok(true);
+                    }
+                }
+            }
+            Ok(false)
+        };
+        match tokio::time::timeout(self.wait, watch).await {
+            Ok(result) => result,
+            Err(_) => Ok(false),
+        }
+    }
+}
@@ -1,20 +1,64 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::{fs, io};
 
 use async_trait::async_trait;
 
 use crate::{
     errors,
-    model::{ConfigMap, Provenance},
+    model::{ConfigMap, KeyPath, Provenance},
 };
 
 use super::{merge_maps, provenance_entry, Source, SourceSnapshot};
 
+/// Top-level key listing files (relative to the including file) that are
+/// loaded first and then overlaid by the including file.
+pub const INCLUDE_KEY: &str = "include";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl FileFormat {
+    /// Detects the format from the extension; unknown extensions are read as JSON.
+    pub fn from_path(path: &Path) -> Self {
+        match path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("yaml") | Some("yml") => FileFormat::Yaml,
+            Some("toml") => FileFormat::Toml,
+            _ => FileFormat::Json,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct FileSource {
     pub paths: Vec<PathBuf>,
 }
 
+impl FileSource {
+    /// Every file that contributes to this source, includes first. Missing
+    /// top-level files are skipped, as in `load`.
+    #[allow(clippy::result_large_err)]
+    pub fn resolved_paths(&self) -> Result<Vec<PathBuf>, errors::ConfigError> {
+        let mut files = Vec::new();
+        for path in &self.paths {
+            let mut stack = Vec::new();
+            if let Some(file) = read_optional(path)? {
+                collect_includes(path, &file.map, &mut stack, &mut files)?;
+                files.push(path.clone());
+            }
+        }
+        Ok(files)
+    }
+}
+
 #[async_trait]
 impl Source for FileSource {
     fn id(&self) -> &'static str {
@@ -24,27 +68,404 @@ impl Source for FileSource {
     async fn load(&self) -> Result<SourceSnapshot, errors::ConfigError> {
         let mut map = ConfigMap::new();
         let mut provenance = Provenance::default();
+        let mut layers = Vec::new();
+        let mut interpolated = Vec::new();
 
         for path in &self.paths {
-            let content = match fs::read_to_string(path) {
-                Ok(c) => c,
-                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
-                Err(err) => {
-                    return Err(errors::io_provider_unavailable("file", &format!("{}", err)))
-                }
+            let Some(file) = read_optional(path)? else {
+                continue;
             };
+            let mut stack = vec![identity(path)];
+            let resolved = resolve_includes(
+                path,
+                file,
+                &mut stack,
+                &mut provenance,
+                &mut layers,
+                &mut interpolated,
+            )?;
+            merge_maps(&mut map, &resolved);
+        }
+
+        Ok(SourceSnapshot::new(map, provenance)
+            .with_layers(layers)
+            .with_interpolated(interpolated.into_iter().map(KeyPath).collect()))
+    }
+}
+
+/// One parsed file and the dotted keys whose values came from interpolation.
+struct ParsedFile {
+    map: ConfigMap,
+    interpolated: Vec<String>,
+}
+
+fn identity(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[allow(clippy::result_large_err)]
+fn read_optional(path: &Path) -> Result<Option<ParsedFile>, errors::ConfigError> {
+    match fs::read_to_string(path) {
+        Ok(content) => parse_tracked(path, &content).map(Some),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(errors::io_provider_unavailable(
+            "file",
+            &format!("{}: {err}", path.display()),
+        )),
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn read_required(path: &Path) -> Result<ParsedFile, errors::ConfigError> {
+    let content = fs::read_to_string(path).map_err(|err| {
+        errors::io_provider_unavailable("file", &format!("{}: {err}", path.display()))
+    })?;
+    parse_tracked(path, &content)
+}
+
+#[allow(clippy::result_large_err)]
+fn include_targets(path: &Path, map: &ConfigMap) -> Result<Vec<PathBuf>, errors::ConfigError> {
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    let entries = match map.get(INCLUDE_KEY) {
+        None => return Ok(Vec::new()),
+        Some(serde_json::Value::String(one)) => vec![one.as_str()],
+        Some(serde_json::Value::Array(many)) => many
+            .iter()
+            .map(|item| item.as_str())
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| {
+                errors::schema_invalid(
+                    "file",
+                    &format!("{}: `{INCLUDE_KEY}` must list file paths", path.display()),
+                )
+            })?,
+        Some(_) => {
+            return Err(errors::schema_invalid(
+                "file",
+                &format!(
+                    "{}: `{INCLUDE_KEY}` must be a path or a list of paths",
+                    path.display()
+                ),
+            ))
+        }
+    };
+    Ok(entries.into_iter().map(|entry| base.join(entry)).collect())
+}
+
+#[allow(clippy::result_large_err)]
+fn resolve_includes(
+    path: &Path,
+    file: ParsedFile,
+    stack: &mut Vec<PathBuf>,
+    provenance: &mut Provenance,
+    layers: &mut Vec<ConfigMap>,
+    interpolated_keys: &mut Vec<String>,
+) -> Result<ConfigMap, errors::ConfigError> {
+    let ParsedFile {
+        mut map,
+        interpolated,
+    } = file;
+    let mut merged = ConfigMap::new();
+    for target in include_targets(path, &map)? {
+        let id = identity(&target);
+        if stack.contains(&id) {
+            return Err(errors::schema_invalid(
+                "file",
+                &format!("{}: include cycle via {}", path.display(), target.display()),
+            ));
+        }
+        stack.push(id);
+        let included = read_required(&target)?;
+        let included = resolve_includes(
+            &target,
+            included,
+            stack,
+            provenance,
+            layers,
+            interpolated_keys,
+        )?;
+        stack.pop();
+        merge_maps(&mut merged, &included);
+    }
+    map.remove(INCLUDE_KEY);
+    merge_maps(&mut merged, &map);
+    provenance
+        .0
+        .push(provenance_entry(path.to_string_lossy().as_ref(), "file"));
+    overlay_interpolated(interpolated_keys, &map, interpolated);
+    layers.push(map);
+    Ok(merged)
+}
+
+/// Applies one layer's interpolated keys on top of `current`: keys the layer
+/// overrides lose their mark, then the layer's own keys are added.
+fn overlay_interpolated(current: &mut Vec<String>, layer: &ConfigMap, keys: Vec<String>) {
+    current.retain(|key| !overrides(layer, key));
+    current.extend(keys.into_iter().filter(|key| overrides(layer, key)));
+}
+
+/// Whether merging `layer` replaces the value at the dotted `key`, either
+/// directly or through a non-object value at one of its parents.
+fn overrides(layer: &ConfigMap, key: &str) -> bool {
+    let mut node = layer;
+    let mut segments = key.split('.').peekable();
+    while let Some(segment) = segments.next() {
+        match node.get(segment) {
+            None => return false,
+            Some(serde_json::Value::Object(child)) if segments.peek().is_some() => node = child,
+            Some(_) => return true,
+        }
+    }
+    false
+}
 
-            if let Ok(value) = serde_json::from_str::<serde_json::Value>(&content) {
-                if let serde_json::Value::Object(obj) = value {
-                    merge_maps(&mut map, &obj);
-                }
+#[allow(clippy::result_large_err)]
+fn collect_includes(
+    path: &Path,
+    map: &ConfigMap,
+    stack: &mut Vec<PathBuf>,
+    out: &mut Vec<PathBuf>,
+) -> Result<(), errors::ConfigError> {
+    stack.push(identity(path));
+    for target in include_targets(path, map)? {
+        if stack.contains(&identity(&target)) {
+            continue;
+        }
+        let included = read_required(&target)?;
+        collect_includes(&target, &included.map, stack, out)?;
+        out.push(target);
+    }
+    stack.pop();
+    Ok(())
+}
+
+/// Parses `content` according to the file extension, then interpolates
+/// `${VAR}` / `${VAR:-default}` inside string values. Comments and keys are
+/// never expanded, and substituted text cannot change the document structure.
+/// Errors carry `path:line:column`.
+#[allow(clippy::result_large_err)]
+pub fn parse_file(path: &Path, content: &str) -> Result<ConfigMap, errors::ConfigError> {
+    parse_tracked(path, content).map(|file| file.map)
+}
+
+#[allow(clippy::result_large_err)]
+fn parse_tracked(path: &Path, content: &str) -> Result<ParsedFile, errors::ConfigError> {
+    let mut value = match FileFormat::from_path(path) {
+        FileFormat::Json => parse_json(path, content)?,
+        FileFormat::Yaml => parse_yaml(path, content)?,
+        FileFormat::Toml => parse_toml(path, content)?,
+    };
+    let mut interpolated = Vec::new();
+    interpolate_strings(&mut value, Some(""), &mut interpolated).map_err(|(reference, msg)| {
+        let (line, column) = content
+            .find(&reference)
+            .map(|offset| line_col(content, offset))
+            .unwrap_or((1, 1));
+        parse_error(path, line, column, &msg)
+    })?;
+    let map = match value {
+        serde_json::Value::Object(map) => map,
+        serde_json::Value::Null => ConfigMap::new(),
+        _ => return Err(parse_error(path, 1, 1, "top-level value must be a mapping")),
+    };
+    Ok(ParsedFile { map, interpolated })
+}
+
+/// Interpolates every string leaf of `value`, recording the dotted `key` of
+/// each leaf a reference was substituted into (array items have no key). On
+/// failure returns the offending reference text, used to locate it in the
+/// file, and the message.
+fn interpolate_strings(
+    value: &mut serde_json::Value,
+    key: Option<&str>,
+    interpolated: &mut Vec<String>,
+) -> Result<(), (String, String)> {
+    match value {
+        serde_json::Value::String(text) => {
+            let (expanded, substituted) = interpolate_env(text).map_err(|(offset, msg)| {
+                let reference = &text[offset..];
+                let end = reference.find('}').map(|end| end + 1).unwrap_or(2);
+                (reference[..end].to_string(), msg)
+            })?;
+            *text = expanded;
+            if let Some(key) = key.filter(|_| substituted) {
+                interpolated.push(key.to_string());
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                interpolate_strings(item, None, interpolated)?;
             }
+        }
+        serde_json::Value::Object(map) => {
+            for (name, item) in map.iter_mut() {
+                let child = key.map(|key| match key {
+                    "" => name.clone(),
+                    parent => format!("{parent}.{name}"),
+                });
+                interpolate_strings(item, child.as_deref(), interpolated)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
 
-            provenance
-                .0
-                .push(provenance_entry(path.to_string_lossy().as_ref(), "file"));
+fn parse_error(path: &Path, line: usize, column: usize, msg: &str) -> errors::ConfigError {
+    errors::schema_invalid(
+        "file",
+        &format!("{}:{line}:{column}: {msg}", path.display()),
+    )
+}
+
+fn line_col(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map(|idx| idx + 1).unwrap_or(0) + 1;
+    (line, column)
+}
+
+#[allow(clippy::result_large_err)]
+fn parse_json(path: &Path, content: &str) -> Result<serde_json::Value, errors::ConfigError> {
+    serde_json::from_str(content)
+        .map_err(|err| parse_error(path, err.line(), err.column(), &err.to_string()))
+}
+
+#[cfg(feature = "yaml")]
+#[allow(clippy::result_large_err)]
+fn parse_yaml(path: &Path, content: &str) -> Result<serde_json::Value, errors::ConfigError> {
+    use serde::Deserialize;
+
+    let yaml_error = |err: serde_yaml::Error| {
+        let (line, column) = err
+            .location()
+            .map(|loc| (loc.line(), loc.column()))
+            .unwrap_or((1, 1));
+        parse_error(path, line, column, &err.to_string())
+    };
+
+    // Later documents in a multi-document stream override earlier ones.
+    let mut merged = ConfigMap::new();
+    for document in serde_yaml::Deserializer::from_str(content) {
+        let value = serde_yaml::Value::deserialize(document).map_err(yaml_error)?;
+        let json = serde_json::to_value(value)
+            .map_err(|err| parse_error(path, 1, 1, &format!("unsupported yaml value: {err}")))?;
+        match json {
+            serde_json::Value::Object(map) => merge_maps(&mut merged, &map),
+            serde_json::Value::Null => {}
+            _ => {
+                return Err(parse_error(
+                    path,
+                    1,
+                    1,
+                    "every yaml document must be a mapping",
+                ))
+            }
         }
+    }
+    Ok(serde_json::Value::Object(merged))
+}
+
+#[cfg(not(feature = "yaml"))]
+#[allow(clippy::result_large_err)]
+fn parse_yaml(path: &Path, _content: &str) -> Result<serde_json::Value, errors::ConfigError> {
+    Err(parse_error(path, 1, 1, "yaml support is not enabled"))
+}
+
+#[cfg(feature = "toml")]
+#[allow(clippy::result_large_err)]
+fn parse_toml(path: &Path, content: &str) -> Result<serde_json::Value, errors::ConfigError> {
+    let value: toml::Value = toml::from_str(content).map_err(|err| {
+        let (line, column) = err
+            .span()
+            .map(|span| line_col(content, span.start))
+            .unwrap_or((1, 1));
+        parse_error(path, line, column, err.message())
+    })?;
+    Ok(toml_to_json(value))
+}
 
-        Ok(SourceSnapshot { map, provenance })
+#[cfg(feature = "toml")]
+fn toml_to_json(value: toml::Value) -> serde_json::Value {
+    match value {
+        toml::Value::String(s) => serde_json::Value::String(s),
+        toml::Value::Integer(i) => serde_json::Value::from(i),
+        toml::Value::Float(f) => serde_json::Value::from(f),
+        toml::Value::Boolean(b) => serde_json::Value::Bool(b),
+        toml::Value::Datetime(dt) => serde_json::Value::String(dt.to_string()),
+        toml::Value::Array(items) => {
+            serde_json::Value::Array(items.into_iter().map(toml_to_json).collect())
+        }
+        toml::Value::Table(table) => serde_json::Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(not(feature = "toml"))]
+#[allow(clippy::result_large_err)]
+fn parse_toml(path: &Path, _content: &str) -> Result<serde_json::Value, errors::ConfigError> {
+    Err(parse_error(path, 1, 1, "toml support is not enabled"))
+}
+
+/// Replaces `${VAR}` and `${VAR:-default}` in one string value with
+/// environment values; `$${` yields a literal `${`. Also returns whether any
+/// reference was substituted, or the byte offset of the offending reference
+/// on failure.
+fn interpolate_env(content: &str) -> Result<(String, bool), (usize, String)> {
+    let mut out = String::with_capacity(content.len());
+    let mut rest = content;
+    let mut offset = 0;
+    let mut substituted = false;
+    while let Some(idx) = rest.find("${") {
+        if rest[..idx].ends_with('$') {
+            out.push_str(&rest[..idx - 1]);
+            out.push_str("${");
+            rest = &rest[idx + 2..];
+            offset += idx + 2;
+            continue;
+        }
+        out.push_str(&rest[..idx]);
+        let start = offset + idx;
+        let body = &rest[idx + 2..];
+        let end = body
+            .find(['}', '\n'])
+            .filter(|&end| body.as_bytes()[end] == b'}')
+            .ok_or((start, "unterminated `${` reference".to_string()))?;
+        let reference = &body[..end];
+        let (name, default) = match reference.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (reference, None),
+        };
+        let valid_name = !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return Err((start, format!("invalid variable name `{name}`")));
+        }
+        let value = std::env::var(name).ok();
+        match default {
+            Some(default) => out.push_str(
+                value
+                    .as_deref()
+                    .filter(|v| !v.is_empty())
+                    .unwrap_or(default),
+            ),
+            None => out.push_str(&value.ok_or_else(|| {
+                (
+                    start,
+                    format!("environment variable `{name}` is not set and has no default"),
+                )
+            })?),
+        }
+        substituted = true;
+        let consumed = idx + 2 + end + 1;
+        rest = &rest[consumed..];
+        offset += consumed;
     }
+    out.push_str(rest);
+    Ok((out, substituted))
 }
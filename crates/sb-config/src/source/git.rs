@@ -0,0 +1,316 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use git2::{
+    Cred, CredentialType, Direction, FetchOptions, ObjectType, RemoteCallbacks, Repository,
+    TreeWalkMode, TreeWalkResult,
+};
+use parking_lot::Mutex;
+
+use crate::errors::{self, ConfigError};
+use crate::model::{ConfigMap, Provenance, ProvenanceEntry};
+use crate::watch::remote::ChangeFeed;
+
+use super::file::parse_file;
+use super::{merge_maps, Source, SourceSnapshot};
+
+// Local ref the fetched commit is stored under in the cache repository.
+const FETCHED_REF: &str = "refs/sb-config/fetched";
+
+// libgit2 asks again after rejected credentials; stop instead of looping.
+const MAX_CREDENTIAL_ATTEMPTS: usize = 3;
+
+/// Supplies credentials for `(url, username_from_url, allowed_types)`, as
+/// libgit2's credentials callback does.
+pub type GitCredentials =
+    Arc<dyn Fn(&str, Option<&str>, CredentialType) -> Result<Cred, git2::Error> + Send + Sync>;
+
+/// Config files from a Git branch or tag. `path` names one file or a
+/// directory whose JSON/YAML/TOML files are merged in path order. The commit
+/// SHA is recorded as `ProvenanceEntry.version`.
+///
+/// Objects are fetched into a bare cache repository at `cache_dir`. As a
+/// `ChangeFeed` it polls the remote ref every `poll_interval`. Remotes that
+/// need authentication take a token or a credentials callback.
+pub struct GitSource {
+    url: String,
+    reference: String,
+    path: String,
+    cache_dir: PathBuf,
+    poll_interval: Duration,
+    credentials: Option<GitCredentials>,
+    loaded: Mutex<Option<String>>,
+}
+
+impl GitSource {
+    pub fn new(
+        url: impl Into<String>,
+        reference: impl Into<String>,
+        cache_dir: impl Into<PathBuf>,
+    ) -> Self {
+        Self {
+            url: url.into(),
+            reference: reference.into(),
+            path: String::new(),
+            cache_dir: cache_dir.into(),
+            poll_interval: Duration::from_secs(30),
+            credentials: None,
+            loaded: Mutex::new(None),
+        }
+    }
+
+    /// HTTPS access token, sent as the password of a basic-auth login.
+    pub fn with_token(self, token: impl Into<String>) -> Self {
+        let token = token.into();
+        self.with_credentials(Arc::new(move |_, username, _| {
+            Cred::userpass_plaintext(username.unwrap_or("x-access-token"), &token)
+        }))
+    }
+
+    /// Callback for any other scheme, e.g. SSH keys or the SSH agent.
+    pub fn with_credentials(mut self, credentials: GitCredentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    /// File or directory inside the repository; the root by default.
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.path = path.into().trim_matches('/').to_string();
+        self
+    }
+
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Commit SHA of the last load.
+    pub fn commit(&self) -> Option<String> {
+        self.loaded.lock().clone()
+    }
+
+    fn params(&self) -> GitParams {
+        GitParams {
+            url: self.url.clone(),
+            reference: self.reference.clone(),
+            path: self.path.clone(),
+            cache_dir: self.cache_dir.clone(),
+            credentials: self.credentials.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct GitParams {
+    url: String,
+    reference: String,
+    path: String,
+    cache_dir: PathBuf,
+    credentials: Option<GitCredentials>,
+}
+
+fn git_error(url: &str, err: git2::Error) -> ConfigError {
+    errors::io_provider_unavailable("git", &format!("{url}: {}", err.message()))
+}
+
+impl GitParams {
+    #[allow(clippy::result_large_err)]
+    fn repository(&self) -> Result<Repository, ConfigError> {
+        Repository::open_bare(&self.cache_dir)
+            .or_else(|_| Repository::init_bare(&self.cache_dir))
+            .map_err(|err| git_error(&self.url, err))
+    }
+
+    fn callbacks(&self) -> RemoteCallbacks<'_> {
+        let mut callbacks = RemoteCallbacks::new();
+        if let Some(credentials) = &self.credentials {
+            let mut attempts = 0;
+            callbacks.credentials(move |url, username, allowed| {
+                attempts += 1;
+                if attempts > MAX_CREDENTIAL_ATTEMPTS {
+                    return Err(git2::Error::from_str("credentials rejected"));
+                }
+                credentials(url, username, allowed)
+            });
+        }
+        callbacks
+    }
+
+    /// Full remote ref name and the commit it points at.
+    #[allow(clippy::result_large_err)]
+    fn remote_head(&self, repo: &Repository) -> Result<(String, git2::Oid), ConfigError> {
+        let mut remote = repo
+            .remote_anonymous(&self.url)
+            .map_err(|err| git_error(&self.url, err))?;
+        let connection = remote
+            .connect_auth(Direction::Fetch, Some(self.callbacks()), None)
+            .map_err(|err| git_error(&self.url, err))?;
+        let heads = connection.list().map_err(|err| git_error(&self.url, err))?;
+        let candidates = [
+            format!("refs/heads/{}", self.reference),
+            format!("refs/tags/{}", self.reference),
+            self.reference.clone(),
+        ];
+        candidates
+            .iter()
+            .find_map(|name| {
+                // Annotated tags are listed twice; `^{}` carries the commit.
+                let peeled = format!("{name}^{{}}");
+                let oid = heads
+                    .iter()
+                    .find(|head| head.name() == peeled)
+                    .or_else(|| heads.iter().find(|head| head.name() == name))?
+                    .oid();
+                Some((name.clone(), oid))
+            })
+            .ok_or_else(|| {
+                errors::schema_invalid(
+                    "git",
+                    &format!("{}: no branch or tag `{}`", self.url, self.reference),
+                )
+            })
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn fetch(&self) -> Result<(String, Vec<(String, String)>), ConfigError> {
+        let repo = self.repository()?;
+        let (name, _) = self.remote_head(&repo)?;
+        let mut remote = repo
+            .remote_anonymous(&self.url)
+            .map_err(|err| git_error(&self.url, err))?;
+        let mut options = FetchOptions::new();
+        options.remote_callbacks(self.callbacks());
+        remote
+            .fetch(
+                &[format!("+{name}:{FETCHED_REF}")],
+                Some(&mut options),
+                None,
+            )
+            .map_err(|err| git_error(&self.url, err))?;
+        let commit = repo
+            .find_reference(FETCHED_REF)
+            .and_then(|reference| reference.peel_to_commit())
+            .map_err(|err| git_error(&self.url, err))?;
+        let tree = commit.tree().map_err(|err| git_error(&self.url, err))?;
+
+        let mut files = Vec::new();
+        let missing = || {
+            errors::schema_invalid(
+                "git",
+                &format!("{}@{}: no `{}`", self.url, self.reference, self.path),
+            )
+        };
+        let object = if self.path.is_empty() {
+            tree.as_object().clone()
+        } else {
+            tree.get_path(Path::new(&self.path))
+                .and_then(|entry| entry.to_object(&repo))
+                .map_err(|_| missing())?
+        };
+        match object.kind() {
+            Some(ObjectType::Blob) => {
+                let blob = object
+                    .peel_to_blob()
+                    .map_err(|err| git_error(&self.url, err))?;
+                files.push((self.path.clone(), text(&self.path, blob.content())?));
+            }
+            Some(ObjectType::Tree) => {
+                let subtree = object
+                    .peel_to_tree()
+                    .map_err(|err| git_error(&self.url, err))?;
+                let mut blobs = Vec::new();
+                subtree
+                    .walk(TreeWalkMode::PreOrder, |dir, entry| {
+                        let name = entry.name().unwrap_or_default();
+                        if entry.kind() == Some(ObjectType::Blob) && is_config_file(name) {
+                            blobs.push((format!("{dir}{name}"), entry.id()));
+                        }
+                        TreeWalkResult::Ok
+                    })
+                    .map_err(|err| git_error(&self.url, err))?;
+                blobs.sort();
+                for (relative, oid) in blobs {
+                    let blob = repo
+                        .find_blob(oid)
+                        .map_err(|err| git_error(&self.url, err))?;
+                    let full = if self.path.is_empty() {
+                        relative
+                    } else {
+                        format!("{}/{relative}", self.path)
+                    };
+                    let content = text(&full, blob.content())?;
+                    files.push((full, content));
+                }
+            }
+            _ => return Err(missing()),
+        }
+        Ok((commit.id().to_string(), files))
+    }
+}
+
+fn is_config_file(name: &str) -> bool {
+    matches!(
+        Path::new(name).extension().and_then(|ext| ext.to_str()),
+        Some("json" | "yaml" | "yml" | "toml")
+    )
+}
+
+#[allow(clippy::result_large_err)]
+fn text(path: &str, bytes: &[u8]) -> Result<String, ConfigError> {
+    String::from_utf8(bytes.to_vec())
+        .map_err(|_| errors::schema_invalid("git", &format!("{path}: not UTF-8")))
+}
+
+async fn blocking<T: Send + 'static>(
+    job: impl FnOnce() -> Result<T, ConfigError> + Send + 'static,
+) -> Result<T, ConfigError> {
+    tokio::task::spawn_blocking(job)
+        .await
+        .map_err(|err| errors::io_provider_unavailable("git", &err.to_string()))?
+}
+
+#[async_trait]
+impl Source for GitSource {
+    fn id(&self) -> &'static str {
+        "git"
+    }
+
+    #[allow(clippy::result_large_err)]
+    async fn load(&self) -> Result<SourceSnapshot, ConfigError> {
+        let params = self.params();
+        let (sha, files) = blocking(move || params.fetch()).await?;
+
+        let mut map = ConfigMap::new();
+        let mut provenance = Provenance::default();
+        let mut layers = Vec::new();
+        for (path, content) in files {
+            let layer = parse_file(Path::new(&path), &content)?;
+            merge_maps(&mut map, &layer);
+            provenance.0.push(ProvenanceEntry {
+                source: format!("{}:{path}", self.url),
+                version: Some(sha.clone()),
+                layer: Some("git".to_string()),
+            });
+            layers.push(layer);
+        }
+        *self.loaded.lock() = Some(sha);
+        Ok(SourceSnapshot::new(map, provenance).with_layers(layers))
+    }
+}
+
+#[async_trait]
+impl ChangeFeed for GitSource {
+    #[allow(clippy::result_large_err)]
+    async fn wait_for_change(&self) -> Result<bool, ConfigError> {
+        tokio::time::sleep(self.poll_interval).await;
+        let params = self.params();
+        let (_, head) = blocking(move || {
+            let repo = params.repository()?;
+            params.remote_head(&repo)
+        })
+        .await?;
+        Ok(self.commit().as_deref() != Some(head.to_string().as_str()))
+    }
+}
@@ -1,22 +1,65 @@
 use crate::{
     errors::ConfigError,
-    model::{ConfigMap, ProvenanceEntry},
+    model::{ConfigMap, KeyPath, ProvenanceEntry},
 };
 use async_trait::async_trait;
 
 pub mod cli;
+#[cfg(feature = "remote_consul")]
+pub mod consul;
 pub mod env;
+#[cfg(feature = "remote_etcd")]
+pub mod etcd;
 pub mod file;
+#[cfg(feature = "remote_git")]
+pub mod git;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
 pub struct SourceSnapshot {
     pub map: ConfigMap,
     pub provenance: crate::model::Provenance,
+    /// What each `provenance` entry contributed, in override order, for
+    /// sources with several layers (e.g. one per file). When empty, the whole
+    /// `map` is attributed to the last entry.
+    pub layers: Vec<ConfigMap>,
+    /// String leaves produced by `${VAR}` interpolation, as dotted paths. The
+    /// loader coerces these (and only these) to their schema types.
+    pub interpolated: Vec<KeyPath>,
+}
+
+impl SourceSnapshot {
+    pub fn new(map: ConfigMap, provenance: crate::model::Provenance) -> Self {
+        Self {
+            map,
+            provenance,
+            layers: Vec::new(),
+            interpolated: Vec::new(),
+        }
+    }
+
+    pub fn with_layers(mut self, layers: Vec<ConfigMap>) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    pub fn with_interpolated(mut self, interpolated: Vec<KeyPath>) -> Self {
+        self.interpolated = interpolated;
+        self
+    }
 }
 
 #[async_trait]
 pub trait Source: Send + Sync {
     fn id(&self) -> &'static str;
+
+    /// Whether every leaf this source produces is a raw string (env vars,
+    /// CLI flags). The loader coerces such values to the types declared by
+    /// the namespace schema before merging.
+    fn string_typed(&self) -> bool {
+        false
+    }
+
     async fn load(&self) -> Result<SourceSnapshot, ConfigError>;
 }
 
@@ -67,3 +110,17 @@ pub(crate) fn provenance_entry(source: &str, layer: &str) -> ProvenanceEntry {
         layer: Some(layer.to_string()),
     }
 }
+
+/// Inserts a KV-store entry (`app/db/port`) as a dotted config key
+/// (`app.db.port`); directory markers with an empty key are skipped.
+#[cfg(any(feature = "remote_consul", feature = "remote_etcd"))]
+pub(crate) fn insert_kv(map: &mut ConfigMap, relative: &str, value: String) {
+    let key = relative
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>()
+        .join(".");
+    if !key.is_empty() {
+        merge_value(map, &key, serde_json::Value::String(value));
+    }
+}
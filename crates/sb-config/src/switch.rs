@@ -1,32 +1,251 @@
 use parking_lot::RwLock;
+use sb_types::prelude::TenantId;
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::model::{Checksum, SnapshotVersion};
 use crate::snapshot::ConfigSnapshot;
 
-pub struct SnapshotSwitch {
-    current: RwLock<Arc<ConfigSnapshot>>,
+/// A snapshot that has been live, as listed by [`SnapshotSwitch::history`].
+#[derive(Clone, Debug)]
+pub struct SnapshotRecord {
+    pub version: SnapshotVersion,
+    pub checksum: Checksum,
+    pub snapshot: Arc<ConfigSnapshot>,
+    pub activated_at: Instant,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HealthOutcome {
+    /// The current snapshot became last-known-good.
+    Promoted(SnapshotVersion),
+    /// Healthy, but not live long enough yet.
+    Pending(Duration),
+    AlreadyLkg,
+    /// Unhealthy: switched back to last-known-good.
+    RolledBack(SnapshotVersion),
+}
+
+struct Canary {
+    snapshot: Arc<ConfigSnapshot>,
+    percent: u8,
+}
+
+struct SwitchState {
+    current: Arc<ConfigSnapshot>,
+    activated_at: Instant,
     lkg: Arc<ConfigSnapshot>,
+    history: VecDeque<SnapshotRecord>,
+    canary: Option<Canary>,
+}
+
+/// Holds the live snapshot plus what is needed to undo it: a bounded history,
+/// the last-known-good (LKG) snapshot, and an optional canary that only a
+/// share of tenants read.
+pub struct SnapshotSwitch {
+    state: RwLock<SwitchState>,
+    history_limit: usize,
+    health_window: Duration,
 }
 
 impl SnapshotSwitch {
     pub fn new(initial: Arc<ConfigSnapshot>) -> Self {
+        let now = Instant::now();
         Self {
-            current: RwLock::new(initial.clone()),
-            lkg: initial,
+            state: RwLock::new(SwitchState {
+                current: initial.clone(),
+                activated_at: now,
+                lkg: initial.clone(),
+                history: VecDeque::from([record(initial, now)]),
+                canary: None,
+            }),
+            history_limit: 16,
+            health_window: Duration::from_secs(60),
         }
     }
 
+    /// Number of snapshots kept for `rollback_to` (at least one).
+    pub fn with_history_limit(mut self, limit: usize) -> Self {
+        self.history_limit = limit.max(1);
+        self
+    }
+
+    /// How long a snapshot must stay live and healthy before it becomes LKG.
+    pub fn with_health_window(mut self, window: Duration) -> Self {
+        self.health_window = window;
+        self
+    }
+
+    /// The snapshot every tenant outside a canary reads.
     pub fn get(&self) -> Arc<ConfigSnapshot> {
-        self.current.read().clone()
+        self.state.read().current.clone()
+    }
+
+    /// The canary snapshot if `tenant` falls into the canary share, else the
+    /// current one.
+    pub fn get_for_tenant(&self, tenant: &TenantId) -> Arc<ConfigSnapshot> {
+        let state = self.state.read();
+        match &state.canary {
+            Some(canary) if tenant_bucket(tenant) < canary.percent => canary.snapshot.clone(),
+            _ => state.current.clone(),
+        }
     }
 
+    /// Makes `next` live for everyone, ending any canary.
     pub fn swap(&self, next: Arc<ConfigSnapshot>) {
-        *self.current.write() = next;
+        let mut state = self.state.write();
+        state.canary = None;
+        self.activate(&mut state, next);
     }
 
+    pub fn lkg(&self) -> Arc<ConfigSnapshot> {
+        self.state.read().lkg.clone()
+    }
+
+    /// Switches back to the last-known-good snapshot.
     pub fn rollback(&self) -> Arc<ConfigSnapshot> {
-        let lkg = self.lkg.clone();
-        *self.current.write() = lkg.clone();
+        let mut state = self.state.write();
+        let lkg = state.lkg.clone();
+        state.canary = None;
+        state.current = lkg.clone();
+        state.activated_at = Instant::now();
         lkg
     }
+
+    /// Switches back to a retained version; `None` if it has been evicted.
+    pub fn rollback_to(&self, version: &SnapshotVersion) -> Option<Arc<ConfigSnapshot>> {
+        let mut state = self.state.write();
+        let target = state
+            .history
+            .iter()
+            .rev()
+            .find(|entry| &entry.version == version)
+            .map(|entry| entry.snapshot.clone())?;
+        state.canary = None;
+        state.current = target.clone();
+        state.activated_at = Instant::now();
+        Some(target)
+    }
+
+    /// Retained snapshots, oldest first.
+    pub fn history(&self) -> Vec<SnapshotRecord> {
+        self.state.read().history.iter().cloned().collect()
+    }
+
+    /// Feeds a health-check result for the current snapshot. Healthy results
+    /// promote it to LKG once it has been live for the health window; an
+    /// unhealthy result rolls back to LKG. Either way, nothing changes while
+    /// the LKG is what is live.
+    pub fn report_health(&self, healthy: bool) -> HealthOutcome {
+        let mut state = self.state.write();
+        if Arc::ptr_eq(&state.current, &state.lkg) {
+            return HealthOutcome::AlreadyLkg;
+        }
+        if !healthy {
+            let lkg = state.lkg.clone();
+            state.canary = None;
+            state.current = lkg.clone();
+            state.activated_at = Instant::now();
+            return HealthOutcome::RolledBack(lkg.metadata().version.clone());
+        }
+        let live_for = state.activated_at.elapsed();
+        if live_for < self.health_window {
+            return HealthOutcome::Pending(self.health_window - live_for);
+        }
+        state.lkg = state.current.clone();
+        HealthOutcome::Promoted(state.lkg.metadata().version.clone())
+    }
+
+    /// Serves `next` to `percent`% of tenants (by `TenantId` hash) until
+    /// [`promote_canary`](Self::promote_canary) or
+    /// [`abort_canary`](Self::abort_canary).
+    pub fn start_canary(&self, next: Arc<ConfigSnapshot>, percent: u8) {
+        self.state.write().canary = Some(Canary {
+            snapshot: next,
+            percent: percent.min(100),
+        });
+    }
+
+    pub fn set_canary_percent(&self, percent: u8) {
+        if let Some(canary) = self.state.write().canary.as_mut() {
+            canary.percent = percent.min(100);
+        }
+    }
+
+    /// Replaces the running canary's snapshot, keeping its tenant share;
+    /// `false` when no canary is running.
+    pub fn restage_canary(&self, next: Arc<ConfigSnapshot>) -> bool {
+        match self.state.write().canary.as_mut() {
+            Some(canary) => {
+                canary.snapshot = next;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The snapshot the canary share of tenants reads, if a canary is running.
+    pub fn canary_snapshot(&self) -> Option<Arc<ConfigSnapshot>> {
+        self.state
+            .read()
+            .canary
+            .as_ref()
+            .map(|canary| canary.snapshot.clone())
+    }
+
+    /// Version and tenant share of the running canary.
+    pub fn canary(&self) -> Option<(SnapshotVersion, u8)> {
+        self.state
+            .read()
+            .canary
+            .as_ref()
+            .map(|canary| (canary.snapshot.metadata().version.clone(), canary.percent))
+    }
+
+    /// Makes the canary live for everyone.
+    pub fn promote_canary(&self) -> Option<SnapshotVersion> {
+        let mut state = self.state.write();
+        let canary = state.canary.take()?;
+        let version = canary.snapshot.metadata().version.clone();
+        self.activate(&mut state, canary.snapshot);
+        Some(version)
+    }
+
+    pub fn abort_canary(&self) -> Option<SnapshotVersion> {
+        self.state
+            .write()
+            .canary
+            .take()
+            .map(|canary| canary.snapshot.metadata().version.clone())
+    }
+
+    fn activate(&self, state: &mut SwitchState, next: Arc<ConfigSnapshot>) {
+        let now = Instant::now();
+        state.current = next.clone();
+        state.activated_at = now;
+        state.history.push_back(record(next, now));
+        while state.history.len() > self.history_limit {
+            state.history.pop_front();
+        }
+    }
+}
+
+fn record(snapshot: Arc<ConfigSnapshot>, activated_at: Instant) -> SnapshotRecord {
+    SnapshotRecord {
+        version: snapshot.metadata().version.clone(),
+        checksum: snapshot.checksum().clone(),
+        snapshot,
+        activated_at,
+    }
+}
+
+/// Stable 0..100 bucket, so a tenant stays on the same side of a canary
+/// across processes and restarts.
+pub fn tenant_bucket(tenant: &TenantId) -> u8 {
+    let digest = Sha256::digest(tenant.0.as_bytes());
+    let mut head = [0u8; 8];
+    head.copy_from_slice(&digest[..8]);
+    (u64::from_be_bytes(head) % 100) as u8
 }
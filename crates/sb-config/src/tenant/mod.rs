@@ -0,0 +1,236 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use sb_types::prelude::TenantId;
+use serde::de::DeserializeOwned;
+
+use crate::errors::{self, ConfigError};
+use crate::model::{Checksum, ConfigMap, ConfigValue, KeyPath};
+use crate::snapshot::ConfigSnapshot;
+use crate::source::file::parse_file;
+use crate::source::merge_maps;
+
+#[cfg(feature = "tenant_storage")]
+pub mod storage;
+
+/// Where tenant overlays live. Overlay keys use the same dotted namespaces as
+/// the global tree (`qos.rps`, `llm.model`).
+#[async_trait]
+pub trait OverlayStore: Send + Sync {
+    fn id(&self) -> &str;
+
+    async fn load_all(&self) -> Result<Vec<(TenantId, ConfigMap)>, ConfigError>;
+}
+
+/// One overlay file per tenant in `dir`, named `<tenant>.{json,yaml,toml}`.
+/// A missing directory means no overlays.
+pub struct FileOverlayStore {
+    dir: PathBuf,
+    id: String,
+}
+
+impl FileOverlayStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        let id = dir.display().to_string();
+        Self { dir, id }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+/// Whether `path` names an overlay file by its extension.
+pub(crate) fn is_overlay_file(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("json" | "yaml" | "yml" | "toml")
+    )
+}
+
+#[async_trait]
+impl OverlayStore for FileOverlayStore {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    async fn load_all(&self) -> Result<Vec<(TenantId, ConfigMap)>, ConfigError> {
+        let unavailable =
+            |err: std::io::Error| errors::io_provider_unavailable("tenant", &err.to_string());
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(unavailable(err)),
+        };
+        let mut paths = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(unavailable)?.path();
+            if path.is_file() && is_overlay_file(&path) {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        let mut overlays: Vec<(TenantId, ConfigMap)> = Vec::new();
+        for path in paths {
+            let Some(tenant) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            if overlays.iter().any(|(seen, _)| seen.0 == tenant) {
+                return Err(errors::schema_invalid(
+                    "tenant",
+                    &format!("{}: more than one overlay file for `{tenant}`", self.id),
+                ));
+            }
+            let content = std::fs::read_to_string(&path).map_err(unavailable)?;
+            overlays.push((TenantId(tenant.to_string()), parse_file(&path, &content)?));
+        }
+        Ok(overlays)
+    }
+}
+
+/// Tenant overlays plus the whitelist of keys tenants may override.
+pub struct TenantOverlays {
+    store: Arc<dyn OverlayStore>,
+    allowed: Vec<String>,
+}
+
+impl TenantOverlays {
+    pub fn new(store: Arc<dyn OverlayStore>) -> Self {
+        Self {
+            store,
+            allowed: Vec::new(),
+        }
+    }
+
+    /// Lets tenants override `pattern`: an exact key, or `prefix.*` for
+    /// everything below `prefix`.
+    pub fn allow(mut self, pattern: impl Into<String>) -> Self {
+        self.allowed.push(pattern.into());
+        self
+    }
+
+    pub fn store(&self) -> &dyn OverlayStore {
+        self.store.as_ref()
+    }
+
+    pub fn is_allowed(&self, key: &str) -> bool {
+        self.allowed
+            .iter()
+            .any(|pattern| match pattern.strip_suffix(".*") {
+                Some(prefix) => key
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.starts_with('.')),
+                None => pattern == key,
+            })
+    }
+}
+
+/// A validated overlay as carried by a snapshot.
+#[derive(Clone)]
+pub struct TenantOverlay {
+    pub(crate) data: ConfigMap,
+    pub(crate) checksum: Checksum,
+}
+
+// `data` holds resolved secrets; never print it.
+impl fmt::Debug for TenantOverlay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TenantOverlay")
+            .field("checksum", &self.checksum)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Read access to the snapshot as seen by one tenant: overlay values win,
+/// everything else falls through to the global tree. Nothing is merged until
+/// a key is read.
+#[derive(Clone, Copy)]
+pub struct TenantView<'a> {
+    snapshot: &'a ConfigSnapshot,
+    tenant: &'a TenantId,
+    overlay: Option<&'a TenantOverlay>,
+}
+
+impl fmt::Debug for TenantView<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TenantView")
+            .field("tenant", self.tenant)
+            .field("checksum", self.checksum())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a> TenantView<'a> {
+    pub(crate) fn new(
+        snapshot: &'a ConfigSnapshot,
+        tenant: &'a TenantId,
+        overlay: Option<&'a TenantOverlay>,
+    ) -> Self {
+        Self {
+            snapshot,
+            tenant,
+            overlay,
+        }
+    }
+
+    pub fn tenant(&self) -> &TenantId {
+        self.tenant
+    }
+
+    pub fn snapshot(&self) -> &'a ConfigSnapshot {
+        self.snapshot
+    }
+
+    pub fn has_overlay(&self) -> bool {
+        self.overlay.is_some()
+    }
+
+    /// Checksum of the effective tree for this tenant; the global checksum
+    /// when the tenant has no overlay.
+    pub fn checksum(&self) -> &'a Checksum {
+        match self.overlay {
+            Some(overlay) => &overlay.checksum,
+            None => self.snapshot.checksum(),
+        }
+    }
+
+    pub fn get_raw(&self, path: &KeyPath) -> Option<Cow<'a, ConfigValue>> {
+        let base = self.snapshot.get_raw(path);
+        let Some(overlay) = self.overlay.and_then(|overlay| lookup(&overlay.data, path)) else {
+            return base.map(Cow::Borrowed);
+        };
+        match (base, overlay) {
+            (Some(ConfigValue::Object(base)), ConfigValue::Object(over)) => {
+                let mut merged = base.clone();
+                merge_maps(&mut merged, over);
+                Some(Cow::Owned(ConfigValue::Object(merged)))
+            }
+            _ => Some(Cow::Borrowed(overlay)),
+        }
+    }
+
+    pub fn get<T>(&self, path: &KeyPath) -> Option<T>
+    where
+        T: DeserializeOwned,
+    {
+        self.get_raw(path)
+            .and_then(|value| serde_json::from_value(value.into_owned()).ok())
+    }
+}
+
+fn lookup<'a>(map: &'a ConfigMap, path: &KeyPath) -> Option<&'a ConfigValue> {
+    let mut segments = path.0.split('.').filter(|s| !s.is_empty());
+    let mut cursor = map.get(segments.next()?)?;
+    for segment in segments {
+        cursor = cursor.as_object()?.get(segment)?;
+    }
+    Some(cursor)
+}
+
+pub(crate) type TenantOverlayMap = HashMap<TenantId, TenantOverlay>;
@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use sb_storage::prelude::{make_record_id, Entity, Repository};
+use sb_types::prelude::TenantId;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::ConfigError;
+use crate::model::ConfigMap;
+
+use super::OverlayStore;
+
+/// A tenant's overlay as stored in sb-storage; one record per tenant.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TenantOverlayDoc {
+    pub id: String,
+    pub tenant: TenantId,
+    pub overlay: ConfigMap,
+}
+
+impl TenantOverlayDoc {
+    pub fn new(tenant: TenantId, overlay: ConfigMap) -> Self {
+        Self {
+            id: Self::record_id(&tenant),
+            tenant,
+            overlay,
+        }
+    }
+
+    pub fn record_id(tenant: &TenantId) -> String {
+        make_record_id(Self::TABLE, tenant, "overlay")
+    }
+}
+
+impl Entity for TenantOverlayDoc {
+    const TABLE: &'static str = "config_tenant_overlay";
+    type Key = String;
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+/// Overlays kept in a tenant-scoped repository. Repositories cannot list
+/// across tenants, so the tenants to read are given up front.
+pub struct StorageOverlayStore<R: Repository<TenantOverlayDoc>> {
+    repo: Arc<R>,
+    tenants: Vec<TenantId>,
+}
+
+impl<R: Repository<TenantOverlayDoc>> StorageOverlayStore<R> {
+    pub fn new(repo: Arc<R>, tenants: Vec<TenantId>) -> Self {
+        Self { repo, tenants }
+    }
+
+    pub fn with_tenant(mut self, tenant: TenantId) -> Self {
+        self.tenants.push(tenant);
+        self
+    }
+}
+
+#[async_trait]
+impl<R: Repository<TenantOverlayDoc>> OverlayStore for StorageOverlayStore<R> {
+    fn id(&self) -> &str {
+        TenantOverlayDoc::TABLE
+    }
+
+    async fn load_all(&self) -> Result<Vec<(TenantId, ConfigMap)>, ConfigError> {
+        let mut overlays = Vec::new();
+        for tenant in &self.tenants {
+            let doc = self
+                .repo
+                .get(tenant, &TenantOverlayDoc::record_id(tenant))
+                .await
+                .map_err(|err| ConfigError::from(err.into_inner()))?;
+            if let Some(doc) = doc {
+                overlays.push((tenant.clone(), doc.overlay));
+            }
+        }
+        Ok(overlays)
+    }
+}
@@ -1,8 +1,13 @@
 use async_trait::async_trait;
+use sb_errors::prelude::codes;
+use std::collections::HashMap;
 
+#[cfg(feature = "schema_json")]
+use crate::errors::schema_invalid;
 use crate::{
     errors::ConfigError,
-    model::{ConfigMap, NamespaceId},
+    model::{ConfigMap, ConfigValue, KeyPath, KeyProvenance, NamespaceId, ValueOrigin},
+    schema::{FieldMeta, SchemaRegistry},
 };
 
 #[async_trait]
@@ -21,3 +26,166 @@ pub struct BasicValidator;
 
 #[async_trait]
 impl Validator for BasicValidator {}
+
+/// One failed schema check, located by a JSON pointer from the config root.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct SchemaViolation {
+    pub pointer: String,
+    /// Source that supplied the offending value (`default` if none did).
+    pub source: String,
+    pub message: String,
+}
+
+/// Validates every namespace sub-tree against its registered `SchemaDoc` and
+/// reports all violations at once, attributing each to the source that set
+/// the value according to `provenance`.
+#[allow(clippy::result_large_err)]
+pub fn validate_schemas(
+    map: &ConfigMap,
+    registry: &dyn SchemaRegistry,
+    provenance: &KeyProvenance,
+) -> Result<(), ConfigError> {
+    let mut namespaces = registry.namespaces();
+    namespaces.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut violations = Vec::new();
+    for ns in namespaces {
+        let Some(view) = registry.get_namespace(&ns) else {
+            continue;
+        };
+        let Some(schema) = view
+            .schema
+            .as_ref()
+            .and_then(|doc| serde_json::to_value(doc).ok())
+        else {
+            continue;
+        };
+        let instance = map
+            .get(&ns.0)
+            .cloned()
+            .unwrap_or_else(|| ConfigValue::Object(ConfigMap::new()));
+        check_namespace(
+            &ns,
+            &schema,
+            &instance,
+            &view.fields,
+            provenance,
+            &mut violations,
+        )?;
+    }
+
+    if violations.is_empty() {
+        return Ok(());
+    }
+    let detail = violations
+        .iter()
+        .map(|v| format!("{} ({}): {}", v.pointer, v.source, v.message))
+        .collect::<Vec<_>>()
+        .join("; ");
+    Err(ConfigError::from(
+        ConfigError::builder(codes::SCHEMA_VALIDATION_FAILED)
+            .user_msg("Configuration is invalid.")
+            .dev_msg(format!("schema: {detail}"))
+            .meta_kv(
+                "violations",
+                serde_json::to_value(&violations).unwrap_or_default(),
+            )
+            .build(),
+    ))
+}
+
+#[cfg(feature = "schema_json")]
+#[allow(clippy::result_large_err)]
+fn check_namespace(
+    ns: &NamespaceId,
+    schema: &ConfigValue,
+    instance: &ConfigValue,
+    fields: &HashMap<KeyPath, FieldMeta>,
+    provenance: &KeyProvenance,
+    out: &mut Vec<SchemaViolation>,
+) -> Result<(), ConfigError> {
+    let validator = jsonschema::validator_for(schema)
+        .map_err(|err| schema_invalid("schema", &format!("{}: invalid schema: {err}", ns.0)))?;
+    for error in validator.iter_errors(instance) {
+        let relative: Vec<String> = error
+            .instance_path
+            .as_str()
+            .split('/')
+            .skip(1)
+            .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+            .collect();
+        let key = std::iter::once(ns.0.as_str())
+            .chain(relative.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(".");
+        // Messages quote the offending value, so keep it out for secrets.
+        let message = if is_sensitive(fields, &relative) {
+            let keyword = error.schema_path.as_str().rsplit('/').next().unwrap_or("");
+            format!("value rejected by `{keyword}`")
+        } else {
+            error.to_string()
+        };
+        out.push(SchemaViolation {
+            pointer: format!("/{}{}", ns.0, error.instance_path.as_str()),
+            source: origin_of(provenance, &key),
+            message,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "schema_json"))]
+#[allow(clippy::result_large_err)]
+fn check_namespace(
+    _ns: &NamespaceId,
+    _schema: &ConfigValue,
+    _instance: &ConfigValue,
+    _fields: &HashMap<KeyPath, FieldMeta>,
+    _provenance: &KeyProvenance,
+    _out: &mut Vec<SchemaViolation>,
+) -> Result<(), ConfigError> {
+    Ok(())
+}
+
+#[cfg_attr(not(feature = "schema_json"), allow(dead_code))]
+fn is_sensitive(fields: &HashMap<KeyPath, FieldMeta>, relative: &[String]) -> bool {
+    (1..=relative.len()).any(|len| {
+        fields
+            .get(&KeyPath(relative[..len].join(".")))
+            .is_some_and(|meta| meta.sensitive)
+    })
+}
+
+/// Source of `key` itself, else of the values below it (e.g. for a missing
+/// required property), else of the leaf it sits in (array items).
+#[cfg_attr(not(feature = "schema_json"), allow(dead_code))]
+fn origin_of(provenance: &KeyProvenance, key: &str) -> String {
+    let label = |path: &str| {
+        provenance
+            .get(&KeyPath(path.to_string()))
+            .and_then(|chain| chain.last())
+            .map(ValueOrigin::label)
+    };
+    if let Some(origin) = label(key) {
+        return origin;
+    }
+    let prefix = format!("{key}.");
+    let mut below: Vec<String> = provenance
+        .iter()
+        .filter(|(leaf, _)| leaf.0.starts_with(&prefix))
+        .filter_map(|(_, chain)| chain.last().map(ValueOrigin::label))
+        .collect();
+    if !below.is_empty() {
+        below.sort_unstable();
+        below.dedup();
+        return below.join(", ");
+    }
+    let mut path = key;
+    while let Some(idx) = path.rfind('.') {
+        path = &path[..idx];
+        if let Some(origin) = label(path) {
+            return origin;
+        }
+    }
+    "default".to_string()
+}
@@ -0,0 +1,200 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use notify::{RecursiveMode, Watcher as _};
+use sha2::{Digest, Sha256};
+
+use crate::errors::{self, ConfigError};
+use crate::source::file::FileSource;
+use crate::tenant::is_overlay_file;
+
+use super::{Reloader, Watcher};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchBackend {
+    /// inotify/FSEvents/ReadDirectoryChanges via `notify`.
+    Native,
+    /// Re-hash the files every `poll_interval`; works on any filesystem,
+    /// including network mounts and container volumes without inotify.
+    Poll,
+}
+
+/// Watches the files of a `FileSource` (and optionally a tenant overlay
+/// directory) and drives a `Reloader` once changes have settled for the
+/// debounce window.
+pub struct FsWatcher {
+    reloader: Arc<Reloader>,
+    paths: Vec<PathBuf>,
+    overlay_dir: Option<PathBuf>,
+    backend: WatchBackend,
+    debounce: Duration,
+    poll_interval: Duration,
+}
+
+impl FsWatcher {
+    /// Watches every file of `source`, including files pulled in through
+    /// `include`, as resolved at construction time.
+    pub fn new(reloader: Arc<Reloader>, source: &FileSource) -> Self {
+        Self {
+            reloader,
+            paths: source
+                .resolved_paths()
+                .unwrap_or_else(|_| source.paths.clone()),
+            overlay_dir: None,
+            backend: WatchBackend::Native,
+            debounce: Duration::from_millis(250),
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+
+    /// Also reload when an overlay file in `dir` (see `FileOverlayStore`) is
+    /// added, edited or removed. Pair with `Reloader::with_overlays`.
+    pub fn with_overlay_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.overlay_dir = Some(dir.into());
+        self
+    }
+
+    pub fn with_backend(mut self, backend: WatchBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Content digest per path, then one for the overlay directory; `None`
+    /// for files that are missing or unreadable.
+    fn fingerprint(&self) -> Vec<Option<Vec<u8>>> {
+        let mut digests: Vec<_> = self
+            .paths
+            .iter()
+            .map(|path| {
+                std::fs::read(path)
+                    .ok()
+                    .map(|bytes| Sha256::digest(bytes).to_vec())
+            })
+            .collect();
+        if let Some(dir) = &self.overlay_dir {
+            digests.push(overlay_digest(dir));
+        }
+        digests
+    }
+
+    async fn run_poll(&self) -> Result<(), ConfigError> {
+        let mut last = self.fingerprint();
+        loop {
+            tokio::time::sleep(self.poll_interval).await;
+            let mut settled = self.fingerprint();
+            if settled == last {
+                continue;
+            }
+            loop {
+                tokio::time::sleep(self.debounce).await;
+                let again = self.fingerprint();
+                if again == settled {
+                    break;
+                }
+                settled = again;
+            }
+            last = settled;
+            self.reloader.reload().await;
+        }
+    }
+
+    async fn run_native(&self) -> Result<(), ConfigError> {
+        // Watch parent directories so editors that replace files via rename
+        // are still observed.
+        let mut targets = BTreeSet::new();
+        let mut dirs = BTreeSet::new();
+        for path in &self.paths {
+            let parent = match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                _ => PathBuf::from("."),
+            };
+            let parent = parent.canonicalize().map_err(|err| {
+                errors::io_provider_unavailable("watch", &format!("{}: {err}", parent.display()))
+            })?;
+            if let Some(name) = path.file_name() {
+                targets.insert(parent.join(name));
+            }
+            dirs.insert(parent);
+        }
+        let overlay_dir = match &self.overlay_dir {
+            Some(dir) => {
+                let dir = dir.canonicalize().map_err(|err| {
+                    errors::io_provider_unavailable("watch", &format!("{}: {err}", dir.display()))
+                })?;
+                dirs.insert(dir.clone());
+                Some(dir)
+            }
+            None => None,
+        };
+        let relevant = |path: &PathBuf| {
+            targets.contains(path)
+                || overlay_dir
+                    .as_deref()
+                    .is_some_and(|dir| path.parent() == Some(dir) && is_overlay_file(path))
+        };
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            if let Ok(event) = res {
+                let _ = tx.send(event);
+            }
+        })
+        .map_err(|err| errors::io_provider_unavailable("watch", &err.to_string()))?;
+        for dir in &dirs {
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .map_err(|err| errors::io_provider_unavailable("watch", &err.to_string()))?;
+        }
+
+        while let Some(event) = rx.recv().await {
+            if event.kind.is_access() || !event.paths.iter().any(relevant) {
+                continue;
+            }
+            while let Ok(Some(_)) = tokio::time::timeout(self.debounce, rx.recv()).await {}
+            self.reloader.reload().await;
+        }
+        Ok(())
+    }
+}
+
+/// One digest over the names and contents of the overlay files in `dir`;
+/// `None` when the directory cannot be read.
+fn overlay_digest(dir: &Path) -> Option<Vec<u8>> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| is_overlay_file(path))
+        .collect();
+    files.sort();
+    let mut hasher = Sha256::new();
+    for path in files {
+        hasher.update(path.file_name()?.as_encoded_bytes());
+        hasher.update(Sha256::digest(std::fs::read(&path).unwrap_or_default()));
+    }
+    Some(hasher.finalize().to_vec())
+}
+
+#[async_trait]
+impl Watcher for FsWatcher {
+    /// Runs until the task is cancelled; reload failures are reported through
+    /// `Reloader::subscribe` rather than ending the loop.
+    async fn run(&self) -> Result<(), ConfigError> {
+        match self.backend {
+            WatchBackend::Native => self.run_native().await,
+            WatchBackend::Poll => self.run_poll().await,
+        }
+    }
+}
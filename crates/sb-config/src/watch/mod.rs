@@ -0,0 +1,181 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+use tokio::sync::{broadcast, Mutex};
+
+use crate::errors::ConfigError;
+use crate::events::{ConfigErrorEvent, ConfigUpdateEvent};
+use crate::loader::Loader;
+use crate::model::ReloadClass;
+use crate::snapshot::ConfigSnapshot;
+use crate::switch::SnapshotSwitch;
+use crate::tenant::TenantOverlays;
+
+#[cfg(feature = "watch_fs")]
+pub mod fs;
+#[cfg(any(
+    feature = "remote_consul",
+    feature = "remote_etcd",
+    feature = "remote_git"
+))]
+pub mod remote;
+
+#[derive(Clone, Debug)]
+pub struct WatchEvent {
+    pub snapshot: Arc<ConfigSnapshot>,
+}
+
+#[async_trait]
+pub trait Watcher: Send + Sync {
+    async fn run(&self) -> Result<(), ConfigError>;
+}
+
+#[derive(Default)]
+pub struct NoopWatcher;
+
+#[async_trait]
+impl Watcher for NoopWatcher {
+    async fn run(&self) -> Result<(), ConfigError> {
+        Ok(())
+    }
+}
+
+/// Notification broadcast to subscribers after every reload attempt.
+#[derive(Clone, Debug)]
+pub enum ConfigChange {
+    Updated(ConfigUpdateEvent),
+    Failed(ConfigErrorEvent),
+}
+
+#[derive(Clone, Debug)]
+pub enum ReloadOutcome {
+    Applied(ConfigUpdateEvent),
+    /// Sources were re-read but produced the same configuration.
+    Unchanged,
+    Rejected(ConfigErrorEvent),
+}
+
+/// Re-runs the loader against the current snapshot and swaps the switch when
+/// the result is valid and every changed key may be hot-reloaded.
+///
+/// Watchers only decide *when* to reload; this type decides *whether* the new
+/// snapshot goes live, so every trigger shares the same safety rules.
+pub struct Reloader {
+    loader: Loader,
+    overlays: Option<Arc<TenantOverlays>>,
+    switch: Arc<SnapshotSwitch>,
+    events: broadcast::Sender<ConfigChange>,
+    canary_percent: Option<u8>,
+    // Serialises reloads so two triggers never race on the same baseline.
+    gate: Mutex<()>,
+}
+
+impl Reloader {
+    pub fn new(loader: Loader, switch: Arc<SnapshotSwitch>) -> Self {
+        let (events, _) = broadcast::channel(64);
+        Self {
+            loader,
+            overlays: None,
+            switch,
+            events,
+            canary_percent: None,
+            gate: Mutex::new(()),
+        }
+    }
+
+    /// Stage accepted snapshots as a canary for `percent`% of tenants instead
+    /// of swapping them in; promote with `SnapshotSwitch::promote_canary`.
+    ///
+    /// While a canary runs, reloads compare against it: changes restage it at
+    /// its current share, and sources reverting to the live snapshot abort it.
+    pub fn with_canary(mut self, percent: u8) -> Self {
+        self.canary_percent = Some(percent);
+        self
+    }
+
+    /// Reload tenant overlays along with the global tree.
+    pub fn with_overlays(mut self, overlays: Arc<TenantOverlays>) -> Self {
+        self.overlays = Some(overlays);
+        self
+    }
+
+    pub fn switch(&self) -> &Arc<SnapshotSwitch> {
+        &self.switch
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ConfigChange> {
+        self.events.subscribe()
+    }
+
+    pub async fn reload(&self) -> ReloadOutcome {
+        let _guard = self.gate.lock().await;
+        let live = self.switch.get();
+        let current = self
+            .switch
+            .canary_snapshot()
+            .unwrap_or_else(|| live.clone());
+        let loaded = self
+            .loader
+            .load_snapshot(Some(&current), self.overlays.as_deref())
+            .await;
+        let (next, event, tenant_failures) = match loaded {
+            Ok(loaded) => loaded,
+            Err(err) => return self.reject("load", err.to_string()),
+        };
+        // Rejected overlays only hold back their own tenant.
+        for failure in tenant_failures {
+            let _ = self.events.send(ConfigChange::Failed(failure));
+        }
+        if event.changed_keys.is_empty() {
+            return ReloadOutcome::Unchanged;
+        }
+
+        let boot_only: Vec<&str> = event
+            .changed_keys
+            .iter()
+            .filter(|key| current.reload_class(key) == Some(ReloadClass::BootOnly))
+            .map(|key| key.0.as_str())
+            .collect();
+        if !boot_only.is_empty() {
+            return self.reject(
+                "reload_class",
+                format!(
+                    "boot-only keys changed, restart required: {}",
+                    boot_only.join(", ")
+                ),
+            );
+        }
+
+        let next = Arc::new(next);
+        match self.canary_percent {
+            Some(_) if next.checksum() == live.checksum() => {
+                self.switch.abort_canary();
+            }
+            Some(percent) => {
+                if !self.switch.restage_canary(next.clone()) {
+                    self.switch.start_canary(next, percent);
+                }
+            }
+            None => self.switch.swap(next),
+        }
+        let _ = self.events.send(ConfigChange::Updated(event.clone()));
+        ReloadOutcome::Applied(event)
+    }
+
+    /// Broadcasts a failure that happened outside `reload`, e.g. a watcher
+    /// losing its connection to a remote source.
+    pub fn report_failure(&self, phase: &str, message: String) {
+        let _ = self.events.send(ConfigChange::Failed(ConfigErrorEvent {
+            phase: phase.to_string(),
+            message,
+        }));
+    }
+
+    fn reject(&self, phase: &str, message: String) -> ReloadOutcome {
+        let event = ConfigErrorEvent {
+            phase: phase.to_string(),
+            message,
+        };
+        let _ = self.events.send(ConfigChange::Failed(event.clone()));
+        ReloadOutcome::Rejected(event)
+    }
+}
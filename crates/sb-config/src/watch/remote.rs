@@ -0,0 +1,63 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::errors::ConfigError;
+
+use super::{Reloader, Watcher};
+
+/// A remote backend that can tell when its data changed (Consul blocking
+/// queries, etcd watches, Git ref polling).
+#[async_trait]
+pub trait ChangeFeed: Send + Sync {
+    /// Waits for the next change. `Ok(false)` means the wait ended without
+    /// one (e.g. a blocking query timed out) and should simply be retried.
+    async fn wait_for_change(&self) -> Result<bool, ConfigError>;
+}
+
+/// Drives a `Reloader` from a remote source's `ChangeFeed`.
+pub struct RemoteWatcher {
+    reloader: Arc<Reloader>,
+    feed: Arc<dyn ChangeFeed>,
+    retry: Duration,
+}
+
+impl RemoteWatcher {
+    /// `feed` is normally the same source instance the loader reads, so the
+    /// change cursor and the loaded data stay in step.
+    pub fn new(reloader: Arc<Reloader>, feed: Arc<dyn ChangeFeed>) -> Self {
+        Self {
+            reloader,
+            feed,
+            retry: Duration::from_secs(5),
+        }
+    }
+
+    /// Pause after a failed wait before asking the backend again.
+    pub fn with_retry(mut self, retry: Duration) -> Self {
+        self.retry = retry;
+        self
+    }
+}
+
+#[async_trait]
+impl Watcher for RemoteWatcher {
+    /// Runs until the task is cancelled; backend failures are reported
+    /// through `Reloader::subscribe` and retried.
+    async fn run(&self) -> Result<(), ConfigError> {
+        loop {
+            match self.feed.wait_for_change().await {
+                Ok(true) => {
+                    self.reloader.reload().await;
+                }
+                Ok(false) => {}
+                Err(err) => {
+                    let message = err.0.message_dev.clone().unwrap_or_else(|| err.to_string());
+                    self.reloader.report_failure("watch", message);
+                    tokio::time::sleep(self.retry).await;
+                }
+            }
+        }
+    }
+}
@@ -30,8 +30,8 @@ fn load_minimal_snapshot_and_read() {
                 separator: "__".into(),
             }),
         ],
-        secrets: vec![Arc::new(NoopSecretResolver::default())],
-        validator: Arc::new(BasicValidator::default()),
+        secrets: vec![Arc::new(NoopSecretResolver)],
+        validator: Arc::new(BasicValidator),
         schema_registry: registry.clone(),
     };
 
@@ -47,3 +47,1513 @@ fn load_minimal_snapshot_and_read() {
         .expect("snapshot");
     assert!(event2.changed_keys.is_empty());
 }
+
+fn temp_config(label: &str, body: &str) -> std::path::PathBuf {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let path = std::env::temp_dir().join(format!(
+        "sb-config-{label}-{}-{nanos}.json",
+        std::process::id()
+    ));
+    std::fs::write(&path, body).unwrap();
+    path
+}
+
+fn reload_loader(path: &std::path::Path) -> Loader {
+    let registry = Arc::new(InMemorySchemaRegistry::new());
+    let field = |reload| FieldMeta {
+        reload,
+        sensitive: false,
+        default_value: None,
+        description: None,
+    };
+    registry
+        .register_namespace(
+            NamespaceId("app".into()),
+            None,
+            HashMap::from([
+                (KeyPath("name".into()), field(ReloadClass::HotReloadSafe)),
+                (KeyPath("port".into()), field(ReloadClass::BootOnly)),
+            ]),
+        )
+        .unwrap();
+    Loader {
+        sources: vec![Arc::new(FileSource {
+            paths: vec![path.to_path_buf()],
+        })],
+        secrets: vec![],
+        validator: Arc::new(BasicValidator),
+        schema_registry: registry,
+    }
+}
+
+#[test]
+fn reloader_swaps_valid_changes_and_refuses_boot_only_keys() {
+    let path = temp_config("reload", r#"{"app":{"name":"a","port":80}}"#);
+    let loader = reload_loader(&path);
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let initial = loader.load_once().await.unwrap();
+        let switch = Arc::new(SnapshotSwitch::new(Arc::new(initial)));
+        let reloader = Reloader::new(loader, switch.clone());
+        let mut changes = reloader.subscribe();
+
+        assert!(matches!(reloader.reload().await, ReloadOutcome::Unchanged));
+
+        std::fs::write(&path, r#"{"app":{"name":"b","port":80}}"#).unwrap();
+        assert!(matches!(reloader.reload().await, ReloadOutcome::Applied(_)));
+        match changes.recv().await.unwrap() {
+            ConfigChange::Updated(event) => {
+                assert_eq!(event.changed_keys, vec![KeyPath("app.name".into())])
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        let name: Option<String> = switch.get().get(&KeyPath("app.name".into()));
+        assert_eq!(name.as_deref(), Some("b"));
+
+        std::fs::write(&path, r#"{"app":{"name":"c","port":81}}"#).unwrap();
+        match reloader.reload().await {
+            ReloadOutcome::Rejected(event) => {
+                assert_eq!(event.phase, "reload_class");
+                assert!(event.message.contains("app.port"));
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        std::fs::write(&path, r#"{"app":{"name":"d","port":80,"bogus":1}}"#).unwrap();
+        assert!(matches!(
+            reloader.reload().await,
+            ReloadOutcome::Rejected(ref e) if e.phase == "load"
+        ));
+        let name: Option<String> = switch.get().get(&KeyPath("app.name".into()));
+        assert_eq!(
+            name.as_deref(),
+            Some("b"),
+            "rejected reloads keep the live snapshot"
+        );
+    });
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn canary_reloads_restage_the_canary_and_abort_on_revert() {
+    let path = temp_config("canary", r#"{"app":{"name":"a","port":80}}"#);
+    let loader = reload_loader(&path);
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let initial = loader.load_once().await.unwrap();
+        let switch = Arc::new(SnapshotSwitch::new(Arc::new(initial)));
+        let reloader = Reloader::new(loader, switch.clone()).with_canary(10);
+        let canary_name = |switch: &SnapshotSwitch| -> Option<String> {
+            switch.canary_snapshot()?.get(&KeyPath("app.name".into()))
+        };
+
+        std::fs::write(&path, r#"{"app":{"name":"b","port":80}}"#).unwrap();
+        assert!(matches!(reloader.reload().await, ReloadOutcome::Applied(_)));
+        switch.set_canary_percent(40);
+        assert!(matches!(reloader.reload().await, ReloadOutcome::Unchanged));
+
+        std::fs::write(&path, r#"{"app":{"name":"c","port":80}}"#).unwrap();
+        assert!(matches!(reloader.reload().await, ReloadOutcome::Applied(_)));
+        assert_eq!(canary_name(&switch).as_deref(), Some("c"));
+        assert_eq!(switch.canary().map(|(_, percent)| percent), Some(40));
+
+        std::fs::write(&path, r#"{"app":{"name":"a","port":80}}"#).unwrap();
+        assert!(matches!(reloader.reload().await, ReloadOutcome::Applied(_)));
+        assert!(switch.canary().is_none(), "reverted sources end the canary");
+        let name: Option<String> = switch.get().get(&KeyPath("app.name".into()));
+        assert_eq!(name.as_deref(), Some("a"));
+    });
+    std::fs::remove_file(&path).ok();
+}
+
+#[cfg(feature = "watch_fs")]
+#[test]
+fn fs_watcher_debounces_and_notifies_subscribers() {
+    use std::time::Duration;
+
+    let path = temp_config("watch", r#"{"app":{"name":"a","port":80}}"#);
+    let loader = reload_loader(&path);
+    let source = FileSource {
+        paths: vec![path.clone()],
+    };
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let initial = loader.load_once().await.unwrap();
+        let switch = Arc::new(SnapshotSwitch::new(Arc::new(initial)));
+        let reloader = Arc::new(Reloader::new(loader, switch.clone()));
+        let mut changes = reloader.subscribe();
+        let watcher = FsWatcher::new(reloader, &source)
+            .with_backend(WatchBackend::Poll)
+            .with_poll_interval(Duration::from_millis(20))
+            .with_debounce(Duration::from_millis(40));
+        let task = tokio::spawn(async move { watcher.run().await });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        for name in ["b", "c", "d"] {
+            let body = format!(r#"{{"app":{{"name":"{name}","port":80}}}}"#);
+            std::fs::write(&path, body).unwrap();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        let change = tokio::time::timeout(Duration::from_secs(5), changes.recv())
+            .await
+            .expect("reload within timeout")
+            .unwrap();
+        assert!(matches!(change, ConfigChange::Updated(_)));
+        let name: Option<String> = switch.get().get(&KeyPath("app.name".into()));
+        assert_eq!(name.as_deref(), Some("d"));
+        task.abort();
+    });
+    std::fs::remove_file(&path).ok();
+}
+
+#[cfg(feature = "watch_fs")]
+#[test]
+fn fs_watcher_reloads_when_only_an_overlay_changes() {
+    use sb_types::prelude::TenantId;
+    use std::time::Duration;
+
+    let path = temp_config("watch-overlay", r#"{"app":{"name":"a","port":80}}"#);
+    let dir = path.with_extension("tenants");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("acme.json"), r#"{"app":{"name":"acme"}}"#).unwrap();
+    let loader = reload_loader(&path);
+    let overlays =
+        Arc::new(TenantOverlays::new(Arc::new(FileOverlayStore::new(&dir))).allow("app.name"));
+    let source = FileSource {
+        paths: vec![path.clone()],
+    };
+    let acme = TenantId("acme".into());
+    let name = KeyPath("app.name".into());
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let initial = loader.load_with_overlays(None, &overlays).await.unwrap().0;
+        let switch = Arc::new(SnapshotSwitch::new(Arc::new(initial)));
+        let reloader = Arc::new(Reloader::new(loader, switch.clone()).with_overlays(overlays));
+        let mut changes = reloader.subscribe();
+        let watcher = FsWatcher::new(reloader, &source)
+            .with_overlay_dir(&dir)
+            .with_backend(WatchBackend::Poll)
+            .with_poll_interval(Duration::from_millis(20))
+            .with_debounce(Duration::from_millis(40));
+        let task = tokio::spawn(async move { watcher.run().await });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        std::fs::write(dir.join("acme.json"), r#"{"app":{"name":"acme-2"}}"#).unwrap();
+        let change = tokio::time::timeout(Duration::from_secs(5), changes.recv())
+            .await
+            .expect("reload within timeout")
+            .unwrap();
+        let ConfigChange::Updated(event) = change else {
+            panic!("expected an update, got {change:?}");
+        };
+        assert_eq!(event.changed_keys, vec![name.clone()]);
+        let live = switch.get();
+        assert_eq!(
+            live.for_tenant(&acme).get::<String>(&name).as_deref(),
+            Some("acme-2")
+        );
+        assert_eq!(live.get::<String>(&name).as_deref(), Some("a"));
+        task.abort();
+    });
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+#[cfg(all(feature = "yaml", feature = "toml"))]
+fn file_source_reads_yaml_toml_with_includes_and_env() {
+    let dir = temp_config("formats", "{}").with_extension("d");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("base.yaml"),
+        "app:\n  name: base\n  port: 80\n---\n# was ${SB_CONFIG_TEST_UNSET}\napp:\n  port: ${SB_CONFIG_TEST_PORT:-8080}\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("app.toml"),
+        "include = [\"base.yaml\"]\n\n[app]\nname = \"${SB_CONFIG_TEST_UNSET:-from-toml}\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("broken.toml"),
+        "[app]\nname = \"ok\"\nport = = 1\n",
+    )
+    .unwrap();
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let source = FileSource {
+        paths: vec![dir.join("app.toml")],
+    };
+    let snap = rt.block_on(source.load()).expect("load");
+    // Interpolated values stay strings until the loader applies the schema.
+    assert_eq!(
+        snap.map["app"],
+        json!({ "name": "from-toml", "port": "8080" })
+    );
+    let mut interpolated: Vec<_> = snap.interpolated.iter().map(|key| key.0.as_str()).collect();
+    interpolated.sort();
+    assert_eq!(interpolated, ["app.name", "app.port"]);
+    assert!(!snap.map.contains_key("include"));
+    assert_eq!(snap.provenance.0.len(), 2);
+    assert_eq!(
+        source.resolved_paths().unwrap(),
+        vec![dir.join("base.yaml"), dir.join("app.toml")]
+    );
+
+    let broken = FileSource {
+        paths: vec![dir.join("broken.toml")],
+    };
+    let err = rt.block_on(broken.load()).unwrap_err().into_inner();
+    let detail = err.message_dev.unwrap();
+    assert!(detail.contains("broken.toml:3:"), "{detail}");
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+#[cfg(feature = "schema_json")]
+fn file_sources_coerce_only_interpolated_values() {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "port": { "type": "integer" },
+            "name": { "type": "string" }
+        }
+    });
+    let registry = Arc::new(InMemorySchemaRegistry::new());
+    registry
+        .register_namespace(
+            NamespaceId("app".into()),
+            Some(serde_json::from_value(schema).unwrap()),
+            ["port", "name"]
+                .into_iter()
+                .map(|key| {
+                    let meta = FieldMeta {
+                        reload: ReloadClass::HotReloadSafe,
+                        sensitive: false,
+                        default_value: None,
+                        description: None,
+                    };
+                    (KeyPath(key.into()), meta)
+                })
+                .collect(),
+        )
+        .unwrap();
+    let loader = |path: &std::path::Path| Loader {
+        sources: vec![Arc::new(FileSource {
+            paths: vec![path.to_path_buf()],
+        })],
+        secrets: vec![],
+        validator: Arc::new(BasicValidator),
+        schema_registry: registry.clone(),
+    };
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    let interpolated = temp_config(
+        "interpolated",
+        r#"{ "app": { "port": "${SB_CONFIG_TEST_UNSET:-8080}", "name": "svc" } }"#,
+    );
+    let snapshot = rt
+        .block_on(loader(&interpolated).load_once())
+        .expect("coerced snapshot");
+    assert_eq!(snapshot.get::<u32>(&KeyPath("app.port".into())), Some(8080));
+
+    // A quoted literal is what the author wrote, so it stays a string.
+    let literal = temp_config("literal", r#"{ "app": { "port": "9090" } }"#);
+    let source = FileSource {
+        paths: vec![literal.clone()],
+    };
+    let snap = rt.block_on(source.load()).expect("load");
+    assert_eq!(snap.map["app"]["port"], json!("9090"));
+    assert!(snap.interpolated.is_empty());
+    let err = rt
+        .block_on(loader(&literal).load_once())
+        .unwrap_err()
+        .into_inner();
+    let detail = err.message_dev.unwrap();
+    assert!(detail.contains("/app/port"), "{detail}");
+
+    std::fs::remove_file(&interpolated).ok();
+    std::fs::remove_file(&literal).ok();
+}
+
+#[test]
+fn string_sources_are_coerced_to_schema_types() {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "outbox": {
+                "type": "object",
+                "properties": {
+                    "batch": { "type": "integer" },
+                    "enabled": { "type": "boolean" },
+                    "ratio": { "type": ["number", "null"] },
+                    "weights": { "type": "array", "items": { "type": "integer" } },
+                    "limits": { "$ref": "#/definitions/Limits" },
+                    "label": { "type": "string" }
+                }
+            }
+        },
+        "definitions": { "Limits": { "type": "object" } }
+    });
+    let field = || FieldMeta {
+        reload: ReloadClass::HotReloadSafe,
+        sensitive: false,
+        default_value: None,
+        description: None,
+    };
+    let registry = Arc::new(InMemorySchemaRegistry::new());
+    registry
+        .register_namespace(
+            NamespaceId("tx".into()),
+            Some(serde_json::from_value(schema).unwrap()),
+            [
+                "outbox",
+                "outbox.batch",
+                "outbox.enabled",
+                "outbox.ratio",
+                "outbox.weights",
+                "outbox.limits",
+                "outbox.limits.max",
+                "outbox.label",
+            ]
+            .into_iter()
+            .map(|key| (KeyPath(key.into()), field()))
+            .collect(),
+        )
+        .unwrap();
+    let loader = |args: &[&str]| Loader {
+        sources: vec![Arc::new(CliArgsSource {
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        })],
+        secrets: vec![],
+        validator: Arc::new(BasicValidator),
+        schema_registry: registry.clone(),
+    };
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let snapshot = rt
+        .block_on(
+            loader(&[
+                "--tx.outbox.batch=50",
+                "--tx.outbox.enabled=on",
+                "--tx.outbox.ratio=0.25",
+                "--tx.outbox.weights=3, 5,8",
+                "--tx.outbox.limits={\"max\": 4}",
+                "--tx.outbox.label=007",
+            ])
+            .load_once(),
+        )
+        .expect("coerced snapshot");
+    assert_eq!(
+        snapshot.get::<u32>(&KeyPath("tx.outbox.batch".into())),
+        Some(50)
+    );
+    assert_eq!(
+        snapshot.get::<bool>(&KeyPath("tx.outbox.enabled".into())),
+        Some(true)
+    );
+    assert_eq!(
+        snapshot.get::<f64>(&KeyPath("tx.outbox.ratio".into())),
+        Some(0.25)
+    );
+    assert_eq!(
+        snapshot.get::<Vec<u32>>(&KeyPath("tx.outbox.weights".into())),
+        Some(vec![3, 5, 8])
+    );
+    assert_eq!(
+        snapshot.get::<u32>(&KeyPath("tx.outbox.limits.max".into())),
+        Some(4)
+    );
+    assert_eq!(
+        snapshot.get::<String>(&KeyPath("tx.outbox.label".into())),
+        Some("007".into())
+    );
+
+    let err = rt
+        .block_on(loader(&["--tx.outbox.batch=lots", "--tx.outbox.weights=1,x"]).load_once())
+        .unwrap_err()
+        .into_inner();
+    let detail = err.message_dev.unwrap();
+    assert!(
+        detail.contains("cli: tx.outbox.batch: expected integer"),
+        "{detail}"
+    );
+    assert!(
+        detail.contains("cli: tx.outbox.weights: item 1"),
+        "{detail}"
+    );
+}
+
+#[cfg(feature = "schema_json")]
+#[derive(Default, serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum DbMode {
+    #[default]
+    Primary,
+    Replica,
+}
+
+#[cfg(feature = "schema_json")]
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+#[allow(dead_code)]
+struct DbConfig {
+    /// Connection string.
+    url: String,
+    #[schemars(range(min = 1, max = 64))]
+    pool: u32,
+    #[serde(default)]
+    mode: DbMode,
+    password: Option<String>,
+    #[serde(default)]
+    labels: HashMap<String, String>,
+}
+
+#[cfg(feature = "schema_json")]
+impl ConfigNamespace for DbConfig {
+    const NAMESPACE: &'static str = "db";
+
+    fn annotations() -> Vec<FieldAnnotation> {
+        vec![
+            FieldAnnotation::new("url").reload(ReloadClass::BootOnly),
+            FieldAnnotation::new("password").sensitive(),
+        ]
+    }
+}
+
+#[test]
+#[cfg(feature = "schema_json")]
+fn namespace_schema_is_enforced_with_pointers_and_sources() {
+    let registry = Arc::new(InMemorySchemaRegistry::new());
+    register_config::<DbConfig>(registry.as_ref()).expect("register");
+    let ns = NamespaceId("db".into());
+    let meta = |key: &str| registry.field_meta(&ns, &KeyPath(key.into())).unwrap();
+    assert_eq!(meta("url").reload, ReloadClass::BootOnly);
+    assert_eq!(
+        meta("url").description.as_deref(),
+        Some("Connection string.")
+    );
+    assert_eq!(meta("pool").reload, ReloadClass::HotReloadSafe);
+    assert!(meta("password").sensitive);
+    assert_eq!(meta("mode").default_value, Some(json!("primary")));
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let load = |body: &str, args: &[&str]| {
+        let path = temp_config("schema", body);
+        let loader = Loader {
+            sources: vec![
+                Arc::new(FileSource {
+                    paths: vec![path.clone()],
+                }),
+                Arc::new(CliArgsSource {
+                    args: args.iter().map(|arg| arg.to_string()).collect(),
+                }),
+            ],
+            secrets: vec![],
+            validator: Arc::new(BasicValidator),
+            schema_registry: registry.clone(),
+        };
+        let result = rt.block_on(loader.load_once());
+        std::fs::remove_file(&path).ok();
+        (result, path)
+    };
+
+    let (ok, _) = load(
+        r#"{"db": {"url": "pg://a", "pool": 8, "labels": {"team": "core"}}}"#,
+        &["--db.pool=9"],
+    );
+    let snapshot = ok.expect("valid config");
+    assert_eq!(snapshot.get::<u32>(&KeyPath("db.pool".into())), Some(9));
+    assert_eq!(
+        snapshot.get::<String>(&KeyPath("db.mode".into())),
+        Some("primary".into())
+    );
+
+    let (err, path) = load(
+        r#"{"db": {"pool": 100, "mode": "standby", "password": 42}}"#,
+        &["--db.pool=0"],
+    );
+    let err = err.unwrap_err().into_inner();
+    let violations = err.meta["violations"].as_array().unwrap();
+    let find = |pointer: &str| {
+        violations
+            .iter()
+            .find(|v| v["pointer"] == pointer)
+            .unwrap_or_else(|| panic!("no violation at {pointer}: {violations:?}"))
+    };
+    assert_eq!(find("/db/pool")["source"], "cli");
+    assert_eq!(
+        find("/db/mode")["source"],
+        format!("file:{}", path.display()).as_str()
+    );
+    assert!(find("/db")["message"].as_str().unwrap().contains("url"));
+    let secret = find("/db/password")["message"].as_str().unwrap();
+    assert!(!secret.contains("42"), "{secret}");
+    assert_eq!(violations.len(), 4);
+
+    // Only the flags namespace is free-form as a whole; other map-typed
+    // namespaces still need their keys registered.
+    registry
+        .register_namespace(
+            NamespaceId("tags".into()),
+            Some(
+                serde_json::from_value(json!({
+                    "type": "object",
+                    "additionalProperties": { "type": "string" }
+                }))
+                .unwrap(),
+            ),
+            HashMap::new(),
+        )
+        .unwrap();
+    let (err, _) = load(
+        r#"{"db": {"url": "pg://a"}, "tags": {"team": "core"}}"#,
+        &[],
+    );
+    let detail = err
+        .unwrap_err()
+        .into_inner()
+        .message_dev
+        .unwrap_or_default();
+    assert!(detail.contains("unknown key: tags.team"), "{detail}");
+}
+
+#[test]
+fn snapshot_explains_override_chain_and_redacts_sensitive_keys() {
+    let dir = temp_config("explain", "{}").with_extension("d");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("base.json"),
+        r#"{"app": {"name": "base", "port": 80, "token": "s3cr3t"}}"#,
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("app.json"),
+        r#"{"include": "base.json", "app": {"port": 81}}"#,
+    )
+    .unwrap();
+
+    let meta = |sensitive: bool, default_value: Option<serde_json::Value>| FieldMeta {
+        reload: ReloadClass::HotReloadSafe,
+        sensitive,
+        default_value,
+        description: None,
+    };
+    let registry = Arc::new(InMemorySchemaRegistry::new());
+    registry
+        .register_namespace(
+            NamespaceId("app".into()),
+            None,
+            HashMap::from([
+                (KeyPath("name".into()), meta(false, None)),
+                (KeyPath("port".into()), meta(false, None)),
+                (KeyPath("token".into()), meta(true, None)),
+                (KeyPath("region".into()), meta(false, Some(json!("eu")))),
+            ]),
+        )
+        .unwrap();
+    let loader = Loader {
+        sources: vec![
+            Arc::new(FileSource {
+                paths: vec![dir.join("app.json")],
+            }),
+            Arc::new(CliArgsSource {
+                args: vec!["--app.port=82".into()],
+            }),
+        ],
+        secrets: vec![],
+        validator: Arc::new(BasicValidator),
+        schema_registry: registry,
+    };
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let snapshot = rt.block_on(loader.load_once()).expect("snapshot");
+
+    let port = snapshot.explain(&KeyPath("app.port".into())).unwrap();
+    let chain: Vec<_> = port
+        .chain
+        .iter()
+        .map(|origin| (origin.label(), origin.value.clone()))
+        .collect();
+    let file = |name: &str| format!("file:{}", dir.join(name).display());
+    assert_eq!(
+        chain,
+        vec![
+            (file("base.json"), json!(80)),
+            (file("app.json"), json!(81)),
+            ("cli".to_string(), json!("82")),
+        ]
+    );
+    assert_eq!(port.value, Some(json!("82")));
+
+    let region = snapshot.explain(&KeyPath("app.region".into())).unwrap();
+    assert_eq!(region.chain[0].source, "default");
+
+    let token = snapshot.explain(&KeyPath("app.token".into())).unwrap();
+    assert!(token.sensitive);
+    assert_eq!(token.value, Some(json!(REDACTED)));
+    assert_eq!(token.chain[0].value, json!(REDACTED));
+    assert_eq!(token.chain[0].label(), file("base.json"));
+
+    let dump = snapshot.redacted();
+    assert_eq!(dump["app"]["token"], json!(REDACTED));
+    assert_eq!(dump["app"]["name"], json!("base"));
+    assert!(!serde_json::to_string(&dump).unwrap().contains("s3cr3t"));
+    assert!(snapshot.explain(&KeyPath("app.missing".into())).is_none());
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+struct RotatingProvider {
+    version: Arc<std::sync::atomic::AtomicUsize>,
+    fetches: Arc<std::sync::atomic::AtomicUsize>,
+}
+
+#[async_trait::async_trait]
+impl SecretProvider for RotatingProvider {
+    fn name(&self) -> &str {
+        "mem"
+    }
+
+    async fn fetch(&self, path: &str) -> Result<SecretValue, ConfigError> {
+        use std::sync::atomic::Ordering;
+        assert_eq!(path, "db/primary");
+        self.fetches.fetch_add(1, Ordering::SeqCst);
+        let version = self.version.load(Ordering::SeqCst);
+        Ok(SecretValue::new(
+            json!({ "user": "svc", "password": format!("pw-{version}") }),
+        ))
+    }
+}
+
+#[test]
+fn secret_references_resolve_with_cache_rotation_and_redaction() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let version = Arc::new(AtomicUsize::new(1));
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let resolver = Arc::new(
+        ReferenceResolver::new().with_provider(Arc::new(RotatingProvider {
+            version: version.clone(),
+            fetches: fetches.clone(),
+        })),
+    );
+    let field = || FieldMeta {
+        reload: ReloadClass::HotReloadSafe,
+        sensitive: false,
+        default_value: None,
+        description: None,
+    };
+    let registry = Arc::new(InMemorySchemaRegistry::new());
+    registry
+        .register_namespace(
+            NamespaceId("db".into()),
+            None,
+            HashMap::from([
+                (KeyPath("user".into()), field()),
+                (KeyPath("password".into()), field()),
+            ]),
+        )
+        .unwrap();
+    let loader = |args: &[&str]| Loader {
+        sources: vec![Arc::new(CliArgsSource {
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        })],
+        secrets: vec![resolver.clone()],
+        validator: Arc::new(BasicValidator),
+        schema_registry: registry.clone(),
+    };
+    let refs = loader(&[
+        "--db.user=secret://mem/db/primary#user",
+        "--db.password=secret://mem/db/primary#password",
+    ]);
+    let password = KeyPath("db.password".into());
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let first = rt.block_on(refs.load_once()).expect("resolved");
+    assert_eq!(first.get::<String>(&password).as_deref(), Some("pw-1"));
+    assert_eq!(
+        first.get::<String>(&KeyPath("db.user".into())).as_deref(),
+        Some("svc")
+    );
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+    version.store(2, Ordering::SeqCst);
+    let cached = rt.block_on(refs.load_with_prev(Some(&first))).unwrap();
+    assert!(cached.1.changed_keys.is_empty());
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+    resolver.invalidate("mem", "db/primary");
+    let (rotated, event) = rt.block_on(refs.load_with_prev(Some(&first))).unwrap();
+    assert_eq!(rotated.get::<String>(&password).as_deref(), Some("pw-2"));
+    assert_eq!(event.changed_keys, vec![password.clone()]);
+    assert!(rotated.is_sensitive(&password));
+    assert_eq!(rotated.redacted()["db"]["password"], json!(REDACTED));
+    for rendered in [format!("{rotated:?}"), format!("{event:?}")] {
+        assert!(!rendered.contains("pw-2"), "{rendered}");
+    }
+
+    let unknown = rt
+        .block_on(loader(&["--db.password=secret://nope/db#password"]).load_once())
+        .unwrap_err()
+        .into_inner();
+    assert!(unknown.message_dev.unwrap().contains("db.password"));
+    assert!(rt
+        .block_on(loader(&["--db.password=secret://mem"]).load_once())
+        .is_err());
+}
+
+#[test]
+#[cfg(feature = "secrets_file")]
+fn encrypted_file_store_round_trips_and_rejects_wrong_keys() {
+    use sb_config::secrets::SecretResolver as _;
+
+    let path = temp_config("vault-file", "{}").with_extension("sealed");
+    let store =
+        EncryptedFileProvider::from_base64_key(&path, &EncryptedFileProvider::generate_key())
+            .unwrap();
+    let secrets = json!({ "db/primary": { "password": "hunter2" } });
+    store.seal(secrets.as_object().unwrap()).unwrap();
+    assert!(!std::fs::read_to_string(&path).unwrap().contains("hunter2"));
+
+    let resolver = ReferenceResolver::new().with_provider(Arc::new(store));
+    let mut map = json!({ "db": { "password": "secret://file/db/primary#password" } })
+        .as_object()
+        .cloned()
+        .unwrap();
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(resolver.resolve(&mut map)).unwrap();
+    assert_eq!(map["db"]["password"], json!("hunter2"));
+
+    let wrong = EncryptedFileProvider::new(&path, [7; 32]);
+    let err = wrong.open().unwrap_err().into_inner();
+    assert!(err.message_dev.unwrap().contains("decryption failed"));
+
+    std::fs::remove_file(&path).ok();
+}
+
+/// Minimal Vault KV v2 stand-in: one canned secret behind a fixed token.
+#[cfg(feature = "secrets_vault")]
+fn vault_stand_in() -> String {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+            let request = String::from_utf8_lossy(&request).to_ascii_lowercase();
+            let (status, body) = if !request.contains("x-vault-token: t0k") {
+                ("403 Forbidden", json!({ "errors": ["permission denied"] }))
+            } else if request.starts_with("get /v1/secret/data/app/db ") {
+                (
+                    "200 OK",
+                    json!({ "data": { "data": { "password": "vault-pw" }, "metadata": { "version": 3 } } }),
+                )
+            } else {
+                ("404 Not Found", json!({ "errors": [] }))
+            };
+            let body = body.to_string();
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            );
+        }
+    });
+    addr
+}
+
+#[test]
+#[cfg(feature = "secrets_vault")]
+fn vault_kv_provider_reads_fields_and_maps_errors() {
+    let addr = vault_stand_in();
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let vault = VaultKvProvider::new(&addr, "t0k");
+    let secret = rt.block_on(vault.fetch("app/db")).unwrap();
+    assert_eq!(secret.expose()["password"], json!("vault-pw"));
+    assert!(!format!("{secret:?}").contains("vault-pw"));
+
+    let missing = rt
+        .block_on(vault.fetch("app/none"))
+        .unwrap_err()
+        .into_inner();
+    assert!(missing.message_dev.unwrap().contains("not found"));
+
+    let denied = rt
+        .block_on(VaultKvProvider::new(&addr, "wrong").fetch("app/db"))
+        .unwrap_err()
+        .into_inner();
+    assert_eq!(denied.code, sb_errors::prelude::codes::AUTH_FORBIDDEN);
+}
+
+fn versioned_snapshot(version: &str) -> Arc<ConfigSnapshot> {
+    Arc::new(ConfigSnapshot::new(
+        json!({ "app": { "version": version } })
+            .as_object()
+            .cloned()
+            .unwrap(),
+        sb_config::model::SnapshotMetadata {
+            version: SnapshotVersion(version.into()),
+            ..Default::default()
+        },
+    ))
+}
+
+#[test]
+fn switch_keeps_history_promotes_lkg_and_rolls_back_by_version() {
+    let version = |snap: Arc<ConfigSnapshot>| snap.metadata().version.0.clone();
+    let switch = SnapshotSwitch::new(versioned_snapshot("v1"))
+        .with_history_limit(3)
+        .with_health_window(std::time::Duration::from_millis(50));
+    for v in ["v2", "v3", "v4"] {
+        switch.swap(versioned_snapshot(v));
+    }
+    let retained: Vec<_> = switch
+        .history()
+        .into_iter()
+        .map(|record| record.version.0)
+        .collect();
+    assert_eq!(retained, ["v2", "v3", "v4"]);
+    assert!(switch.rollback_to(&SnapshotVersion("v1".into())).is_none());
+    assert_eq!(
+        version(switch.rollback_to(&SnapshotVersion("v3".into())).unwrap()),
+        "v3"
+    );
+
+    assert!(matches!(
+        switch.report_health(true),
+        HealthOutcome::Pending(_)
+    ));
+    std::thread::sleep(std::time::Duration::from_millis(60));
+    assert_eq!(
+        switch.report_health(true),
+        HealthOutcome::Promoted(SnapshotVersion("v3".into()))
+    );
+    assert_eq!(version(switch.lkg()), "v3");
+
+    switch.swap(versioned_snapshot("v5"));
+    assert_eq!(
+        switch.report_health(false),
+        HealthOutcome::RolledBack(SnapshotVersion("v3".into()))
+    );
+    assert_eq!(version(switch.get()), "v3");
+    assert_eq!(switch.report_health(true), HealthOutcome::AlreadyLkg);
+    assert_eq!(switch.report_health(false), HealthOutcome::AlreadyLkg);
+}
+
+#[test]
+fn canary_serves_a_stable_share_of_tenants_until_promoted() {
+    use sb_types::prelude::TenantId;
+
+    let switch = SnapshotSwitch::new(versioned_snapshot("v1"));
+    switch.start_canary(versioned_snapshot("v2"), 30);
+    let tenants: Vec<TenantId> = (0..1000).map(|i| TenantId(format!("t-{i}"))).collect();
+    let on_canary = |switch: &SnapshotSwitch| {
+        tenants
+            .iter()
+            .filter(|t| switch.get_for_tenant(t).metadata().version.0 == "v2")
+            .count()
+    };
+    let share = on_canary(&switch);
+    assert!((200..400).contains(&share), "{share}");
+    assert_eq!(on_canary(&switch), share);
+    assert_eq!(switch.get().metadata().version.0, "v1");
+
+    switch.set_canary_percent(100);
+    assert_eq!(on_canary(&switch), tenants.len());
+    assert_eq!(switch.promote_canary(), Some(SnapshotVersion("v2".into())));
+    assert!(switch.canary().is_none());
+    assert_eq!(switch.get().metadata().version.0, "v2");
+
+    switch.start_canary(versioned_snapshot("v3"), 50);
+    assert_eq!(switch.abort_canary(), Some(SnapshotVersion("v3".into())));
+    assert_eq!(on_canary(&switch), tenants.len());
+}
+
+#[cfg(any(feature = "remote_consul", feature = "remote_etcd"))]
+type StandInReply = (&'static str, Vec<(&'static str, String)>, String);
+
+/// Tiny threaded HTTP/1.1 server; `handler` gets the lower-cased request
+/// line and the body, and may block (blocking queries, watches).
+#[cfg(any(feature = "remote_consul", feature = "remote_etcd"))]
+fn http_stand_in(handler: impl Fn(&str, &str) -> StandInReply + Send + Sync + 'static) -> String {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = format!("http://{}", listener.local_addr().unwrap());
+    let handler = Arc::new(handler);
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let handler = handler.clone();
+            std::thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).ok();
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    if reader.read_line(&mut header).unwrap_or(0) == 0 || header == "\r\n" {
+                        break;
+                    }
+                    let header = header.to_ascii_lowercase();
+                    if let Some(value) = header.strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap_or(0);
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).ok();
+                let (status, headers, reply) = handler(
+                    &request_line.to_ascii_lowercase(),
+                    &String::from_utf8_lossy(&body),
+                );
+                let mut stream = stream;
+                let mut head = format!(
+                    "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n",
+                    reply.len()
+                );
+                for (name, value) in headers {
+                    head.push_str(&format!("{name}: {value}\r\n"));
+                }
+                let _ = write!(stream, "{head}\r\n{reply}");
+            });
+        }
+    });
+    addr
+}
+
+#[cfg(any(feature = "remote_consul", feature = "remote_etcd"))]
+type KvState = Arc<(
+    std::sync::Mutex<(u64, Vec<(String, String)>)>,
+    std::sync::Condvar,
+)>;
+
+#[cfg(any(feature = "remote_consul", feature = "remote_etcd"))]
+fn kv_state(entries: &[(&str, &str)]) -> KvState {
+    let entries = entries
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    Arc::new((
+        std::sync::Mutex::new((1, entries)),
+        std::sync::Condvar::new(),
+    ))
+}
+
+#[cfg(any(feature = "remote_consul", feature = "remote_etcd"))]
+fn kv_put(state: &KvState, key: &str, value: &str) {
+    let mut guard = state.0.lock().unwrap();
+    guard.1.retain(|(k, _)| k != key);
+    guard.1.push((key.to_string(), value.to_string()));
+    guard.0 += 1;
+    state.1.notify_all();
+}
+
+/// Blocks until the store moves past `seen` or `wait` elapses.
+#[cfg(any(feature = "remote_consul", feature = "remote_etcd"))]
+fn kv_wait(state: &KvState, seen: u64, wait: std::time::Duration) -> (u64, Vec<(String, String)>) {
+    let guard = state.0.lock().unwrap();
+    let (guard, _) = state
+        .1
+        .wait_timeout_while(guard, wait, |(index, _)| *index <= seen)
+        .unwrap();
+    guard.clone()
+}
+
+#[cfg(feature = "remote_consul")]
+#[test]
+fn consul_blocking_queries_hot_reload_typed_values() {
+    use base64::Engine as _;
+    use std::time::Duration;
+
+    let b64 = |v: &str| base64::engine::general_purpose::STANDARD.encode(v);
+    let state = kv_state(&[("config/app/name", "a"), ("config/app/port", "80")]);
+    let server_state = state.clone();
+    let addr = http_stand_in(move |line, _| {
+        let query = line.split(' ').nth(1).unwrap_or_default();
+        let seen = query
+            .split(['?', '&'])
+            .find_map(|pair| pair.strip_prefix("index="))
+            .and_then(|index| index.parse().ok());
+        let (index, entries) = match seen {
+            Some(seen) => kv_wait(&server_state, seen, Duration::from_secs(2)),
+            None => server_state.0.lock().unwrap().clone(),
+        };
+        let body: Vec<_> = entries
+            .iter()
+            .map(|(k, v)| json!({ "Key": k, "Value": b64(v), "ModifyIndex": index }))
+            .collect();
+        (
+            "200 OK",
+            vec![("X-Consul-Index", index.to_string())],
+            json!(body).to_string(),
+        )
+    });
+
+    let registry = Arc::new(InMemorySchemaRegistry::new());
+    let field = || FieldMeta {
+        reload: ReloadClass::HotReloadSafe,
+        sensitive: false,
+        default_value: None,
+        description: None,
+    };
+    let schema = json!({
+        "type": "object",
+        "properties": { "name": { "type": "string" }, "port": { "type": "integer" } }
+    });
+    registry
+        .register_namespace(
+            NamespaceId("app".into()),
+            Some(serde_json::from_value(schema).unwrap()),
+            HashMap::from([
+                (KeyPath("name".into()), field()),
+                (KeyPath("port".into()), field()),
+            ]),
+        )
+        .unwrap();
+    let consul = Arc::new(ConsulSource::new(&addr, "config/").with_wait(Duration::from_secs(1)));
+    let loader = Loader {
+        sources: vec![consul.clone()],
+        secrets: vec![],
+        validator: Arc::new(BasicValidator),
+        schema_registry: registry,
+    };
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let initial = loader.load_once().await.unwrap();
+        assert_eq!(initial.get::<u32>(&KeyPath("app.port".into())), Some(80));
+        let switch = Arc::new(SnapshotSwitch::new(Arc::new(initial)));
+        let reloader = Arc::new(Reloader::new(loader, switch.clone()));
+        let mut changes = reloader.subscribe();
+        let watcher = RemoteWatcher::new(reloader, consul.clone());
+        let task = tokio::spawn(async move { watcher.run().await });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        kv_put(&state, "config/app/name", "b");
+        let change = tokio::time::timeout(Duration::from_secs(5), changes.recv())
+            .await
+            .expect("reload within timeout")
+            .unwrap();
+        assert!(matches!(change, ConfigChange::Updated(_)), "{change:?}");
+        let live = switch.get();
+        let name: Option<String> = live.get(&KeyPath("app.name".into()));
+        assert_eq!(name.as_deref(), Some("b"));
+        let origin = live.provenance(&KeyPath("app.name".into())).last().unwrap();
+        assert_eq!(origin.version.as_deref(), Some("2"));
+        task.abort();
+    });
+}
+
+#[cfg(feature = "remote_etcd")]
+#[test]
+fn etcd_watch_reports_changes_after_the_loaded_revision() {
+    use base64::Engine as _;
+    use std::time::Duration;
+
+    let b64 = |v: &str| base64::engine::general_purpose::STANDARD.encode(v);
+    let state = kv_state(&[("/config/app/name", "a")]);
+    let server_state = state.clone();
+    let addr = http_stand_in(move |line, body| {
+        let request: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+        if line.starts_with("post /v3/kv/range ") {
+            let (revision, entries) = server_state.0.lock().unwrap().clone();
+            let kvs: Vec<_> = entries
+                .iter()
+                .map(|(k, v)| json!({ "key": b64(k), "value": b64(v) }))
+                .collect();
+            let reply = json!({ "header": { "revision": revision.to_string() }, "kvs": kvs });
+            ("200 OK", vec![], reply.to_string())
+        } else if line.starts_with("post /v3/watch ") {
+            let start: u64 = request["create_request"]["start_revision"]
+                .as_str()
+                .and_then(|r| r.parse().ok())
+                .unwrap();
+            let (revision, _) = kv_wait(&server_state, start - 1, Duration::from_secs(2));
+            let mut reply = json!({ "result": { "created": true } }).to_string() + "\n";
+            if revision >= start {
+                let event = json!({ "result": {
+                    "header": { "revision": revision.to_string() },
+                    "events": [{ "kv": { "key": b64("/config/app/name") } }]
+                } });
+                reply += &(event.to_string() + "\n");
+            }
+            ("200 OK", vec![], reply)
+        } else {
+            ("404 Not Found", vec![], "{}".into())
+        }
+    });
+
+    let etcd = EtcdSource::new(&addr, "/config/").with_wait(Duration::from_millis(300));
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let first = etcd.load().await.unwrap();
+        assert_eq!(first.map["app"]["name"], json!("a"));
+        assert_eq!(first.provenance.0[0].version.as_deref(), Some("1"));
+        assert!(!etcd.wait_for_change().await.unwrap(), "no change yet");
+
+        let writer = state.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            kv_put(&writer, "/config/app/name", "b");
+        });
+        assert!(etcd.wait_for_change().await.unwrap());
+        assert_eq!(etcd.revision(), 2);
+        let second = etcd.load().await.unwrap();
+        assert_eq!(second.map["app"]["name"], json!("b"));
+    });
+}
+
+#[cfg(feature = "remote_git")]
+#[test]
+fn git_source_reads_a_branch_path_and_detects_new_commits() {
+    use std::process::Command;
+    use std::time::Duration;
+
+    let root = temp_config("git", "{}").with_extension("d");
+    let (remote, work) = (root.join("remote.git"), root.join("work"));
+    std::fs::create_dir_all(work.join("config")).unwrap();
+    let git = |dir: &std::path::Path, args: &[&str]| {
+        let out = Command::new("git")
+            .args(["-c", "user.name=t", "-c", "user.email=t@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .expect("git");
+        assert!(
+            out.status.success(),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        String::from_utf8_lossy(&out.stdout).trim().to_string()
+    };
+    git(&root, &["init", "--bare", "-q", "remote.git"]);
+    git(&work, &["init", "-q"]);
+    let commit = |name: &str| {
+        std::fs::write(
+            work.join("config/app.json"),
+            format!(r#"{{"app": {{"name": "{name}"}}}}"#),
+        )
+        .unwrap();
+        std::fs::write(work.join("config/db.json"), r#"{"db": {"pool": 4}}"#).unwrap();
+        std::fs::write(work.join("README.md"), "not config").unwrap();
+        git(&work, &["add", "-A"]);
+        git(&work, &["commit", "-q", "-m", name]);
+        git(
+            &work,
+            &[
+                "push",
+                "-q",
+                remote.to_str().unwrap(),
+                "HEAD:refs/heads/main",
+            ],
+        );
+        git(&work, &["rev-parse", "HEAD"])
+    };
+    let first_sha = commit("a");
+    git(&work, &["tag", "-a", "v1", "-m", "v1"]);
+    git(&work, &["push", "-q", remote.to_str().unwrap(), "v1"]);
+
+    let source = GitSource::new(remote.to_str().unwrap(), "main", root.join("cache"))
+        .with_path("config")
+        .with_poll_interval(Duration::from_millis(10));
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let snap = source.load().await.unwrap();
+        assert_eq!(snap.map["app"]["name"], json!("a"));
+        assert_eq!(snap.map["db"]["pool"], json!(4));
+        assert_eq!(snap.provenance.0.len(), 2);
+        assert!(snap.provenance.0[0].source.ends_with(":config/app.json"));
+        assert_eq!(
+            snap.provenance.0[0].version.as_deref(),
+            Some(first_sha.as_str())
+        );
+        assert!(!source.wait_for_change().await.unwrap());
+
+        let second_sha = commit("b");
+        assert!(source.wait_for_change().await.unwrap());
+        let snap = source.load().await.unwrap();
+        assert_eq!(snap.map["app"]["name"], json!("b"));
+        assert_eq!(source.commit(), Some(second_sha));
+
+        // Local remotes never ask for credentials; a token must not get in the way.
+        let tagged = GitSource::new(remote.to_str().unwrap(), "v1", root.join("cache-tag"))
+            .with_path("config/app.json")
+            .with_token("unused");
+        let snap = tagged.load().await.unwrap();
+        assert_eq!(snap.map["app"]["name"], json!("a"));
+        assert_eq!(
+            snap.provenance.0[0].version.as_deref(),
+            Some(first_sha.as_str())
+        );
+    });
+    std::fs::remove_dir_all(&root).ok();
+}
+
+#[test]
+fn tenant_overlays_apply_whitelisted_keys_with_their_own_checksum() {
+    use sb_types::prelude::TenantId;
+
+    let path = temp_config("tenant-base", r#"{"app":{"name":"global","port":80}}"#);
+    let dir = path.with_extension("tenants");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("acme.json"), r#"{"app":{"name":"acme"}}"#).unwrap();
+
+    let registry = Arc::new(InMemorySchemaRegistry::new());
+    let field = || FieldMeta {
+        reload: ReloadClass::HotReloadSafe,
+        sensitive: false,
+        default_value: None,
+        description: None,
+    };
+    let schema = json!({
+        "type": "object",
+        "properties": { "name": { "type": "string" }, "port": { "type": "integer" } }
+    });
+    registry
+        .register_namespace(
+            NamespaceId("app".into()),
+            Some(serde_json::from_value(schema).unwrap()),
+            HashMap::from([
+                (KeyPath("name".into()), field()),
+                (KeyPath("port".into()), field()),
+            ]),
+        )
+        .unwrap();
+    let loader = Loader {
+        sources: vec![Arc::new(FileSource {
+            paths: vec![path.clone()],
+        })],
+        secrets: vec![],
+        validator: Arc::new(BasicValidator),
+        schema_registry: registry,
+    };
+    let overlays = TenantOverlays::new(Arc::new(FileOverlayStore::new(&dir))).allow("app.name");
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let snapshot = rt
+        .block_on(loader.load_with_overlays(None, &overlays))
+        .unwrap()
+        .0;
+    let (acme, other) = (TenantId("acme".into()), TenantId("other".into()));
+    let name = KeyPath("app.name".into());
+
+    let acme_view = snapshot.for_tenant(&acme);
+    assert!(acme_view.has_overlay());
+    assert_eq!(acme_view.get::<String>(&name).as_deref(), Some("acme"));
+    assert_eq!(acme_view.get::<u16>(&KeyPath("app.port".into())), Some(80));
+    assert_eq!(
+        tenant_namespace_view(&acme_view, &NamespaceId("app".into())),
+        json!({ "name": "acme", "port": 80 }).as_object().cloned()
+    );
+    assert_ne!(acme_view.checksum(), snapshot.checksum());
+    let debug = format!("{acme_view:?}");
+    assert!(debug.contains("acme") && !debug.contains("app"), "{debug}");
+
+    let other_view = snapshot.for_tenant(&other);
+    assert_eq!(other_view.get::<String>(&name).as_deref(), Some("global"));
+    assert_eq!(other_view.checksum(), snapshot.checksum());
+    assert_eq!(snapshot.get::<String>(&name).as_deref(), Some("global"));
+
+    // A rejected overlay only affects its own tenant.
+    std::fs::write(dir.join("beta.json"), r#"{"app":{"port":81}}"#).unwrap();
+    let beta = TenantId("beta".into());
+    let (next, _, failures) = rt
+        .block_on(loader.load_with_overlays(Some(&snapshot), &overlays))
+        .unwrap();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].phase, "tenant");
+    assert!(
+        failures[0].message.contains("tenant beta") && failures[0].message.contains("app.port"),
+        "{}",
+        failures[0].message
+    );
+    assert!(!next.for_tenant(&beta).has_overlay());
+    assert_eq!(
+        next.for_tenant(&acme).get::<String>(&name).as_deref(),
+        Some("acme")
+    );
+
+    #[cfg(feature = "schema_json")]
+    {
+        std::fs::write(dir.join("beta.json"), r#"{"app":{"name":"beta"}}"#).unwrap();
+        let (good, event, failures) = rt
+            .block_on(loader.load_with_overlays(Some(&next), &overlays))
+            .unwrap();
+        assert!(failures.is_empty());
+        // Only an overlay changed, which still counts as a change.
+        assert_eq!(event.changed_keys, vec![name.clone()]);
+
+        std::fs::write(dir.join("beta.json"), r#"{"app":{"name":5}}"#).unwrap();
+        let (kept, _, failures) = rt
+            .block_on(loader.load_with_overlays(Some(&good), &overlays))
+            .unwrap();
+        let detail = &failures[0].message;
+        assert!(
+            detail.contains("tenant beta") && detail.contains("/app/name"),
+            "{detail}"
+        );
+        assert_eq!(
+            kept.for_tenant(&beta).get::<String>(&name).as_deref(),
+            Some("beta"),
+            "the last accepted overlay stays in effect"
+        );
+    }
+
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::remove_file(&path).ok();
+}
+
+#[cfg(feature = "tenant_storage")]
+#[test]
+fn storage_overlay_store_reads_the_listed_tenants() {
+    use sb_storage::mock::{InMemoryRepository, MockDatastore};
+    use sb_storage::prelude::Repository;
+    use sb_types::prelude::TenantId;
+
+    let datastore = MockDatastore::new();
+    let repo = Arc::new(InMemoryRepository::<TenantOverlayDoc>::new(&datastore));
+    let acme = TenantId("acme".into());
+    let overlay = json!({ "llm": { "model": "small" } });
+    let doc = TenantOverlayDoc::new(acme.clone(), overlay.as_object().cloned().unwrap());
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        repo.create(&acme, &doc).await.unwrap();
+        let store = StorageOverlayStore::new(repo, vec![acme.clone()])
+            .with_tenant(TenantId("empty".into()));
+        let overlays = store.load_all().await.unwrap();
+        assert_eq!(overlays.len(), 1);
+        assert_eq!(overlays[0].0, acme);
+        assert_eq!(overlays[0].1["llm"]["model"], json!("small"));
+    });
+}
+
+#[test]
+fn feature_flags_target_roll_out_and_follow_reloads() {
+    use sb_types::prelude::{Id, Subject, SubjectKind, TenantId};
+
+    let flags = json!({ "flags": {
+        "legacy": true,
+        "checkout": {
+            "variants": { "control": "v1", "treatment": { "layout": "grid" } },
+            "fallthrough": "control",
+            "off_variant": "control",
+            "rules": [
+                { "id": "pro", "subject_kinds": ["User"], "claims": { "plan": ["pro"] },
+                  "variant": "treatment" },
+                { "id": "launch", "tenants": ["acme"],
+                  "window": { "start": "2026-01-01T00:00:00Z", "end": "2026-02-01T00:00:00Z" },
+                  "variant": "treatment" },
+                { "id": "ramp", "rollout": { "variants": [{ "variant": "treatment", "percent": 50 }] } }
+            ]
+        }
+    } });
+    let path = temp_config("flags", &flags.to_string());
+    let registry = Arc::new(InMemorySchemaRegistry::new());
+    register_flags(registry.as_ref()).unwrap();
+    let loader = Loader {
+        sources: vec![Arc::new(FileSource {
+            paths: vec![path.clone()],
+        })],
+        secrets: vec![],
+        validator: Arc::new(BasicValidator),
+        schema_registry: registry,
+    };
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let switch = Arc::new(SnapshotSwitch::new(Arc::new(
+        rt.block_on(loader.load_once()).unwrap(),
+    )));
+    let reloader = Reloader::new(loader, switch.clone());
+    let engine = FlagEngine::new(switch);
+    let subject =
+        |kind, id: &str, tenant: &str| Subject::new(kind, Id(id.into()), TenantId(tenant.into()));
+    let at = |raw: &str| raw.parse::<chrono::DateTime<chrono::Utc>>().unwrap();
+    let outside = at("2026-03-01T00:00:00Z");
+
+    let mut pro = subject(SubjectKind::User, "u-pro", "beta");
+    pro.claims.insert("plan".into(), json!("pro"));
+    let eval = engine.evaluate_at("checkout", &pro, json!(null), outside);
+    assert_eq!(eval.value, json!({ "layout": "grid" }));
+    assert_eq!(
+        eval.reason,
+        EvalReason::RuleMatch {
+            rule: 0,
+            id: Some("pro".into())
+        }
+    );
+
+    let service = subject(SubjectKind::Service, "svc", "acme");
+    let launched = engine.evaluate_at(
+        "checkout",
+        &service,
+        json!(null),
+        at("2026-01-15T00:00:00Z"),
+    );
+    assert_eq!(launched.variant.as_deref(), Some("treatment"));
+    assert!(matches!(
+        launched.reason,
+        EvalReason::RuleMatch { rule: 1, .. }
+    ));
+    let later = engine.evaluate_at("checkout", &service, json!(null), outside);
+    assert!(matches!(
+        later.reason,
+        EvalReason::Rollout { rule: 2, .. } | EvalReason::Fallthrough
+    ));
+
+    let treated = (0..1000)
+        .filter(|i| {
+            let user = subject(SubjectKind::User, &format!("u-{i}"), "beta");
+            let first = engine.evaluate_at("checkout", &user, json!(null), outside);
+            let again = engine.evaluate_at("checkout", &user, json!(null), outside);
+            assert_eq!(first, again, "bucketing is stable");
+            first.variant.as_deref() == Some("treatment")
+        })
+        .count();
+    assert!((400..600).contains(&treated), "{treated} of 1000 treated");
+
+    let missing = engine.evaluate("nope", &pro, json!("fallback"));
+    assert_eq!(
+        (missing.value, missing.reason),
+        (json!("fallback"), EvalReason::FlagMissing)
+    );
+    assert!(engine.is_enabled("legacy", &pro));
+    assert_eq!(engine.variation("checkout", &pro, String::new()), "");
+
+    let mut disabled = flags.clone();
+    disabled["flags"]["checkout"]["enabled"] = json!(false);
+    std::fs::write(&path, disabled.to_string()).unwrap();
+    assert!(matches!(
+        rt.block_on(reloader.reload()),
+        ReloadOutcome::Applied(_)
+    ));
+    let eval = engine.evaluate("checkout", &pro, json!(null));
+    assert_eq!(
+        (eval.value, eval.reason),
+        (json!("v1"), EvalReason::Disabled)
+    );
+
+    #[cfg(feature = "schema_json")]
+    {
+        let mut broken = disabled.clone();
+        broken["flags"]["checkout"]["rules"][0]["rollout"] = json!({ "variants": [] });
+        std::fs::write(&path, broken.to_string()).unwrap();
+        assert!(matches!(
+            rt.block_on(reloader.reload()),
+            ReloadOutcome::Rejected(_)
+        ));
+        let eval = engine.evaluate("checkout", &pro, json!(null));
+        assert_eq!(eval.reason, EvalReason::Disabled);
+    }
+    std::fs::remove_file(&path).ok();
+}
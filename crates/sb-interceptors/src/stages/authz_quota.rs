@@ -58,7 +58,7 @@ impl Stage for AuthzQuotaStage {
             }
         }
 
-        let consent = parse_consent(cx.consent_token.as_ref())?;
+        let (consent, consent_token) = parse_consent(cx.consent_token.as_ref())?;
 
         let ctx = AuthContext {
             input: auth_input,
@@ -66,6 +66,7 @@ impl Stage for AuthzQuotaStage {
             action: route.action.clone(),
             attrs: route.attrs.clone(),
             consent,
+            consent_token,
             correlation_id: cx.envelope_seed.correlation_id.clone(),
             cost: self.default_cost,
         };
@@ -89,18 +90,27 @@ impl Stage for AuthzQuotaStage {
     }
 }
 
-fn parse_consent(token: Option<&String>) -> Result<Option<Consent>, InterceptError> {
+/// Signed tokens (compact JWS) are passed on for the facade's consent
+/// verifier to check; anything else is read as a base64 JSON `Consent`,
+/// which only verifiers that accept unsigned consent will honour.
+fn parse_consent(
+    token: Option<&String>,
+) -> Result<(Option<Consent>, Option<String>), InterceptError> {
     let Some(raw) = token else {
-        return Ok(None);
+        return Ok((None, None));
     };
-    if raw.trim().is_empty() {
-        return Ok(None);
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return Ok((None, None));
     }
-    let decoded = BASE64.decode(raw.trim()).map_err(|err| {
+    if raw.split('.').count() == 3 {
+        return Ok((None, Some(raw.to_string())));
+    }
+    let decoded = BASE64.decode(raw).map_err(|err| {
         InterceptError::from_public(codes::AUTH_FORBIDDEN, format!("无效的同意凭证: {err}"))
     })?;
     let consent: Consent = serde_json::from_slice(&decoded).map_err(|err| {
         InterceptError::from_public(codes::AUTH_FORBIDDEN, format!("无法解析同意凭证: {err}"))
     })?;
-    Ok(Some(consent))
+    Ok((Some(consent), None))
 }
@@ -4,7 +4,7 @@ use crate::stages::{Stage, StageOutcome};
 use async_trait::async_trait;
 use chrono::Utc;
 use sb_config::prelude::ConfigSnapshot;
-use sb_types::prelude::TraceContext;
+use sb_types::prelude::{TenantId, TraceContext};
 use std::sync::Arc;
 use uuid::Uuid;
 
@@ -49,7 +49,16 @@ impl Stage for ContextInitStage {
         if let Some(provider) = &self.config {
             if let Some(snapshot) = provider.snapshot() {
                 cx.config_version = Some(snapshot.metadata().version.0.clone());
-                cx.config_checksum = Some(snapshot.checksum().0.clone());
+                // Tenant overlays change the effective tree, so stamp the
+                // tenant's own checksum.
+                let checksum = match &cx.tenant_header {
+                    Some(tenant) => {
+                        let tenant = TenantId(tenant.clone());
+                        snapshot.for_tenant(&tenant).checksum().0.clone()
+                    }
+                    None => snapshot.checksum().0.clone(),
+                };
+                cx.config_checksum = Some(checksum);
             }
         }
         if cx.config_version.is_none() {
@@ -0,0 +1,506 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::chat::{ChatDelta, ChatModel, ChatRequest, ChatResponse, ChatStream};
+use crate::cost::estimate_usage;
+use crate::errors::LlmError;
+use crate::jsonsafe::StructOutPolicy;
+use crate::model::{ContentSegment, Cost, Usage};
+use async_trait::async_trait;
+use futures_util::{stream, StreamExt};
+use parking_lot::Mutex;
+use sb_auth::prelude::{Action, QuotaKey, QuotaOutcome, QuotaStore, ResourceUrn};
+use sb_errors::prelude::BackoffHint;
+use sb_types::prelude::Subject;
+use serde_json::{json, Value};
+use tokio::sync::Mutex as AsyncMutex;
+
+/// Per-key provider limits (requests and tokens per minute).
+#[derive(Clone, Debug)]
+pub struct ProviderLimits {
+    pub rpm: u32,
+    pub tpm: u32,
+    /// How long a caller may queue for capacity before `QUOTA.RATE_LIMITED`.
+    pub max_wait_ms: u64,
+}
+
+struct Buckets {
+    requests: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+struct KeyLimiter {
+    limits: ProviderLimits,
+    // Never held across a sleep, so `settle` is not queued behind waiters.
+    state: AsyncMutex<Buckets>,
+}
+
+impl KeyLimiter {
+    fn refill(&self, buckets: &mut Buckets) {
+        let now = Instant::now();
+        let elapsed_ms = now.duration_since(buckets.refilled_at).as_secs_f64() * 1000.0;
+        buckets.refilled_at = now;
+        buckets.requests = (buckets.requests + elapsed_ms * self.limits.rpm as f64 / 60_000.0)
+            .min(self.limits.rpm as f64);
+        buckets.tokens = (buckets.tokens + elapsed_ms * self.limits.tpm as f64 / 60_000.0)
+            .min(self.limits.tpm as f64);
+    }
+}
+
+/// Token-bucket RPM/TPM limiter shared by every tenant using a provider key.
+#[derive(Default)]
+pub struct ProviderRateLimiter {
+    keys: Mutex<HashMap<String, Arc<KeyLimiter>>>,
+}
+
+impl ProviderRateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_key(self, key: impl Into<String>, limits: ProviderLimits) -> Self {
+        let limiter = KeyLimiter {
+            state: AsyncMutex::new(Buckets {
+                requests: limits.rpm as f64,
+                tokens: limits.tpm as f64,
+                refilled_at: Instant::now(),
+            }),
+            limits,
+        };
+        self.keys.lock().insert(key.into(), Arc::new(limiter));
+        self
+    }
+
+    fn limiter(&self, key: &str) -> Option<Arc<KeyLimiter>> {
+        self.keys.lock().get(key).cloned()
+    }
+
+    /// Waits until one request and `tokens` tokens are available for `key`.
+    /// Keys without configured limits pass immediately.
+    pub async fn acquire(&self, key: &str, tokens: u32) -> Result<(), LlmError> {
+        let Some(limiter) = self.limiter(key) else {
+            return Ok(());
+        };
+        let wanted = (tokens as f64).min(limiter.limits.tpm as f64);
+        let started = Instant::now();
+        loop {
+            let wait_ms = {
+                let mut buckets = limiter.state.lock().await;
+                limiter.refill(&mut buckets);
+                if buckets.requests >= 1.0 && buckets.tokens >= wanted {
+                    buckets.requests -= 1.0;
+                    buckets.tokens -= wanted;
+                    return Ok(());
+                }
+                let req_wait = if buckets.requests >= 1.0 || limiter.limits.rpm == 0 {
+                    0.0
+                } else {
+                    (1.0 - buckets.requests) * 60_000.0 / limiter.limits.rpm as f64
+                };
+                let tok_wait = if buckets.tokens >= wanted || limiter.limits.tpm == 0 {
+                    0.0
+                } else {
+                    (wanted - buckets.tokens) * 60_000.0 / limiter.limits.tpm as f64
+                };
+                req_wait.max(tok_wait).ceil().max(1.0) as u64
+            };
+            let waited_ms = started.elapsed().as_millis() as u64;
+            if limiter.limits.rpm == 0
+                || limiter.limits.tpm == 0
+                || waited_ms + wait_ms > limiter.limits.max_wait_ms
+            {
+                return Err(LlmError::rate_limited(
+                    format!("provider key '{key}' is saturated (rpm/tpm)"),
+                    Some(BackoffHint {
+                        initial_ms: wait_ms,
+                        max_ms: wait_ms.max(60_000),
+                    }),
+                ));
+            }
+            tokio::time::sleep(Duration::from_millis(wait_ms)).await;
+        }
+    }
+
+    /// Corrects the token bucket once the real usage is known.
+    pub async fn settle(&self, key: &str, reserved: u32, actual: u32) {
+        let Some(limiter) = self.limiter(key) else {
+            return;
+        };
+        let mut buckets = limiter.state.lock().await;
+        let reserved = (reserved as f64).min(limiter.limits.tpm as f64);
+        buckets.tokens = (buckets.tokens + reserved - actual as f64).min(limiter.limits.tpm as f64);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BudgetScope {
+    /// Charge the calling subject's own quota.
+    Subject,
+    /// Charge a tenant-wide quota (`subject_id = "*"`).
+    Tenant,
+}
+
+#[derive(Clone, Debug)]
+pub struct LlmBudgetConfig {
+    pub scope: BudgetScope,
+    /// Quota resource charged in tokens.
+    pub token_resource: ResourceUrn,
+    /// Quota resource charged in micro-USD.
+    pub cost_resource: ResourceUrn,
+    pub input_usd_per_1k: f64,
+    pub output_usd_per_1k: f64,
+    /// Output tokens assumed when the request has no `max_tokens`.
+    pub default_output_tokens: u32,
+    /// Provider key whose RPM/TPM limits apply to the wrapped model.
+    pub provider_key: String,
+    /// Hint returned when the quota store reports `RateLimited`.
+    pub quota_backoff: BackoffHint,
+}
+
+impl Default for LlmBudgetConfig {
+    fn default() -> Self {
+        Self {
+            scope: BudgetScope::Subject,
+            token_resource: ResourceUrn("soul:llm:tokens".into()),
+            cost_resource: ResourceUrn("soul:llm:cost_micros".into()),
+            input_usd_per_1k: 0.0,
+            output_usd_per_1k: 0.0,
+            default_output_tokens: 1024,
+            provider_key: "default".into(),
+            quota_backoff: BackoffHint {
+                initial_ms: 1_000,
+                max_ms: 60_000,
+            },
+        }
+    }
+}
+
+/// Shared budget enforcement; wrap models per caller with [`LlmBudget::chat`].
+#[derive(Clone)]
+pub struct LlmBudget {
+    quota: Arc<dyn QuotaStore>,
+    limiter: Arc<ProviderRateLimiter>,
+    config: LlmBudgetConfig,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Reservation {
+    tokens: i64,
+    cost_micros: i64,
+    /// When the quotas were charged, so refunds land in the same period.
+    taken_at: SystemTime,
+}
+
+impl LlmBudget {
+    pub fn new(
+        quota: Arc<dyn QuotaStore>,
+        limiter: Arc<ProviderRateLimiter>,
+        config: LlmBudgetConfig,
+    ) -> Self {
+        Self {
+            quota,
+            limiter,
+            config,
+        }
+    }
+
+    pub fn chat<M: ChatModel>(&self, inner: M, subject: Subject) -> BudgetedChatModel<M> {
+        BudgetedChatModel {
+            inner,
+            subject,
+            budget: self.clone(),
+        }
+    }
+
+    fn key(&self, subject: &Subject, resource: &ResourceUrn) -> QuotaKey {
+        QuotaKey {
+            tenant: subject.tenant.0.clone(),
+            subject_id: match self.config.scope {
+                BudgetScope::Subject => subject.subject_id.0.clone(),
+                BudgetScope::Tenant => "*".into(),
+            },
+            resource: resource.clone(),
+            action: Action::Invoke,
+        }
+    }
+
+    fn cost_micros(&self, usage: &Usage) -> i64 {
+        let usd = usage.input_tokens as f64 / 1000.0 * self.config.input_usd_per_1k
+            + usage.output_tokens as f64 / 1000.0 * self.config.output_usd_per_1k;
+        (usd * 1_000_000.0).ceil() as i64
+    }
+
+    fn estimate(&self, req: &ChatRequest) -> Usage {
+        let texts: Vec<&str> = req
+            .messages
+            .iter()
+            .flat_map(|m| m.segments.iter())
+            .filter_map(|seg| match seg {
+                ContentSegment::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        let mut usage = estimate_usage(&texts, "");
+        usage.output_tokens = req.max_tokens.unwrap_or(self.config.default_output_tokens);
+        usage
+    }
+
+    async fn consume(&self, key: &QuotaKey, amount: i64) -> Result<(), LlmError> {
+        if amount <= 0 {
+            return Ok(());
+        }
+        let decision = self
+            .quota
+            .check(key, amount)
+            .await
+            .map_err(|err| LlmError::from(err.into_inner()))?;
+        match decision.outcome {
+            QuotaOutcome::Allowed => Ok(()),
+            QuotaOutcome::RateLimited => Err(LlmError::rate_limited(
+                format!("llm quota '{}' rate limited", key.resource.0),
+                Some(decision.backoff_hint().unwrap_or(self.config.quota_backoff)),
+            )),
+            QuotaOutcome::BudgetExceeded => Err(LlmError::budget_exceeded(
+                format!(
+                    "llm quota '{}' exhausted for tenant '{}'",
+                    key.resource.0, key.tenant
+                ),
+                decision.backoff_hint(),
+            )),
+        }
+    }
+
+    async fn refund(&self, key: &QuotaKey, amount: i64, taken_at: SystemTime) {
+        if amount > 0 {
+            // Refund failures only leave the caller under-credited.
+            let _ = self.quota.refund(key, amount, taken_at).await;
+        }
+    }
+
+    async fn reserve(&self, subject: &Subject, req: &ChatRequest) -> Result<Reservation, LlmError> {
+        let estimate = self.estimate(req);
+        let reservation = Reservation {
+            tokens: (estimate.input_tokens + estimate.output_tokens) as i64,
+            cost_micros: self.cost_micros(&estimate),
+            taken_at: SystemTime::now(),
+        };
+        let token_key = self.key(subject, &self.config.token_resource);
+        let cost_key = self.key(subject, &self.config.cost_resource);
+
+        self.consume(&token_key, reservation.tokens).await?;
+        if let Err(err) = self.consume(&cost_key, reservation.cost_micros).await {
+            self.refund(&token_key, reservation.tokens, reservation.taken_at)
+                .await;
+            return Err(err);
+        }
+        if let Err(err) = self
+            .limiter
+            .acquire(&self.config.provider_key, reservation.tokens as u32)
+            .await
+        {
+            self.refund_quotas(subject, reservation).await;
+            return Err(err);
+        }
+        Ok(reservation)
+    }
+
+    /// Gives back everything a reservation took, for calls that failed.
+    async fn release(&self, subject: &Subject, reservation: Reservation) {
+        self.refund_quotas(subject, reservation).await;
+        self.limiter
+            .settle(&self.config.provider_key, reservation.tokens as u32, 0)
+            .await;
+    }
+
+    async fn refund_quotas(&self, subject: &Subject, reservation: Reservation) {
+        self.refund(
+            &self.key(subject, &self.config.token_resource),
+            reservation.tokens,
+            reservation.taken_at,
+        )
+        .await;
+        self.refund(
+            &self.key(subject, &self.config.cost_resource),
+            reservation.cost_micros,
+            reservation.taken_at,
+        )
+        .await;
+    }
+
+    /// Adjusts quotas and the provider bucket from the reservation to actual
+    /// usage. Overshoot is charged best-effort: the call already happened.
+    async fn reconcile(
+        &self,
+        subject: &Subject,
+        reservation: Reservation,
+        usage: &Usage,
+        cost: Option<&Cost>,
+    ) -> Value {
+        let actual = Reservation {
+            tokens: (usage.input_tokens + usage.output_tokens) as i64,
+            cost_micros: match cost {
+                Some(cost) if cost.usd > 0.0 => (cost.usd as f64 * 1_000_000.0).ceil() as i64,
+                _ => self.cost_micros(usage),
+            },
+            taken_at: reservation.taken_at,
+        };
+        let mut overdrawn = false;
+        for (resource, reserved, used) in [
+            (
+                &self.config.token_resource,
+                reservation.tokens,
+                actual.tokens,
+            ),
+            (
+                &self.config.cost_resource,
+                reservation.cost_micros,
+                actual.cost_micros,
+            ),
+        ] {
+            let key = self.key(subject, resource);
+            if used > reserved {
+                overdrawn |= self.consume(&key, used - reserved).await.is_err();
+            } else {
+                self.refund(&key, reserved - used, reservation.taken_at)
+                    .await;
+            }
+        }
+        self.limiter
+            .settle(
+                &self.config.provider_key,
+                reservation.tokens as u32,
+                actual.tokens as u32,
+            )
+            .await;
+        json!({
+            "reserved_tokens": reservation.tokens,
+            "actual_tokens": actual.tokens,
+            "reserved_cost_micros": reservation.cost_micros,
+            "actual_cost_micros": actual.cost_micros,
+            "overdrawn": overdrawn,
+        })
+    }
+}
+
+pub struct BudgetedChatModel<M> {
+    inner: M,
+    subject: Subject,
+    budget: LlmBudget,
+}
+
+#[async_trait]
+impl<M: ChatModel> ChatModel for BudgetedChatModel<M> {
+    type Stream = ChatStream;
+
+    async fn chat(
+        &self,
+        req: ChatRequest,
+        enforce: &StructOutPolicy,
+    ) -> Result<ChatResponse, LlmError> {
+        let reservation = self.budget.reserve(&self.subject, &req).await?;
+        let mut resp = match self.inner.chat(req, enforce).await {
+            Ok(resp) => resp,
+            Err(err) => {
+                self.budget.release(&self.subject, reservation).await;
+                return Err(err);
+            }
+        };
+        let summary = self
+            .budget
+            .reconcile(&self.subject, reservation, &resp.usage, resp.cost.as_ref())
+            .await;
+        match &mut resp.provider_meta {
+            Value::Object(meta) => {
+                meta.insert("budget".into(), summary);
+            }
+            other => *other = json!({ "budget": summary }),
+        }
+        Ok(resp)
+    }
+
+    /// Reconciliation runs when the stream is drained; a stream dropped early
+    /// keeps its full reservation. Providers that never send `usage_partial`
+    /// are charged an estimate from the prompt and the streamed output.
+    async fn chat_stream(
+        &self,
+        req: ChatRequest,
+        enforce: &StructOutPolicy,
+    ) -> Result<Self::Stream, LlmError> {
+        let prompt_tokens = self.budget.estimate(&req).input_tokens;
+        let reservation = self.budget.reserve(&self.subject, &req).await?;
+        let upstream = match self.inner.chat_stream(req, enforce).await {
+            Ok(upstream) => upstream,
+            Err(err) => {
+                self.budget.release(&self.subject, reservation).await;
+                return Err(err);
+            }
+        };
+
+        let budget = self.budget.clone();
+        let subject = self.subject.clone();
+        let state = (upstream, StreamTally::default(), false);
+        let stream = stream::unfold(state, move |(mut upstream, mut tally, done)| {
+            let budget = budget.clone();
+            let subject = subject.clone();
+            async move {
+                if done {
+                    return None;
+                }
+                match upstream.next().await {
+                    Some(Ok(delta)) => {
+                        tally.push(&delta);
+                        Some((Ok(delta), (upstream, tally, false)))
+                    }
+                    Some(Err(err)) => {
+                        budget.release(&subject, reservation).await;
+                        Some((Err(err), (upstream, tally, true)))
+                    }
+                    None => {
+                        let usage = tally.usage(prompt_tokens);
+                        budget.reconcile(&subject, reservation, &usage, None).await;
+                        None
+                    }
+                }
+            }
+        });
+        Ok(stream.boxed())
+    }
+}
+
+/// Usage seen on a stream so far: the reported partials, or the output text
+/// to estimate from when the provider reports none.
+#[derive(Default)]
+struct StreamTally {
+    reported: Option<Usage>,
+    output: String,
+}
+
+impl StreamTally {
+    fn push(&mut self, delta: &ChatDelta) {
+        if let Some(partial) = &delta.usage_partial {
+            let usage = self.reported.get_or_insert_with(Usage::default);
+            usage.input_tokens = usage.input_tokens.max(partial.input_tokens);
+            usage.output_tokens = usage.output_tokens.max(partial.output_tokens);
+        }
+        if let Some(text) = &delta.text_delta {
+            self.output.push_str(text);
+        }
+        if let Some(call) = &delta.tool_call_delta {
+            match &call.arguments {
+                Value::String(fragment) => self.output.push_str(fragment),
+                Value::Null => {}
+                other => self.output.push_str(&other.to_string()),
+            }
+        }
+    }
+
+    fn usage(self, prompt_tokens: u32) -> Usage {
+        self.reported.unwrap_or_else(|| {
+            let mut usage = estimate_usage(&[], &self.output);
+            usage.input_tokens = prompt_tokens;
+            usage
+        })
+    }
+}
@@ -0,0 +1,601 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::chat::{ChatDelta, ChatModel, ChatRequest, ChatResponse, ChatStream};
+use crate::cost::zero_cost;
+use crate::embed::{EmbedModel, EmbedRequest, EmbedResponse};
+use crate::errors::LlmError;
+use crate::jsonsafe::StructOutPolicy;
+use crate::model::{ContentSegment, Usage};
+use crate::util::{canonical_json, now_ms, sha256_hex};
+use async_trait::async_trait;
+use futures_util::lock::Mutex as AsyncMutex;
+use futures_util::{stream, StreamExt};
+use parking_lot::Mutex;
+use sb_storage::prelude::{make_record_id, Entity, Repository};
+use sb_types::prelude::TenantId;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Tenant-scoped key/value store for cached model outputs.
+#[async_trait]
+pub trait ResponseCache: Send + Sync {
+    async fn get(&self, tenant: &TenantId, key: &str) -> Result<Option<Value>, LlmError>;
+    async fn put(
+        &self,
+        tenant: &TenantId,
+        key: &str,
+        value: Value,
+        ttl_ms: u64,
+    ) -> Result<(), LlmError>;
+}
+
+#[derive(Clone, Debug)]
+pub struct CachePolicy {
+    pub chat_ttl_ms: u64,
+    pub embed_ttl_ms: u64,
+    /// Cache chat calls even when they are not reproducible (no seed,
+    /// idempotency key or zero temperature).
+    pub cache_nondeterministic: bool,
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        Self {
+            chat_ttl_ms: 10 * 60 * 1000,
+            embed_ttl_ms: 7 * 24 * 60 * 60 * 1000,
+            cache_nondeterministic: false,
+        }
+    }
+}
+
+impl CachePolicy {
+    fn admits(&self, req: &ChatRequest) -> bool {
+        self.cache_nondeterministic
+            || req.idempotency_key.is_some()
+            || req.seed.is_some()
+            || req.temperature == Some(0.0)
+    }
+}
+
+/// Canonical cache key for a chat request.
+///
+/// An `idempotency_key` identifies the call on its own; otherwise the key
+/// covers model, messages, sampling params, tools and response format.
+pub fn chat_cache_key(req: &ChatRequest, enforce: &StructOutPolicy) -> String {
+    if let Some(idem) = &req.idempotency_key {
+        return sha256_hex(format!("idem\n{}\n{idem}", req.model_id).as_bytes());
+    }
+    let material = json!({
+        "model_id": req.model_id,
+        "messages": req.messages,
+        "tool_specs": req.tool_specs,
+        "temperature": req.temperature,
+        "top_p": req.top_p,
+        "max_tokens": req.max_tokens,
+        "stop": req.stop,
+        "seed": req.seed,
+        "frequency_penalty": req.frequency_penalty,
+        "presence_penalty": req.presence_penalty,
+        "logit_bias": req.logit_bias,
+        "response_format": req.response_format,
+        "allow_sensitive": req.allow_sensitive,
+        "enforce": format!("{enforce:?}"),
+    });
+    sha256_hex(canonical_json(&material).as_bytes())
+}
+
+/// Cache key for a single embedding item; independent of the item id so that
+/// re-embedding unchanged text under a new id still hits.
+pub fn embed_cache_key(req: &EmbedRequest, text: &str) -> String {
+    let pooling = req.pooling.as_deref().unwrap_or("");
+    sha256_hex(
+        format!(
+            "embed\n{}\n{}\n{pooling}\n{text}",
+            req.model_id, req.normalize
+        )
+        .as_bytes(),
+    )
+}
+
+/* ------------------------------------------------------------------
+ * Stores
+ * ------------------------------------------------------------------ */
+
+pub struct MemoryResponseCache {
+    entries: Mutex<HashMap<(TenantId, String), (Value, i64)>>,
+    max_entries: usize,
+}
+
+impl MemoryResponseCache {
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            max_entries: max_entries.max(1),
+        }
+    }
+}
+
+impl Default for MemoryResponseCache {
+    fn default() -> Self {
+        Self::new(10_000)
+    }
+}
+
+#[async_trait]
+impl ResponseCache for MemoryResponseCache {
+    async fn get(&self, tenant: &TenantId, key: &str) -> Result<Option<Value>, LlmError> {
+        let mut entries = self.entries.lock();
+        let map_key = (tenant.clone(), key.to_string());
+        match entries.get(&map_key) {
+            Some((value, expires_at)) if *expires_at > now_ms() => Ok(Some(value.clone())),
+            Some(_) => {
+                entries.remove(&map_key);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn put(
+        &self,
+        tenant: &TenantId,
+        key: &str,
+        value: Value,
+        ttl_ms: u64,
+    ) -> Result<(), LlmError> {
+        let now = now_ms();
+        let mut entries = self.entries.lock();
+        if entries.len() >= self.max_entries {
+            entries.retain(|_, (_, expires_at)| *expires_at > now);
+        }
+        if entries.len() >= self.max_entries {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, (_, expires_at))| *expires_at)
+                .map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            (tenant.clone(), key.to_string()),
+            (value, now.saturating_add(ttl_ms as i64)),
+        );
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct CacheRecord {
+    pub id: String,
+    pub tenant: String,
+    pub key: String,
+    pub value: Value,
+    pub expires_at: i64,
+}
+
+impl Entity for CacheRecord {
+    const TABLE: &'static str = "llm_cache";
+    type Key = String;
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+/// Cache persisted through an sb-storage repository, shared across replicas.
+pub struct StorageResponseCache {
+    repo: Arc<dyn Repository<CacheRecord>>,
+}
+
+impl StorageResponseCache {
+    pub fn new(repo: Arc<dyn Repository<CacheRecord>>) -> Self {
+        Self { repo }
+    }
+}
+
+#[async_trait]
+impl ResponseCache for StorageResponseCache {
+    async fn get(&self, tenant: &TenantId, key: &str) -> Result<Option<Value>, LlmError> {
+        let id = make_record_id(CacheRecord::TABLE, tenant, key);
+        let record = self
+            .repo
+            .get(tenant, &id)
+            .await
+            .map_err(|err| LlmError::from(err.into_inner()))?;
+        match record {
+            Some(record) if record.expires_at > now_ms() => Ok(Some(record.value)),
+            Some(_) => {
+                self.repo
+                    .delete(tenant, &id)
+                    .await
+                    .map_err(|err| LlmError::from(err.into_inner()))?;
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn put(
+        &self,
+        tenant: &TenantId,
+        key: &str,
+        value: Value,
+        ttl_ms: u64,
+    ) -> Result<(), LlmError> {
+        let record = CacheRecord {
+            id: make_record_id(CacheRecord::TABLE, tenant, key),
+            tenant: tenant.0.clone(),
+            key: key.to_string(),
+            value,
+            expires_at: now_ms().saturating_add(ttl_ms as i64),
+        };
+        let existing = self
+            .repo
+            .get(tenant, &record.id)
+            .await
+            .map_err(|err| LlmError::from(err.into_inner()))?;
+        let result = if existing.is_some() {
+            let patch = json!({ "value": record.value, "expires_at": record.expires_at });
+            self.repo.upsert(tenant, &record.id, patch, None).await
+        } else {
+            self.repo.create(tenant, &record).await
+        };
+        result
+            .map(|_| ())
+            .map_err(|err| LlmError::from(err.into_inner()))
+    }
+}
+
+/* ------------------------------------------------------------------
+ * Single-flight coalescing
+ * ------------------------------------------------------------------ */
+
+type FlightSlot = Arc<AsyncMutex<Option<Result<Value, LlmError>>>>;
+
+/// Coalesces identical concurrent calls: the first caller runs the work and
+/// every caller arriving while it is in flight receives the same result.
+#[derive(Default)]
+pub struct SingleFlight {
+    inflight: Mutex<HashMap<String, FlightSlot>>,
+}
+
+struct FlightCleanup<'a> {
+    flights: &'a SingleFlight,
+    key: &'a str,
+    slot: FlightSlot,
+}
+
+impl Drop for FlightCleanup<'_> {
+    fn drop(&mut self) {
+        let mut inflight = self.flights.inflight.lock();
+        if inflight
+            .get(self.key)
+            .is_some_and(|slot| Arc::ptr_eq(slot, &self.slot))
+        {
+            inflight.remove(self.key);
+        }
+    }
+}
+
+impl SingleFlight {
+    /// Returns the result together with `true` when it was shared from another
+    /// caller's in-flight work.
+    pub async fn run<F, Fut>(&self, key: &str, work: F) -> Result<(Value, bool), LlmError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Value, LlmError>>,
+    {
+        let mut work = Some(work);
+        loop {
+            let (slot, leader) = {
+                let mut inflight = self.inflight.lock();
+                match inflight.get(key) {
+                    Some(slot) => (slot.clone(), None),
+                    None => {
+                        let slot: FlightSlot = Arc::new(AsyncMutex::new(None));
+                        let guard = slot
+                            .try_lock_owned()
+                            .expect("fresh flight slot is unlocked");
+                        inflight.insert(key.to_string(), slot.clone());
+                        (slot, Some(guard))
+                    }
+                }
+            };
+
+            match (leader, work.take()) {
+                (Some(mut guard), Some(work)) => {
+                    let _cleanup = FlightCleanup {
+                        flights: self,
+                        key,
+                        slot,
+                    };
+                    let result = work().await;
+                    *guard = Some(result.clone());
+                    return result.map(|value| (value, false));
+                }
+                (leader, pending) => {
+                    drop(leader);
+                    work = pending;
+                    let done = slot.lock().await;
+                    if let Some(result) = done.as_ref() {
+                        return result.clone().map(|value| (value, true));
+                    }
+                    // The leader was cancelled before finishing; try again.
+                }
+            }
+        }
+    }
+}
+
+/* ------------------------------------------------------------------
+ * Model wrappers
+ * ------------------------------------------------------------------ */
+
+/// Shared cache handle; wrap models per tenant with [`LlmCache::chat`] and
+/// [`LlmCache::embed`].
+#[derive(Clone)]
+pub struct LlmCache {
+    store: Arc<dyn ResponseCache>,
+    flights: Arc<SingleFlight>,
+    policy: CachePolicy,
+}
+
+impl LlmCache {
+    pub fn new(store: Arc<dyn ResponseCache>, policy: CachePolicy) -> Self {
+        Self {
+            store,
+            flights: Arc::new(SingleFlight::default()),
+            policy,
+        }
+    }
+
+    pub fn memory(policy: CachePolicy) -> Self {
+        Self::new(Arc::new(MemoryResponseCache::default()), policy)
+    }
+
+    pub fn chat<M: ChatModel>(&self, inner: M, tenant: TenantId) -> CachedChatModel<M> {
+        CachedChatModel {
+            inner,
+            tenant,
+            cache: self.clone(),
+        }
+    }
+
+    pub fn embed<E: EmbedModel>(&self, inner: E, tenant: TenantId) -> CachedEmbedModel<E> {
+        CachedEmbedModel {
+            inner,
+            tenant,
+            cache: self.clone(),
+        }
+    }
+
+    // Cache reads and writes are best effort: a failing store degrades to
+    // uncached calls instead of failing the request.
+    async fn lookup(&self, tenant: &TenantId, key: &str) -> Option<Value> {
+        self.store.get(tenant, key).await.ok().flatten()
+    }
+
+    async fn store(&self, tenant: &TenantId, key: &str, value: Value, ttl_ms: u64) {
+        let _ = self.store.put(tenant, key, value, ttl_ms).await;
+    }
+}
+
+pub struct CachedChatModel<M> {
+    inner: M,
+    tenant: TenantId,
+    cache: LlmCache,
+}
+
+impl<M: ChatModel> CachedChatModel<M> {
+    async fn cached(
+        &self,
+        req: &ChatRequest,
+        enforce: &StructOutPolicy,
+    ) -> Option<Result<ChatResponse, LlmError>> {
+        if !self.cache.policy.admits(req) {
+            return None;
+        }
+        let key = chat_cache_key(req, enforce);
+        if let Some(value) = self.cache.lookup(&self.tenant, &key).await {
+            if let Ok(resp) = serde_json::from_value::<ChatResponse>(value) {
+                return Some(Ok(mark_hit(resp, "hit")));
+            }
+        }
+
+        let flight_key = format!("{}::{key}", self.tenant);
+        let outcome = self
+            .cache
+            .flights
+            .run(&flight_key, || async {
+                let resp = self.inner.chat(req.clone(), enforce).await?;
+                let value = serde_json::to_value(&resp)
+                    .map_err(|err| LlmError::unknown(format!("serialize chat response: {err}")))?;
+                self.cache
+                    .store(
+                        &self.tenant,
+                        &key,
+                        value.clone(),
+                        self.cache.policy.chat_ttl_ms,
+                    )
+                    .await;
+                Ok(value)
+            })
+            .await;
+        Some(outcome.and_then(|(value, shared)| {
+            let resp = serde_json::from_value::<ChatResponse>(value)
+                .map_err(|err| LlmError::unknown(format!("decode chat response: {err}")))?;
+            Ok(if shared {
+                mark_hit(resp, "coalesced")
+            } else {
+                resp
+            })
+        }))
+    }
+}
+
+fn mark_hit(mut resp: ChatResponse, how: &str) -> ChatResponse {
+    resp.cost = zero_cost();
+    match &mut resp.provider_meta {
+        Value::Object(meta) => {
+            meta.insert("cache".into(), Value::String(how.into()));
+        }
+        other => *other = json!({ "cache": how }),
+    }
+    resp
+}
+
+fn replay_deltas(resp: ChatResponse) -> Vec<Result<ChatDelta, LlmError>> {
+    let text: String = resp
+        .message
+        .segments
+        .iter()
+        .filter_map(|seg| match seg {
+            ContentSegment::Text { text } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect();
+    let mut deltas = Vec::new();
+    if !text.is_empty() {
+        deltas.push(ChatDelta {
+            text_delta: Some(text),
+            tool_call_delta: None,
+            usage_partial: None,
+            finish: None,
+            first_token_ms: Some(0),
+        });
+    }
+    for call in resp.message.tool_calls {
+        deltas.push(ChatDelta {
+            text_delta: None,
+            tool_call_delta: Some(call),
+            usage_partial: None,
+            finish: None,
+            first_token_ms: None,
+        });
+    }
+    deltas.push(ChatDelta {
+        text_delta: None,
+        tool_call_delta: None,
+        usage_partial: Some(resp.usage),
+        finish: Some(resp.finish),
+        first_token_ms: None,
+    });
+    deltas.into_iter().map(Ok).collect()
+}
+
+#[async_trait]
+impl<M: ChatModel> ChatModel for CachedChatModel<M> {
+    type Stream = ChatStream;
+
+    async fn chat(
+        &self,
+        req: ChatRequest,
+        enforce: &StructOutPolicy,
+    ) -> Result<ChatResponse, LlmError> {
+        match self.cached(&req, enforce).await {
+            Some(result) => result,
+            None => self.inner.chat(req, enforce).await,
+        }
+    }
+
+    /// Cache hits are replayed as a short delta sequence; misses stream
+    /// straight from the provider and are not cached.
+    async fn chat_stream(
+        &self,
+        req: ChatRequest,
+        enforce: &StructOutPolicy,
+    ) -> Result<Self::Stream, LlmError> {
+        if self.cache.policy.admits(&req) {
+            let key = chat_cache_key(&req, enforce);
+            if let Some(value) = self.cache.lookup(&self.tenant, &key).await {
+                if let Ok(resp) = serde_json::from_value::<ChatResponse>(value) {
+                    return Ok(stream::iter(replay_deltas(resp)).boxed());
+                }
+            }
+        }
+        Ok(self.inner.chat_stream(req, enforce).await?.boxed())
+    }
+}
+
+pub struct CachedEmbedModel<E> {
+    inner: E,
+    tenant: TenantId,
+    cache: LlmCache,
+}
+
+#[async_trait]
+impl<E: EmbedModel> EmbedModel for CachedEmbedModel<E> {
+    async fn embed(&self, req: EmbedRequest) -> Result<EmbedResponse, LlmError> {
+        let keys: Vec<String> = req
+            .items
+            .iter()
+            .map(|item| embed_cache_key(&req, &item.text))
+            .collect();
+        let mut vectors: Vec<Option<Vec<f32>>> = Vec::with_capacity(keys.len());
+        for key in &keys {
+            let hit = self
+                .cache
+                .lookup(&self.tenant, key)
+                .await
+                .and_then(|value| serde_json::from_value::<Vec<f32>>(value).ok());
+            vectors.push(hit);
+        }
+
+        let missing: Vec<usize> = (0..vectors.len())
+            .filter(|&idx| vectors[idx].is_none())
+            .collect();
+        let hits = vectors.len() - missing.len();
+        let (usage, cost, mut dim) = if missing.is_empty() {
+            (
+                Usage {
+                    requests: 0,
+                    ..Usage::default()
+                },
+                zero_cost(),
+                0,
+            )
+        } else {
+            let sub = EmbedRequest {
+                model_id: req.model_id.clone(),
+                items: missing.iter().map(|&idx| req.items[idx].clone()).collect(),
+                normalize: req.normalize,
+                pooling: req.pooling.clone(),
+            };
+            let resp = self.inner.embed(sub).await?;
+            if resp.vectors.len() != missing.len() {
+                return Err(LlmError::provider_unavailable(format!(
+                    "embedding provider returned {} vectors for {} items",
+                    resp.vectors.len(),
+                    missing.len()
+                )));
+            }
+            for (&idx, vector) in missing.iter().zip(resp.vectors) {
+                self.cache
+                    .store(
+                        &self.tenant,
+                        &keys[idx],
+                        json!(vector),
+                        self.cache.policy.embed_ttl_ms,
+                    )
+                    .await;
+                vectors[idx] = Some(vector);
+            }
+            (resp.usage, resp.cost, resp.dim)
+        };
+
+        let vectors: Vec<Vec<f32>> = vectors.into_iter().flatten().collect();
+        if dim == 0 {
+            dim = vectors.first().map(|v| v.len() as u32).unwrap_or_default();
+        }
+        Ok(EmbedResponse {
+            dim,
+            vectors,
+            usage,
+            cost,
+            provider_meta: json!({ "cache": { "hits": hits, "misses": missing.len() } }),
+        })
+    }
+}
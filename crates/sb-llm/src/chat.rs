@@ -110,6 +110,27 @@ pub trait ChatModel: Send + Sync {
     ) -> Result<Self::Stream, LlmError>;
 }
 
+#[async_trait]
+impl<M: ChatModel + ?Sized> ChatModel for Box<M> {
+    type Stream = M::Stream;
+
+    async fn chat(
+        &self,
+        req: ChatRequest,
+        enforce: &StructOutPolicy,
+    ) -> Result<ChatResponse, LlmError> {
+        (**self).chat(req, enforce).await
+    }
+
+    async fn chat_stream(
+        &self,
+        req: ChatRequest,
+        enforce: &StructOutPolicy,
+    ) -> Result<Self::Stream, LlmError> {
+        (**self).chat_stream(req, enforce).await
+    }
+}
+
 pub type ChatStream = BoxStream<'static, Result<ChatDelta, LlmError>>;
 pub type BoxChatModel = Box<dyn ChatModel<Stream = ChatStream>>;
 
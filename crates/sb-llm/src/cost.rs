@@ -4,9 +4,9 @@ use crate::model::{Cost, CostBreakdown, Usage};
 pub fn estimate_usage(inputs: &[&str], output: &str) -> Usage {
     let input_tokens = inputs
         .iter()
-        .map(|s| ((s.chars().count() as u32) + 3) / 4)
+        .map(|s| (s.chars().count() as u32).div_ceil(4))
         .sum();
-    let output_tokens = ((output.chars().count() as u32) + 3) / 4;
+    let output_tokens = (output.chars().count() as u32).div_ceil(4);
     Usage {
         input_tokens,
         output_tokens,
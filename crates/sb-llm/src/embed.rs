@@ -33,3 +33,10 @@ pub struct EmbedResponse {
 pub trait EmbedModel: Send + Sync {
     async fn embed(&self, req: EmbedRequest) -> Result<EmbedResponse, LlmError>;
 }
+
+#[async_trait]
+impl<M: EmbedModel + ?Sized> EmbedModel for Box<M> {
+    async fn embed(&self, req: EmbedRequest) -> Result<EmbedResponse, LlmError> {
+        (**self).embed(req).await
+    }
+}
@@ -1,10 +1,10 @@
-use sb_errors::prelude::{codes, ErrorBuilder, ErrorObj};
+use sb_errors::prelude::{codes, BackoffHint, ErrorBuilder, ErrorObj, RetryClass};
 use thiserror::Error;
 
-#[derive(Debug, Error)]
+#[derive(Clone, Debug, Error)]
 #[error("{public}")]
 pub struct LlmError {
-    inner: ErrorObj,
+    inner: Box<ErrorObj>,
     public: String,
 }
 
@@ -12,11 +12,26 @@ impl LlmError {
     pub fn new(inner: ErrorObj) -> Self {
         let view = inner.to_public();
         let public = format!("{}: {}", view.code, view.message);
-        Self { inner, public }
+        Self {
+            inner: Box::new(inner),
+            public,
+        }
     }
 
     pub fn into_inner(self) -> ErrorObj {
-        self.inner
+        *self.inner
+    }
+
+    pub fn detail(&self) -> Option<&str> {
+        self.inner.message_dev.as_deref()
+    }
+
+    pub fn is_transient(&self) -> bool {
+        self.inner.retryable == RetryClass::Transient
+    }
+
+    pub fn backoff_hint(&self) -> Option<BackoffHint> {
+        self.inner.backoff_hint
     }
 
     pub fn to_public(&self) -> sb_errors::render::PublicErrorView {
@@ -59,6 +74,26 @@ impl LlmError {
         )
     }
 
+    pub fn rate_limited(msg: impl Into<String>, hint: Option<BackoffHint>) -> Self {
+        let mut builder = ErrorBuilder::new(codes::QUOTA_RATE_LIMITED)
+            .user_msg("Model rate limit reached, please retry later.")
+            .dev_msg(msg.into());
+        if let Some(hint) = hint {
+            builder = builder.backoff_hint(hint);
+        }
+        Self::new(builder.build())
+    }
+
+    pub fn budget_exceeded(msg: impl Into<String>, hint: Option<BackoffHint>) -> Self {
+        let mut builder = ErrorBuilder::new(codes::QUOTA_BUDGET_EXCEEDED)
+            .user_msg("Model usage budget exhausted.")
+            .dev_msg(msg.into());
+        if let Some(hint) = hint {
+            builder = builder.backoff_hint(hint);
+        }
+        Self::new(builder.build())
+    }
+
     pub fn schema(msg: impl Into<String>) -> Self {
         Self::new(
             ErrorBuilder::new(codes::SCHEMA_VALIDATION_FAILED)
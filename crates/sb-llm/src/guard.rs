@@ -0,0 +1,621 @@
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use crate::chat::{ChatDelta, ChatModel, ChatRequest, ChatResponse, ChatStream};
+use crate::errors::LlmError;
+use crate::jsonsafe::StructOutPolicy;
+use crate::model::{ContentSegment, Message, Role};
+use crate::stream::AggregatingStream;
+use async_trait::async_trait;
+use futures_util::{stream, Stream, StreamExt};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Where a guard finding was made.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardStage {
+    Input,
+    Output,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Redaction {
+    pub guard: String,
+    pub kind: String,
+    pub stage: GuardStage,
+    pub message_index: Option<usize>,
+    pub count: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct InjectionFinding {
+    pub guard: String,
+    pub message_index: usize,
+    pub marker: String,
+    pub neutralized: bool,
+}
+
+/// Everything the guard chain did to one call; surfaced under
+/// `provider_meta.guard`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GuardReport {
+    pub redactions: Vec<Redaction>,
+    pub injections: Vec<InjectionFinding>,
+}
+
+impl GuardReport {
+    pub fn is_empty(&self) -> bool {
+        self.redactions.is_empty() && self.injections.is_empty()
+    }
+}
+
+pub trait InputGuard: Send + Sync {
+    fn name(&self) -> &str;
+    fn check_input(&self, req: &mut ChatRequest, report: &mut GuardReport) -> Result<(), LlmError>;
+}
+
+pub trait OutputGuard: Send + Sync {
+    fn name(&self) -> &str;
+    fn check_output(
+        &self,
+        req: &ChatRequest,
+        resp: &mut ChatResponse,
+        report: &mut GuardReport,
+    ) -> Result<(), LlmError>;
+}
+
+/* ------------------------------------------------------------------
+ * PII redaction
+ * ------------------------------------------------------------------ */
+
+pub struct PiiRule {
+    pub kind: String,
+    pattern: Regex,
+    validator: Option<fn(&str) -> bool>,
+}
+
+impl PiiRule {
+    pub fn new(kind: impl Into<String>, pattern: &str) -> Result<Self, LlmError> {
+        let pattern = Regex::new(pattern)
+            .map_err(|err| LlmError::unknown(format!("invalid pii pattern: {err}")))?;
+        Ok(Self {
+            kind: kind.into(),
+            pattern,
+            validator: None,
+        })
+    }
+
+    pub fn with_validator(mut self, validator: fn(&str) -> bool) -> Self {
+        self.validator = Some(validator);
+        self
+    }
+
+    fn redact(&self, text: &str) -> (String, usize) {
+        let mut count = 0;
+        let replaced = self
+            .pattern
+            .replace_all(text, |caps: &regex::Captures<'_>| {
+                let found = &caps[0];
+                if self.validator.is_some_and(|valid| !valid(found)) {
+                    return found.to_string();
+                }
+                count += 1;
+                format!("[REDACTED:{}]", self.kind.to_uppercase())
+            });
+        (replaced.into_owned(), count)
+    }
+}
+
+fn luhn_valid(candidate: &str) -> bool {
+    let digits: Vec<u32> = candidate.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(idx, &d)| {
+            if idx % 2 == 1 {
+                let doubled = d * 2;
+                if doubled > 9 {
+                    doubled - 9
+                } else {
+                    doubled
+                }
+            } else {
+                d
+            }
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// Rule-based redactor for emails, phone numbers, national ID and card numbers.
+///
+/// Covers message text and the string values inside tool-call arguments.
+/// Requests with `allow_sensitive = true` pass through untouched.
+pub struct PiiRedactor {
+    rules: Vec<PiiRule>,
+}
+
+impl PiiRedactor {
+    pub fn new(rules: Vec<PiiRule>) -> Self {
+        Self { rules }
+    }
+
+    pub fn default_rules() -> Self {
+        let rules = [
+            ("email", r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}"),
+            ("id_number", r"\b\d{17}[\dXx]\b"),
+            ("card", r"\b(?:\d[ -]?){12,18}\d\b"),
+            (
+                "phone",
+                r"\+\d{1,3}[ -]?\d{1,4}[ -]?\d{3,4}[ -]?\d{4}\b|\(\d{2,4}\)\s?\d{3,4}[ -]\d{4}\b|\b\d{3}[ -]\d{3,4}[ -]\d{4}\b|\b1[3-9]\d{9}\b",
+            ),
+        ];
+        let rules = rules
+            .into_iter()
+            .map(|(kind, pattern)| {
+                let rule = PiiRule::new(kind, pattern).expect("built-in pii pattern compiles");
+                if kind == "card" {
+                    rule.with_validator(luhn_valid)
+                } else {
+                    rule
+                }
+            })
+            .collect();
+        Self { rules }
+    }
+
+    fn redact_message(
+        &self,
+        message: &mut Message,
+        stage: GuardStage,
+        message_index: Option<usize>,
+        report: &mut GuardReport,
+    ) {
+        for rule in &self.rules {
+            let mut total = 0;
+            for seg in message.segments.iter_mut() {
+                if let ContentSegment::Text { text } = seg {
+                    total += redact_text(rule, text);
+                }
+            }
+            for call in message.tool_calls.iter_mut() {
+                total += redact_strings(rule, &mut call.arguments);
+            }
+            if total > 0 {
+                report.redactions.push(Redaction {
+                    guard: "pii".to_string(),
+                    kind: rule.kind.clone(),
+                    stage,
+                    message_index,
+                    count: total,
+                });
+            }
+        }
+    }
+}
+
+fn redact_text(rule: &PiiRule, text: &mut String) -> usize {
+    let (replaced, count) = rule.redact(text);
+    if count > 0 {
+        *text = replaced;
+    }
+    count
+}
+
+/// Redacts every string in `value`, however deeply nested.
+fn redact_strings(rule: &PiiRule, value: &mut Value) -> usize {
+    match value {
+        Value::String(text) => redact_text(rule, text),
+        Value::Array(items) => items
+            .iter_mut()
+            .map(|item| redact_strings(rule, item))
+            .sum(),
+        Value::Object(map) => map
+            .values_mut()
+            .map(|item| redact_strings(rule, item))
+            .sum(),
+        _ => 0,
+    }
+}
+
+impl InputGuard for PiiRedactor {
+    fn name(&self) -> &str {
+        "pii"
+    }
+
+    fn check_input(&self, req: &mut ChatRequest, report: &mut GuardReport) -> Result<(), LlmError> {
+        if req.allow_sensitive {
+            return Ok(());
+        }
+        for (idx, message) in req.messages.iter_mut().enumerate() {
+            self.redact_message(message, GuardStage::Input, Some(idx), report);
+        }
+        Ok(())
+    }
+}
+
+impl OutputGuard for PiiRedactor {
+    fn name(&self) -> &str {
+        "pii"
+    }
+
+    fn check_output(
+        &self,
+        req: &ChatRequest,
+        resp: &mut ChatResponse,
+        report: &mut GuardReport,
+    ) -> Result<(), LlmError> {
+        if req.allow_sensitive {
+            return Ok(());
+        }
+        self.redact_message(&mut resp.message, GuardStage::Output, None, report);
+        Ok(())
+    }
+}
+
+/* ------------------------------------------------------------------
+ * Prompt-injection detection on tool results
+ * ------------------------------------------------------------------ */
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InjectionAction {
+    /// Record the finding only.
+    Flag,
+    /// Replace the offending marker so it no longer reads as an instruction.
+    Neutralize,
+    /// Fail the call with `LLM.SAFETY_BLOCK`.
+    Block,
+}
+
+/// Scans `Role::Tool` messages for common prompt-injection markers before
+/// they re-enter the model context.
+pub struct InjectionDetector {
+    markers: Vec<(String, Regex)>,
+    action: InjectionAction,
+}
+
+impl InjectionDetector {
+    pub fn new(action: InjectionAction) -> Self {
+        let markers = [
+            (
+                "ignore_instructions",
+                r"(?i)\b(?:ignore|disregard|forget)\s+(?:all\s+|any\s+)?(?:the\s+)?(?:previous|prior|above|earlier)\s+(?:instructions|prompts|messages|rules)",
+            ),
+            (
+                "role_override",
+                r"(?i)\byou\s+are\s+now\s+(?:a|an|the|in)\b",
+            ),
+            (
+                "system_prompt_probe",
+                r"(?i)\b(?:reveal|print|show|repeat)\s+(?:your\s+|the\s+)?(?:system\s+prompt|hidden\s+instructions)",
+            ),
+            (
+                "chat_template_token",
+                r"(?im)<\|(?:im_start|im_end|system|endoftext)\|>|\[/?INST\]|^\s*#{2,}\s*system\b",
+            ),
+        ];
+        Self {
+            markers: markers
+                .into_iter()
+                .map(|(name, pattern)| {
+                    (
+                        name.to_string(),
+                        Regex::new(pattern).expect("built-in injection pattern compiles"),
+                    )
+                })
+                .collect(),
+            action,
+        }
+    }
+}
+
+impl InputGuard for InjectionDetector {
+    fn name(&self) -> &str {
+        "injection"
+    }
+
+    fn check_input(&self, req: &mut ChatRequest, report: &mut GuardReport) -> Result<(), LlmError> {
+        for (idx, message) in req.messages.iter_mut().enumerate() {
+            if message.role != Role::Tool {
+                continue;
+            }
+            for seg in message.segments.iter_mut() {
+                let ContentSegment::Text { text } = seg else {
+                    continue;
+                };
+                for (marker, pattern) in &self.markers {
+                    if !pattern.is_match(text) {
+                        continue;
+                    }
+                    if self.action == InjectionAction::Block {
+                        return Err(LlmError::safety_block(format!(
+                            "prompt injection marker '{marker}' in tool result (message {idx})"
+                        )));
+                    }
+                    let neutralized = self.action == InjectionAction::Neutralize;
+                    if neutralized {
+                        *text = pattern
+                            .replace_all(text, "[removed: suspected instruction]")
+                            .into_owned();
+                    }
+                    report.injections.push(InjectionFinding {
+                        guard: self.name().to_string(),
+                        message_index: idx,
+                        marker: marker.clone(),
+                        neutralized,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/* ------------------------------------------------------------------
+ * Output policy
+ * ------------------------------------------------------------------ */
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputAction {
+    Mask,
+    Block,
+}
+
+/// Masks or blocks model output containing any of the configured terms.
+pub struct OutputTermFilter {
+    terms: Vec<(String, Regex)>,
+    action: OutputAction,
+}
+
+impl OutputTermFilter {
+    pub fn new(terms: &[&str], action: OutputAction) -> Self {
+        let terms = terms
+            .iter()
+            .map(|term| {
+                let pattern = format!(r"(?i)\b{}\b", regex::escape(term));
+                (
+                    term.to_string(),
+                    Regex::new(&pattern).expect("escaped term compiles"),
+                )
+            })
+            .collect();
+        Self { terms, action }
+    }
+}
+
+impl OutputGuard for OutputTermFilter {
+    fn name(&self) -> &str {
+        "output_terms"
+    }
+
+    fn check_output(
+        &self,
+        _req: &ChatRequest,
+        resp: &mut ChatResponse,
+        report: &mut GuardReport,
+    ) -> Result<(), LlmError> {
+        for (term, pattern) in &self.terms {
+            let mut total = 0;
+            for seg in resp.message.segments.iter_mut() {
+                let ContentSegment::Text { text } = seg else {
+                    continue;
+                };
+                let count = pattern.find_iter(text).count();
+                if count == 0 {
+                    continue;
+                }
+                if self.action == OutputAction::Block {
+                    return Err(LlmError::safety_block(format!(
+                        "model output contains blocked term '{term}'"
+                    )));
+                }
+                *text = pattern.replace_all(text, "[REDACTED]").into_owned();
+                total += count;
+            }
+            if total > 0 {
+                report.redactions.push(Redaction {
+                    guard: self.name().to_string(),
+                    kind: term.clone(),
+                    stage: GuardStage::Output,
+                    message_index: None,
+                    count: total,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/* ------------------------------------------------------------------
+ * Chain
+ * ------------------------------------------------------------------ */
+
+/// Ordered pre/post guards applied around a `ChatModel`.
+#[derive(Clone, Default)]
+pub struct GuardChain {
+    input: Vec<Arc<dyn InputGuard>>,
+    output: Vec<Arc<dyn OutputGuard>>,
+}
+
+impl GuardChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// PII redaction both ways plus neutralisation of injected instructions
+    /// in tool results.
+    pub fn default_rules() -> Self {
+        let pii = Arc::new(PiiRedactor::default_rules());
+        Self::new()
+            .input(Arc::new(InjectionDetector::new(
+                InjectionAction::Neutralize,
+            )))
+            .input(pii.clone())
+            .output(pii)
+    }
+
+    pub fn input(mut self, guard: Arc<dyn InputGuard>) -> Self {
+        self.input.push(guard);
+        self
+    }
+
+    pub fn output(mut self, guard: Arc<dyn OutputGuard>) -> Self {
+        self.output.push(guard);
+        self
+    }
+
+    pub fn wrap<M: ChatModel>(&self, inner: M) -> GuardedChatModel<M> {
+        GuardedChatModel {
+            inner,
+            chain: self.clone(),
+        }
+    }
+
+    pub fn check_input(
+        &self,
+        req: &mut ChatRequest,
+        report: &mut GuardReport,
+    ) -> Result<(), LlmError> {
+        self.input
+            .iter()
+            .try_for_each(|guard| guard.check_input(req, report))
+    }
+
+    pub fn check_output(
+        &self,
+        req: &ChatRequest,
+        resp: &mut ChatResponse,
+        report: &mut GuardReport,
+    ) -> Result<(), LlmError> {
+        self.output
+            .iter()
+            .try_for_each(|guard| guard.check_output(req, resp, report))
+    }
+}
+
+pub struct GuardedChatModel<M> {
+    inner: M,
+    chain: GuardChain,
+}
+
+fn attach_report(resp: &mut ChatResponse, report: &GuardReport) {
+    if report.is_empty() {
+        return;
+    }
+    let value = json!(report);
+    match &mut resp.provider_meta {
+        Value::Object(meta) => {
+            meta.insert("guard".into(), value);
+        }
+        other => *other = json!({ "guard": value }),
+    }
+}
+
+/// Stream returned by [`GuardedChatModel::chat_stream`]. Deltas carry no
+/// metadata, so the guard findings are exposed through [`Self::report`].
+pub struct GuardedStream {
+    inner: ChatStream,
+    report: GuardReport,
+}
+
+impl GuardedStream {
+    pub fn report(&self) -> &GuardReport {
+        &self.report
+    }
+}
+
+impl Stream for GuardedStream {
+    type Item = Result<ChatDelta, LlmError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+#[async_trait]
+impl<M: ChatModel> ChatModel for GuardedChatModel<M> {
+    type Stream = GuardedStream;
+
+    async fn chat(
+        &self,
+        mut req: ChatRequest,
+        enforce: &StructOutPolicy,
+    ) -> Result<ChatResponse, LlmError> {
+        let mut report = GuardReport::default();
+        self.chain.check_input(&mut req, &mut report)?;
+        let mut resp = self.inner.chat(req.clone(), enforce).await?;
+        self.chain.check_output(&req, &mut resp, &mut report)?;
+        attach_report(&mut resp, &report);
+        Ok(resp)
+    }
+
+    /// With output guards configured the upstream stream is buffered so the
+    /// guards see the complete reply before anything is released. Findings
+    /// are available from [`GuardedStream::report`].
+    async fn chat_stream(
+        &self,
+        mut req: ChatRequest,
+        enforce: &StructOutPolicy,
+    ) -> Result<Self::Stream, LlmError> {
+        let mut report = GuardReport::default();
+        self.chain.check_input(&mut req, &mut report)?;
+        let upstream = self.inner.chat_stream(req.clone(), enforce).await?;
+        if self.chain.output.is_empty() {
+            return Ok(GuardedStream {
+                inner: upstream.boxed(),
+                report,
+            });
+        }
+
+        let mut resp = AggregatingStream::new(upstream, &req, StructOutPolicy::Off)
+            .collect()
+            .await?;
+        self.chain.check_output(&req, &mut resp, &mut report)?;
+        let Message {
+            segments,
+            tool_calls,
+            ..
+        } = resp.message;
+        let text: String = segments
+            .into_iter()
+            .filter_map(|seg| match seg {
+                ContentSegment::Text { text } => Some(text),
+                _ => None,
+            })
+            .collect();
+        let mut deltas = vec![ChatDelta {
+            text_delta: Some(text),
+            tool_call_delta: None,
+            usage_partial: None,
+            finish: None,
+            first_token_ms: resp.provider_meta["first_token_ms"]
+                .as_u64()
+                .map(|ms| ms as u32),
+        }];
+        deltas.extend(tool_calls.into_iter().map(|call| ChatDelta {
+            text_delta: None,
+            tool_call_delta: Some(call),
+            usage_partial: None,
+            finish: None,
+            first_token_ms: None,
+        }));
+        deltas.push(ChatDelta {
+            text_delta: None,
+            tool_call_delta: None,
+            usage_partial: Some(resp.usage),
+            finish: Some(resp.finish),
+            first_token_ms: None,
+        });
+        Ok(GuardedStream {
+            inner: stream::iter(deltas.into_iter().map(Ok)).boxed(),
+            report,
+        })
+    }
+}
@@ -1,50 +1,329 @@
-use crate::chat::JsonSchema;
+use crate::chat::{ChatModel, ChatRequest, ChatResponse, JsonSchema, ResponseFormat};
 use crate::errors::LlmError;
-use serde_json::Value;
+use crate::model::{ContentSegment, Message, Role};
+use sb_errors::prelude::{codes, ErrorBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
 #[derive(Clone, Debug)]
 pub enum StructOutPolicy {
     Off,
     StrictReject,
-    StrictRepair { max_attempts: u8 },
+    /// Run the repair pipeline before parsing; `max_attempts` bounds how many
+    /// times [`chat_structured`] re-asks the model with the validation errors.
+    StrictRepair {
+        max_attempts: u8,
+    },
+}
+
+/// One JSON-Schema violation, addressed by JSON pointer into the instance.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SchemaViolation {
+    pub instance_path: String,
+    pub message: String,
 }
 
 pub fn enforce_json(candidate: &str, policy: &StructOutPolicy) -> Result<Value, LlmError> {
     match policy {
         StructOutPolicy::Off => serde_json::from_str(candidate)
-            .map_err(|err| LlmError::schema(&format!("json parse (off): {err}"))),
+            .map_err(|err| LlmError::schema(format!("json parse (off): {err}"))),
         StructOutPolicy::StrictReject => serde_json::from_str(candidate)
-            .map_err(|err| LlmError::schema(&format!("json parse: {err}"))),
-        StructOutPolicy::StrictRepair { max_attempts } => {
-            let mut text = candidate.trim().to_string();
-            let mut attempts = 0u8;
-            loop {
-                match serde_json::from_str::<Value>(&text) {
-                    Ok(value) => return Ok(value),
-                    Err(_err) if attempts < *max_attempts => {
-                        attempts += 1;
-                        text = text.trim().trim_matches('"').to_string();
-                        if text.is_empty() {
-                            return Err(LlmError::schema("json parse after repair: empty"));
-                        }
-                    }
-                    Err(err) => {
-                        return Err(LlmError::schema(&format!("json parse after repair: {err}")));
-                    }
+            .map_err(|err| LlmError::schema(format!("json parse: {err}"))),
+        StructOutPolicy::StrictRepair { .. } => {
+            if let Ok(value) = serde_json::from_str::<Value>(candidate) {
+                return Ok(value);
+            }
+            let repaired = repair_json(candidate);
+            if repaired.is_empty() {
+                return Err(LlmError::schema("json parse after repair: empty"));
+            }
+            serde_json::from_str(&repaired)
+                .map_err(|err| LlmError::schema(format!("json parse after repair: {err}")))
+        }
+    }
+}
+
+/// Parses `candidate` under `policy` and, unless the policy is `Off`, validates
+/// it against the schema carried by `format`.
+pub fn enforce_format(
+    candidate: &str,
+    format: &ResponseFormat,
+    policy: &StructOutPolicy,
+) -> Result<Value, LlmError> {
+    let value = enforce_json(candidate, policy)?;
+    if !matches!(policy, StructOutPolicy::Off) {
+        validate_against_schema(&value, &format.json_schema)?;
+    }
+    Ok(value)
+}
+
+/// Best-effort repair of model output into parseable JSON.
+///
+/// Strips markdown fences, extracts the first balanced object/array, quotes bare
+/// keys, drops trailing commas and closes anything left open by truncation.
+pub fn repair_json(raw: &str) -> String {
+    let unfenced = strip_code_fences(raw);
+    let block = extract_json_block(unfenced);
+    fix_structure(block)
+}
+
+fn strip_code_fences(raw: &str) -> &str {
+    let trimmed = raw.trim();
+    let Some(start) = trimmed.find("```") else {
+        return trimmed;
+    };
+    let after = &trimmed[start + 3..];
+    // Skip the info string (e.g. ```json) up to the end of the fence line.
+    let body = match after.find('\n') {
+        Some(idx) => &after[idx + 1..],
+        None => after,
+    };
+    match body.find("```") {
+        Some(end) => body[..end].trim(),
+        None => body.trim(),
+    }
+}
+
+fn extract_json_block(text: &str) -> &str {
+    let Some(start) = text.find(['{', '[']) else {
+        return text.trim();
+    };
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (idx, ch) in text[start..].char_indices() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if ch == '\\' {
+                escaped = true;
+            } else if ch == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match ch {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    return &text[start..start + idx + ch.len_utf8()];
                 }
             }
+            _ => {}
         }
     }
+    text[start..].trim_end()
+}
+
+fn fix_structure(block: &str) -> String {
+    let chars: Vec<char> = block.chars().collect();
+    let mut out = String::with_capacity(block.len() + 8);
+    let mut stack: Vec<char> = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut expect_key = false;
+    let mut pending_key = false;
+    let mut idx = 0;
+
+    while idx < chars.len() {
+        let ch = chars[idx];
+        if in_string {
+            out.push(ch);
+            if escaped {
+                escaped = false;
+            } else if ch == '\\' {
+                escaped = true;
+            } else if ch == '"' {
+                in_string = false;
+            }
+            idx += 1;
+            continue;
+        }
+        match ch {
+            '"' => {
+                in_string = true;
+                pending_key = expect_key;
+                expect_key = false;
+                out.push(ch);
+            }
+            '{' | '[' => {
+                stack.push(ch);
+                expect_key = ch == '{';
+                out.push(ch);
+            }
+            '}' | ']' => {
+                trim_trailing_comma(&mut out);
+                stack.pop();
+                expect_key = false;
+                pending_key = false;
+                out.push(ch);
+            }
+            ',' => {
+                expect_key = stack.last() == Some(&'{');
+                out.push(ch);
+            }
+            ':' => {
+                pending_key = false;
+                out.push(ch);
+            }
+            c if expect_key && (c.is_alphabetic() || c == '_' || c == '$') => {
+                let start = idx;
+                while idx < chars.len()
+                    && (chars[idx].is_alphanumeric() || matches!(chars[idx], '_' | '$' | '-'))
+                {
+                    idx += 1;
+                }
+                out.push('"');
+                out.extend(&chars[start..idx]);
+                out.push('"');
+                expect_key = false;
+                pending_key = true;
+                continue;
+            }
+            c if c.is_whitespace() => out.push(c),
+            c => {
+                expect_key = false;
+                out.push(c);
+            }
+        }
+        idx += 1;
+    }
+
+    if in_string {
+        if escaped {
+            out.pop();
+        }
+        out.push('"');
+    }
+    let trimmed_len = out.trim_end().len();
+    out.truncate(trimmed_len);
+    if out.ends_with(',') {
+        out.pop();
+    }
+    if pending_key {
+        out.push_str(":null");
+    } else if out.ends_with(':') {
+        out.push_str("null");
+    }
+    while let Some(open) = stack.pop() {
+        trim_trailing_comma(&mut out);
+        out.push(if open == '{' { '}' } else { ']' });
+    }
+    out
+}
+
+fn trim_trailing_comma(out: &mut String) {
+    let trimmed_len = out.trim_end().len();
+    if out[..trimmed_len].ends_with(',') {
+        out.truncate(trimmed_len - 1);
+    }
+}
+
+/// Evaluates `value` against `schema`, returning every violation found.
+pub fn schema_violations(
+    value: &Value,
+    schema: &JsonSchema,
+) -> Result<Vec<SchemaViolation>, LlmError> {
+    let schema_json = serde_json::to_value(schema)
+        .map_err(|err| LlmError::schema(format!("serialize json schema failed: {err}")))?;
+    let validator = jsonschema::validator_for(&schema_json)
+        .map_err(|err| LlmError::schema(format!("compile json schema failed: {err}")))?;
+    let violations = validator
+        .iter_errors(value)
+        .map(|err| SchemaViolation {
+            instance_path: err.instance_path.as_str().to_string(),
+            message: err.to_string(),
+        })
+        .collect();
+    Ok(violations)
 }
 
-#[allow(unused_variables)]
 pub fn validate_against_schema(value: &Value, schema: &Option<JsonSchema>) -> Result<(), LlmError> {
-    #[cfg(feature = "schema-json")]
-    {
-        if let Some(schema) = schema {
-            schemars::validate::Validate::validate(schema, value)
-                .map_err(|err| LlmError::schema(&format!("schema validation failed: {err}")))?;
+    let Some(schema) = schema else {
+        return Ok(());
+    };
+    let violations = schema_violations(value, schema)?;
+    if violations.is_empty() {
+        return Ok(());
+    }
+    let detail = violations
+        .iter()
+        .map(|v| {
+            let path = if v.instance_path.is_empty() {
+                "/"
+            } else {
+                v.instance_path.as_str()
+            };
+            format!("{path}: {}", v.message)
+        })
+        .collect::<Vec<_>>()
+        .join("; ");
+    Err(ErrorBuilder::new(codes::SCHEMA_VALIDATION_FAILED)
+        .user_msg("Model output failed schema validation.")
+        .dev_msg(format!("schema validation failed: {detail}"))
+        .meta_kv("violations", json!(violations))
+        .build()
+        .into())
+}
+
+/// Calls `model` and enforces `req.response_format` on the reply.
+///
+/// Under `StrictRepair { max_attempts }` a rejected reply is appended to the
+/// conversation together with the parse/validation errors and the model is asked
+/// again, up to `max_attempts` extra calls. The provider itself is invoked with
+/// `StructOutPolicy::Off` so that enforcement happens here, once.
+pub async fn chat_structured<M>(
+    model: &M,
+    mut req: ChatRequest,
+    policy: &StructOutPolicy,
+) -> Result<(ChatResponse, Value), LlmError>
+where
+    M: ChatModel + ?Sized,
+{
+    let format = req
+        .response_format
+        .clone()
+        .ok_or_else(|| LlmError::schema("chat_structured requires a response_format"))?;
+    let max_reasks = match policy {
+        StructOutPolicy::StrictRepair { max_attempts } => *max_attempts,
+        _ => 0,
+    };
+
+    let mut reasks = 0u8;
+    loop {
+        let resp = model.chat(req.clone(), &StructOutPolicy::Off).await?;
+        let text = message_text(&resp.message);
+        match enforce_format(&text, &format, policy) {
+            Ok(value) => return Ok((resp, value)),
+            Err(err) if reasks < max_reasks => {
+                reasks += 1;
+                let feedback = err.detail().unwrap_or("invalid JSON").to_string();
+                req.messages.push(resp.message);
+                req.messages.push(Message {
+                    role: Role::User,
+                    segments: vec![ContentSegment::Text {
+                        text: format!(
+                            "Your previous reply was rejected: {feedback}. \
+                             Reply again with only the corrected JSON value."
+                        ),
+                    }],
+                    tool_calls: Vec::new(),
+                });
+            }
+            Err(err) => return Err(err),
         }
     }
-    Ok(())
+}
+
+fn message_text(message: &Message) -> String {
+    message
+        .segments
+        .iter()
+        .filter_map(|seg| match seg {
+            ContentSegment::Text { text } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect()
 }
@@ -1,12 +1,24 @@
+#[cfg(feature = "budget")]
+pub mod budget;
+#[cfg(feature = "cache")]
+pub mod cache;
 pub mod chat;
 pub mod cost;
 pub mod embed;
 pub mod errors;
+pub mod guard;
 pub mod jsonsafe;
 pub mod model;
 pub mod observe;
 pub mod prelude;
 pub mod provider;
+#[cfg(feature = "replay")]
+pub mod replay;
 pub mod rerank;
+#[cfg(feature = "retrieval")]
+pub mod retrieval;
+pub mod stream;
+#[cfg(any(feature = "cache", feature = "replay", feature = "retrieval"))]
+mod util;
 
 pub use provider::{LocalProviderFactory, Registry};
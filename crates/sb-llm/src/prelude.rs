@@ -1,14 +1,37 @@
+#[cfg(feature = "budget")]
+pub use crate::budget::{
+    BudgetScope, BudgetedChatModel, LlmBudget, LlmBudgetConfig, ProviderLimits, ProviderRateLimiter,
+};
+#[cfg(feature = "cache")]
+pub use crate::cache::{
+    CachePolicy, CachedChatModel, CachedEmbedModel, LlmCache, MemoryResponseCache, ResponseCache,
+    StorageResponseCache,
+};
 pub use crate::chat::{
     BoxChatModel, ChatDelta, ChatModel, ChatRequest, ChatResponse, ChatStream, ResponseFormat,
     ResponseKind, ToolSpec,
 };
 pub use crate::embed::{EmbedItem, EmbedModel, EmbedRequest, EmbedResponse};
 pub use crate::errors::LlmError;
-pub use crate::jsonsafe::StructOutPolicy;
+pub use crate::guard::{
+    GuardChain, GuardReport, GuardedChatModel, GuardedStream, InjectionAction, InjectionDetector,
+    InputGuard, OutputAction, OutputGuard, OutputTermFilter, PiiRedactor,
+};
+pub use crate::jsonsafe::{chat_structured, repair_json, SchemaViolation, StructOutPolicy};
 pub use crate::model::{
     ContentSegment, Cost, CostBreakdown, FinishReason, Message, Role, ToolCallProposal, Usage,
 };
 pub use crate::provider::{
     LocalProviderFactory, ProviderCaps, ProviderCfg, ProviderFactory, Registry,
 };
+#[cfg(feature = "replay")]
+pub use crate::replay::{
+    FixtureKind, FixtureMode, FixtureStore, RecordReplayFactory, ReplayOptions, TimedDelta,
+};
 pub use crate::rerank::{RerankModel, RerankRequest, RerankResponse};
+#[cfg(feature = "retrieval")]
+pub use crate::retrieval::{
+    chunk_text, ChunkRecord, ChunkStrategy, Citation, Document, IngestReport, RetrievalConfig,
+    RetrievalQuery, RetrievalResult, RetrievedChunk, Retriever, TextChunk,
+};
+pub use crate::stream::{AggregatingStream, CancelHandle, DeltaAccumulator};
@@ -7,7 +7,7 @@ use crate::chat::{
 use crate::cost::{estimate_usage, zero_cost};
 use crate::embed::{EmbedModel, EmbedRequest, EmbedResponse};
 use crate::errors::LlmError;
-use crate::jsonsafe::{enforce_json, validate_against_schema, StructOutPolicy};
+use crate::jsonsafe::{enforce_format, StructOutPolicy};
 use crate::model::{ContentSegment, FinishReason, Message, Role, Usage};
 use crate::rerank::{RerankModel, RerankRequest, RerankResponse};
 use async_trait::async_trait;
@@ -44,6 +44,7 @@ pub trait ProviderFactory: Send + Sync {
     }
 }
 
+#[derive(Default)]
 pub struct Registry {
     inner: HashMap<String, Box<dyn ProviderFactory>>,
 }
@@ -157,8 +158,7 @@ impl ChatModel for LocalChat {
         if let Some(format) = &req.response_format {
             if matches!(format.kind, ResponseKind::Json | ResponseKind::JsonSchema) {
                 let json_candidate = json!({ "echo": last_user }).to_string();
-                let value = enforce_json(&json_candidate, enforce)?;
-                validate_against_schema(&value, &format.json_schema)?;
+                enforce_format(&json_candidate, format, enforce)?;
                 text_out = json_candidate;
             }
         }
@@ -198,8 +198,7 @@ impl ChatModel for LocalChat {
         if let Some(format) = &req.response_format {
             if matches!(format.kind, ResponseKind::Json | ResponseKind::JsonSchema) {
                 let candidate = json!({ "echo": last_user }).to_string();
-                let value = enforce_json(&candidate, enforce)?;
-                validate_against_schema(&value, &format.json_schema)?;
+                enforce_format(&candidate, format, enforce)?;
                 body_text = candidate;
             }
         }
@@ -246,7 +245,7 @@ impl EmbedModel for LocalEmbed {
                 input_tokens: req
                     .items
                     .iter()
-                    .map(|item| ((item.text.len() as u32) + 3) / 4)
+                    .map(|item| (item.text.len() as u32).div_ceil(4))
                     .sum(),
                 output_tokens: 0,
                 cached_tokens: None,
@@ -294,7 +293,7 @@ impl RerankModel for LocalRerank {
             scores: sorted_scores,
             ordering: indices,
             usage: Usage {
-                input_tokens: ((req.query.len() as u32) + 3) / 4,
+                input_tokens: (req.query.len() as u32).div_ceil(4),
                 output_tokens: 0,
                 cached_tokens: None,
                 image_units: None,
@@ -0,0 +1,518 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::chat::{BoxChatModel, ChatDelta, ChatModel, ChatRequest, ChatResponse, ChatStream};
+use crate::embed::{EmbedModel, EmbedRequest, EmbedResponse};
+use crate::errors::LlmError;
+use crate::jsonsafe::StructOutPolicy;
+use crate::provider::{ProviderCaps, ProviderCfg, ProviderFactory};
+use crate::rerank::{RerankModel, RerankRequest, RerankResponse};
+use crate::util::{canonical_json, sha256_hex};
+use async_trait::async_trait;
+use futures_util::{stream, StreamExt};
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FixtureMode {
+    /// Proxy to the wrapped provider and write every exchange to disk.
+    Record,
+    /// Serve exchanges from disk without touching any provider.
+    Replay,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum FixtureKind {
+    Chat,
+    ChatStream,
+    Embed,
+    Rerank,
+}
+
+impl FixtureKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            FixtureKind::Chat => "chat",
+            FixtureKind::ChatStream => "chat_stream",
+            FixtureKind::Embed => "embed",
+            FixtureKind::Rerank => "rerank",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ReplayOptions {
+    /// When no exact match exists, retry with `volatile_fields` dropped from
+    /// both the incoming and the recorded requests.
+    pub fuzzy: bool,
+    /// Top-level request fields ignored by fuzzy matching.
+    pub volatile_fields: Vec<String>,
+    /// Sleep for the recorded gap before each replayed stream delta.
+    pub replay_timing: bool,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        Self {
+            fuzzy: false,
+            volatile_fields: vec!["metadata".into(), "idempotency_key".into(), "seed".into()],
+            replay_timing: false,
+        }
+    }
+}
+
+/// A streamed delta together with the gap since the previous one.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TimedDelta {
+    pub after_ms: u64,
+    pub delta: ChatDelta,
+}
+
+/// One recorded exchange, stored as `<dir>/<kind>-<key>.json`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Fixture {
+    pub kind: FixtureKind,
+    pub key: String,
+    pub request: Value,
+    #[serde(default)]
+    pub response: Value,
+    #[serde(default)]
+    pub deltas: Vec<TimedDelta>,
+}
+
+#[derive(Default)]
+struct FixtureIndex {
+    exact: HashMap<String, Arc<Fixture>>,
+    fuzzy: HashMap<String, Arc<Fixture>>,
+}
+
+/// Directory of fixtures keyed by canonical request hash.
+pub struct FixtureStore {
+    dir: PathBuf,
+    options: ReplayOptions,
+    index: Mutex<Option<Arc<FixtureIndex>>>,
+}
+
+impl FixtureStore {
+    pub fn new(dir: impl Into<PathBuf>, options: ReplayOptions) -> Self {
+        Self {
+            dir: dir.into(),
+            options,
+            index: Mutex::new(None),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Hash of the kind plus the canonical (key-sorted) request JSON.
+    pub fn exact_key(kind: FixtureKind, request: &Value) -> String {
+        sha256_hex(format!("{}\n{}", kind.as_str(), canonical_json(request)).as_bytes())
+    }
+
+    pub fn fuzzy_key(&self, kind: FixtureKind, request: &Value) -> String {
+        let mut stripped = request.clone();
+        if let Value::Object(map) = &mut stripped {
+            for field in &self.options.volatile_fields {
+                map.remove(field);
+            }
+        }
+        Self::exact_key(kind, &stripped)
+    }
+
+    pub fn save(
+        &self,
+        kind: FixtureKind,
+        request: Value,
+        response: Value,
+        deltas: Vec<TimedDelta>,
+    ) -> Result<PathBuf, LlmError> {
+        let key = Self::exact_key(kind, &request);
+        let path = self.dir.join(format!("{}-{key}.json", kind.as_str()));
+        let fixture = Fixture {
+            kind,
+            key,
+            request,
+            response,
+            deltas,
+        };
+        let body = serde_json::to_string_pretty(&fixture)
+            .map_err(|err| LlmError::unknown(format!("serialize fixture failed: {err}")))?;
+        fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&path, body))
+            .map_err(|err| {
+                LlmError::unknown(format!("write fixture {} failed: {err}", path.display()))
+            })?;
+        // Make the new fixture visible to lookups on this store.
+        *self.index.lock() = None;
+        Ok(path)
+    }
+
+    /// Finds the fixture recorded for `request`, trying the exact hash first and
+    /// the fuzzy hash only when enabled. Misses are errors, never silent.
+    pub fn lookup(
+        &self,
+        kind: FixtureKind,
+        request: &Value,
+    ) -> Result<(Arc<Fixture>, bool), LlmError> {
+        let index = self.index()?;
+        let key = Self::exact_key(kind, request);
+        if let Some(fixture) = index.exact.get(&key) {
+            return Ok((fixture.clone(), false));
+        }
+        if self.options.fuzzy {
+            if let Some(fixture) = index.fuzzy.get(&self.fuzzy_key(kind, request)) {
+                return Ok((fixture.clone(), true));
+            }
+        }
+        let model = request
+            .get("model_id")
+            .and_then(Value::as_str)
+            .unwrap_or("?");
+        Err(LlmError::unknown(format!(
+            "no recorded {} fixture for model {model} (key {key}, fuzzy={}) in {}; \
+             re-run with FixtureMode::Record to capture it",
+            kind.as_str(),
+            self.options.fuzzy,
+            self.dir.display()
+        )))
+    }
+
+    fn index(&self) -> Result<Arc<FixtureIndex>, LlmError> {
+        let mut guard = self.index.lock();
+        if let Some(index) = guard.as_ref() {
+            return Ok(index.clone());
+        }
+        let index = Arc::new(self.load_index()?);
+        *guard = Some(index.clone());
+        Ok(index)
+    }
+
+    fn load_index(&self) -> Result<FixtureIndex, LlmError> {
+        let entries = fs::read_dir(&self.dir).map_err(|err| {
+            LlmError::unknown(format!(
+                "read fixture dir {} failed: {err}",
+                self.dir.display()
+            ))
+        })?;
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        paths.sort();
+
+        let mut index = FixtureIndex::default();
+        for path in paths {
+            let fixture: Fixture = fs::read_to_string(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|body| serde_json::from_str(&body).map_err(|err| err.to_string()))
+                .map_err(|err| {
+                    LlmError::unknown(format!("load fixture {} failed: {err}", path.display()))
+                })?;
+            let fixture = Arc::new(fixture);
+            let fuzzy = self.fuzzy_key(fixture.kind, &fixture.request);
+            index.fuzzy.entry(fuzzy).or_insert_with(|| fixture.clone());
+            index
+                .exact
+                .insert(Self::exact_key(fixture.kind, &fixture.request), fixture);
+        }
+        Ok(index)
+    }
+}
+
+/// `ProviderFactory` wrapper that records provider traffic to fixture files or
+/// replays it offline.
+///
+/// In record mode every chat, stream, embed and rerank call is proxied to the
+/// wrapped factory and written to disk once it succeeds. In replay mode the
+/// wrapped factory is never consulted; unmatched requests return an error that
+/// names the missing key.
+pub struct RecordReplayFactory {
+    name: &'static str,
+    caps: ProviderCaps,
+    inner: Option<Box<dyn ProviderFactory>>,
+    store: Arc<FixtureStore>,
+}
+
+impl RecordReplayFactory {
+    pub fn record(inner: Box<dyn ProviderFactory>, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            name: inner.name(),
+            caps: inner.caps(),
+            inner: Some(inner),
+            store: Arc::new(FixtureStore::new(dir, ReplayOptions::default())),
+        }
+    }
+
+    /// Serves fixtures under the provider name `name`, e.g. `"openai"` so that
+    /// `openai:gpt-4o` model ids resolve without credentials.
+    pub fn replay(name: &'static str, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            name,
+            caps: ProviderCaps {
+                chat: true,
+                stream: true,
+                tools: true,
+                embeddings: true,
+                rerank: true,
+                multimodal: true,
+                json_schema: true,
+            },
+            inner: None,
+            store: Arc::new(FixtureStore::new(dir, ReplayOptions::default())),
+        }
+    }
+
+    pub fn with_options(mut self, options: ReplayOptions) -> Self {
+        self.store = Arc::new(FixtureStore::new(self.store.dir().to_path_buf(), options));
+        self
+    }
+
+    pub fn mode(&self) -> FixtureMode {
+        if self.inner.is_some() {
+            FixtureMode::Record
+        } else {
+            FixtureMode::Replay
+        }
+    }
+
+    pub fn store(&self) -> Arc<FixtureStore> {
+        self.store.clone()
+    }
+}
+
+#[async_trait]
+impl ProviderFactory for RecordReplayFactory {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn caps(&self) -> ProviderCaps {
+        self.caps.clone()
+    }
+
+    fn create_chat(&self, model: &str, cfg: &ProviderCfg) -> Option<BoxChatModel> {
+        let store = self.store.clone();
+        match &self.inner {
+            Some(inner) => {
+                let inner = inner.create_chat(model, cfg)?;
+                Some(Box::new(RecordingChat { inner, store }))
+            }
+            None => Some(Box::new(ReplayChat { store })),
+        }
+    }
+
+    fn create_embed(&self, model: &str, cfg: &ProviderCfg) -> Option<Box<dyn EmbedModel>> {
+        let store = self.store.clone();
+        match &self.inner {
+            Some(inner) => {
+                let inner = inner.create_embed(model, cfg)?;
+                Some(Box::new(RecordingEmbed { inner, store }))
+            }
+            None => Some(Box::new(ReplayEmbed { store })),
+        }
+    }
+
+    fn create_rerank(&self, model: &str, cfg: &ProviderCfg) -> Option<Box<dyn RerankModel>> {
+        let store = self.store.clone();
+        match &self.inner {
+            Some(inner) => {
+                let inner = inner.create_rerank(model, cfg)?;
+                Some(Box::new(RecordingRerank { inner, store }))
+            }
+            None => Some(Box::new(ReplayRerank { store })),
+        }
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<Value, LlmError> {
+    serde_json::to_value(value)
+        .map_err(|err| LlmError::unknown(format!("serialize fixture payload failed: {err}")))
+}
+
+fn from_fixture<T: DeserializeOwned>(fixture: &Fixture) -> Result<T, LlmError> {
+    serde_json::from_value(fixture.response.clone()).map_err(|err| {
+        LlmError::unknown(format!(
+            "fixture {} has an unreadable {} response: {err}",
+            fixture.key,
+            fixture.kind.as_str()
+        ))
+    })
+}
+
+fn tag_replayed(meta: &mut Value, fixture: &Fixture, fuzzy: bool) {
+    let tag = json!({ "key": fixture.key, "fuzzy": fuzzy });
+    match meta {
+        Value::Object(map) => {
+            map.insert("replay".into(), tag);
+        }
+        other => *other = json!({ "replay": tag }),
+    }
+}
+
+struct RecordingChat {
+    inner: BoxChatModel,
+    store: Arc<FixtureStore>,
+}
+
+#[async_trait]
+impl ChatModel for RecordingChat {
+    type Stream = ChatStream;
+
+    async fn chat(
+        &self,
+        req: ChatRequest,
+        enforce: &StructOutPolicy,
+    ) -> Result<ChatResponse, LlmError> {
+        let request = to_json(&req)?;
+        let resp = self.inner.chat(req, enforce).await?;
+        self.store
+            .save(FixtureKind::Chat, request, to_json(&resp)?, Vec::new())?;
+        Ok(resp)
+    }
+
+    /// The fixture is written once the upstream stream ends cleanly; streams
+    /// that error or are dropped early are not recorded.
+    async fn chat_stream(
+        &self,
+        req: ChatRequest,
+        enforce: &StructOutPolicy,
+    ) -> Result<Self::Stream, LlmError> {
+        let request = to_json(&req)?;
+        let upstream = self.inner.chat_stream(req, enforce).await?;
+        let store = self.store.clone();
+        let state = (upstream, Vec::<TimedDelta>::new(), Instant::now(), false);
+        let stream = stream::unfold(state, move |(mut upstream, mut deltas, last, done)| {
+            let store = store.clone();
+            let request = request.clone();
+            async move {
+                if done {
+                    return None;
+                }
+                match upstream.next().await {
+                    Some(Ok(delta)) => {
+                        deltas.push(TimedDelta {
+                            after_ms: last.elapsed().as_millis() as u64,
+                            delta: delta.clone(),
+                        });
+                        Some((Ok(delta), (upstream, deltas, Instant::now(), false)))
+                    }
+                    Some(Err(err)) => Some((Err(err), (upstream, deltas, last, true))),
+                    None => {
+                        match store.save(FixtureKind::ChatStream, request, Value::Null, deltas) {
+                            Ok(_) => None,
+                            Err(err) => Some((Err(err), (upstream, Vec::new(), last, true))),
+                        }
+                    }
+                }
+            }
+        });
+        Ok(stream.boxed())
+    }
+}
+
+struct ReplayChat {
+    store: Arc<FixtureStore>,
+}
+
+#[async_trait]
+impl ChatModel for ReplayChat {
+    type Stream = ChatStream;
+
+    async fn chat(
+        &self,
+        req: ChatRequest,
+        _enforce: &StructOutPolicy,
+    ) -> Result<ChatResponse, LlmError> {
+        let (fixture, fuzzy) = self.store.lookup(FixtureKind::Chat, &to_json(&req)?)?;
+        let mut resp: ChatResponse = from_fixture(&fixture)?;
+        tag_replayed(&mut resp.provider_meta, &fixture, fuzzy);
+        Ok(resp)
+    }
+
+    async fn chat_stream(
+        &self,
+        req: ChatRequest,
+        _enforce: &StructOutPolicy,
+    ) -> Result<Self::Stream, LlmError> {
+        let (fixture, _) = self
+            .store
+            .lookup(FixtureKind::ChatStream, &to_json(&req)?)?;
+        let deltas = fixture.deltas.clone();
+        if !self.store.options.replay_timing {
+            return Ok(stream::iter(deltas.into_iter().map(|d| Ok(d.delta))).boxed());
+        }
+        let stream = stream::unfold(deltas.into_iter(), |mut deltas| async move {
+            let timed = deltas.next()?;
+            tokio::time::sleep(Duration::from_millis(timed.after_ms)).await;
+            Some((Ok(timed.delta), deltas))
+        });
+        Ok(stream.boxed())
+    }
+}
+
+struct RecordingEmbed {
+    inner: Box<dyn EmbedModel>,
+    store: Arc<FixtureStore>,
+}
+
+#[async_trait]
+impl EmbedModel for RecordingEmbed {
+    async fn embed(&self, req: EmbedRequest) -> Result<EmbedResponse, LlmError> {
+        let request = to_json(&req)?;
+        let resp = self.inner.embed(req).await?;
+        self.store
+            .save(FixtureKind::Embed, request, to_json(&resp)?, Vec::new())?;
+        Ok(resp)
+    }
+}
+
+struct ReplayEmbed {
+    store: Arc<FixtureStore>,
+}
+
+#[async_trait]
+impl EmbedModel for ReplayEmbed {
+    async fn embed(&self, req: EmbedRequest) -> Result<EmbedResponse, LlmError> {
+        let (fixture, fuzzy) = self.store.lookup(FixtureKind::Embed, &to_json(&req)?)?;
+        let mut resp: EmbedResponse = from_fixture(&fixture)?;
+        tag_replayed(&mut resp.provider_meta, &fixture, fuzzy);
+        Ok(resp)
+    }
+}
+
+struct RecordingRerank {
+    inner: Box<dyn RerankModel>,
+    store: Arc<FixtureStore>,
+}
+
+#[async_trait]
+impl RerankModel for RecordingRerank {
+    async fn rerank(&self, req: RerankRequest) -> Result<RerankResponse, LlmError> {
+        let request = to_json(&req)?;
+        let resp = self.inner.rerank(req).await?;
+        self.store
+            .save(FixtureKind::Rerank, request, to_json(&resp)?, Vec::new())?;
+        Ok(resp)
+    }
+}
+
+struct ReplayRerank {
+    store: Arc<FixtureStore>,
+}
+
+#[async_trait]
+impl RerankModel for ReplayRerank {
+    async fn rerank(&self, req: RerankRequest) -> Result<RerankResponse, LlmError> {
+        let (fixture, fuzzy) = self.store.lookup(FixtureKind::Rerank, &to_json(&req)?)?;
+        let mut resp: RerankResponse = from_fixture(&fixture)?;
+        tag_replayed(&mut resp.provider_meta, &fixture, fuzzy);
+        Ok(resp)
+    }
+}
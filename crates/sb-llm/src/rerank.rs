@@ -25,3 +25,10 @@ pub struct RerankResponse {
 pub trait RerankModel: Send + Sync {
     async fn rerank(&self, req: RerankRequest) -> Result<RerankResponse, LlmError>;
 }
+
+#[async_trait]
+impl<M: RerankModel + ?Sized> RerankModel for Box<M> {
+    async fn rerank(&self, req: RerankRequest) -> Result<RerankResponse, LlmError> {
+        (**self).rerank(req).await
+    }
+}
@@ -0,0 +1,709 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::embed::{EmbedItem, EmbedModel, EmbedRequest, EmbedResponse};
+use crate::errors::LlmError;
+use crate::rerank::{RerankModel, RerankRequest};
+use crate::util::{now_ms, sha256_hex};
+use sb_storage::errors::StorageError;
+use sb_storage::model::{make_record_id, Entity, Sort};
+use sb_storage::spi::repo::Repository;
+use sb_storage::spi::vector::VectorIndex;
+use sb_types::prelude::TenantId;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/* ------------------------------------------------------------------
+ * Chunking
+ * ------------------------------------------------------------------ */
+
+/// How documents are split before embedding. Sizes and overlaps are counted in
+/// characters, except `Sentence::overlap` which counts whole sentences.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChunkStrategy {
+    Fixed {
+        size: usize,
+        overlap: usize,
+    },
+    Sentence {
+        max_chars: usize,
+        overlap: usize,
+    },
+    /// One chunk per heading section; sections longer than `max_chars` fall
+    /// back to fixed windows with `overlap`.
+    MarkdownHeading {
+        max_chars: usize,
+        overlap: usize,
+    },
+}
+
+/// A chunk of a document; `start..end` are byte offsets into the source text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextChunk {
+    pub ordinal: u32,
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+    /// Heading path (`"Guide > Install"`) for markdown chunks.
+    pub heading: Option<String>,
+}
+
+pub fn chunk_text(text: &str, strategy: &ChunkStrategy) -> Vec<TextChunk> {
+    let mut chunks = Vec::new();
+    match strategy {
+        ChunkStrategy::Fixed { size, overlap } => {
+            for range in fixed_windows(text, 0, *size, *overlap) {
+                push_chunk(&mut chunks, text, range, None);
+            }
+        }
+        ChunkStrategy::Sentence { max_chars, overlap } => {
+            for range in sentence_windows(text, *max_chars, *overlap) {
+                push_chunk(&mut chunks, text, range, None);
+            }
+        }
+        ChunkStrategy::MarkdownHeading { max_chars, overlap } => {
+            for (range, heading) in markdown_sections(text) {
+                let section = &text[range.clone()];
+                if section.chars().count() <= *max_chars {
+                    push_chunk(&mut chunks, text, range, heading);
+                    continue;
+                }
+                for window in fixed_windows(section, range.start, *max_chars, *overlap) {
+                    push_chunk(&mut chunks, text, window, heading.clone());
+                }
+            }
+        }
+    }
+    chunks
+}
+
+fn push_chunk(out: &mut Vec<TextChunk>, text: &str, range: Range<usize>, heading: Option<String>) {
+    let slice = &text[range.clone()];
+    let trimmed = slice.trim();
+    if trimmed.is_empty() {
+        return;
+    }
+    let start = range.start + (slice.len() - slice.trim_start().len());
+    out.push(TextChunk {
+        ordinal: out.len() as u32,
+        text: trimmed.to_string(),
+        start,
+        end: start + trimmed.len(),
+        heading,
+    });
+}
+
+fn fixed_windows(text: &str, base: usize, size: usize, overlap: usize) -> Vec<Range<usize>> {
+    let bounds: Vec<usize> = text
+        .char_indices()
+        .map(|(idx, _)| idx)
+        .chain(std::iter::once(text.len()))
+        .collect();
+    let chars = bounds.len() - 1;
+    let size = size.max(1);
+    let step = size.saturating_sub(overlap).max(1);
+    let mut windows = Vec::new();
+    let mut start = 0;
+    while start < chars {
+        let end = (start + size).min(chars);
+        windows.push(base + bounds[start]..base + bounds[end]);
+        if end == chars {
+            break;
+        }
+        start += step;
+    }
+    windows
+}
+
+fn sentence_spans(text: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((idx, ch)) = chars.next() {
+        let next = chars.peek().map(|(_, c)| *c);
+        let terminal = matches!(ch, '。' | '！' | '？')
+            || (matches!(ch, '.' | '!' | '?') && next.is_none_or(char::is_whitespace))
+            || (ch == '\n' && next == Some('\n'));
+        if terminal {
+            let end = idx + ch.len_utf8();
+            spans.push(start..end);
+            start = end;
+        }
+    }
+    if start < text.len() {
+        spans.push(start..text.len());
+    }
+    // Leading/trailing whitespace belongs to no sentence.
+    spans
+        .into_iter()
+        .filter_map(|span| {
+            let slice = &text[span.clone()];
+            let trimmed = slice.trim();
+            if trimmed.is_empty() {
+                return None;
+            }
+            let start = span.start + (slice.len() - slice.trim_start().len());
+            Some(start..start + trimmed.len())
+        })
+        .collect()
+}
+
+fn sentence_windows(text: &str, max_chars: usize, overlap: usize) -> Vec<Range<usize>> {
+    let spans = sentence_spans(text);
+    let len_of = |span: &Range<usize>| text[span.clone()].chars().count();
+    let mut windows = Vec::new();
+    let mut first = 0;
+    while first < spans.len() {
+        // A single sentence longer than the budget is cut into fixed windows.
+        if len_of(&spans[first]) > max_chars {
+            let span = spans[first].clone();
+            windows.extend(fixed_windows(&text[span.clone()], span.start, max_chars, 0));
+            first += 1;
+            continue;
+        }
+        let mut last = first;
+        while last + 1 < spans.len()
+            && len_of(&(spans[first].start..spans[last + 1].end)) <= max_chars
+        {
+            last += 1;
+        }
+        windows.push(spans[first].start..spans[last].end);
+        if last + 1 >= spans.len() {
+            break;
+        }
+        let taken = last - first + 1;
+        first = last + 1 - overlap.min(taken - 1);
+    }
+    windows
+}
+
+fn markdown_sections(text: &str) -> Vec<(Range<usize>, Option<String>)> {
+    let mut sections = Vec::new();
+    let mut path: Vec<(usize, String)> = Vec::new();
+    let mut current: Option<String> = None;
+    let mut start = 0;
+    let mut offset = 0;
+    let mut in_fence = false;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") {
+            in_fence = !in_fence;
+        }
+        let level = trimmed.chars().take_while(|c| *c == '#').count();
+        let is_heading =
+            !in_fence && (1..=6).contains(&level) && trimmed[level..].starts_with([' ', '\t']);
+        if is_heading {
+            if offset > start {
+                sections.push((start..offset, current.clone()));
+            }
+            path.retain(|(lvl, _)| *lvl < level);
+            path.push((level, trimmed[level..].trim().to_string()));
+            current = Some(
+                path.iter()
+                    .map(|(_, title)| title.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" > "),
+            );
+            start = offset;
+        }
+        offset += line.len();
+    }
+    if offset > start {
+        sections.push((start..offset, current));
+    }
+    sections
+}
+
+/* ------------------------------------------------------------------
+ * Storage
+ * ------------------------------------------------------------------ */
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Document {
+    pub id: String,
+    pub text: String,
+    /// Title or URI shown in citations.
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub metadata: Value,
+}
+
+/// A stored chunk. The id is derived from the document id and the chunk's
+/// content digest, so unchanged text keeps its id (and vector) across
+/// re-ingestion.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ChunkRecord {
+    pub id: String,
+    pub tenant: String,
+    pub doc_id: String,
+    pub ordinal: u32,
+    pub text: String,
+    pub digest: String,
+    #[serde(default)]
+    pub heading: Option<String>,
+    #[serde(default)]
+    pub source: Option<String>,
+    pub start: usize,
+    pub end: usize,
+    #[serde(default)]
+    pub metadata: Value,
+    pub updated_at: i64,
+}
+
+impl Entity for ChunkRecord {
+    const TABLE: &'static str = "llm_chunk";
+    type Key = String;
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+fn chunk_id(tenant: &TenantId, doc_id: &str, digest: &str) -> String {
+    let doc = sha256_hex(doc_id.as_bytes());
+    make_record_id(
+        ChunkRecord::TABLE,
+        tenant,
+        &format!("{}_{}", &doc[..16], &digest[..16]),
+    )
+}
+
+/// Chunks read per storage round-trip when loading a document's chunks.
+const CHUNK_PAGE_SIZE: usize = 200;
+
+fn storage_err(err: StorageError) -> LlmError {
+    LlmError::from(err.into_inner())
+}
+
+/* ------------------------------------------------------------------
+ * Retriever
+ * ------------------------------------------------------------------ */
+
+#[derive(Clone, Debug)]
+pub struct RetrievalConfig {
+    pub strategy: ChunkStrategy,
+    pub embed_model_id: String,
+    pub normalize: bool,
+    /// Items per embedding request.
+    pub batch_size: usize,
+    /// Extra attempts for transient embedding failures.
+    pub max_retries: u32,
+    /// First retry delay when the error carries no backoff hint; doubles per attempt.
+    pub retry_backoff_ms: u64,
+}
+
+impl RetrievalConfig {
+    pub fn new(embed_model_id: impl Into<String>) -> Self {
+        Self {
+            strategy: ChunkStrategy::Sentence {
+                max_chars: 800,
+                overlap: 1,
+            },
+            embed_model_id: embed_model_id.into(),
+            normalize: true,
+            batch_size: 32,
+            max_retries: 2,
+            retry_backoff_ms: 200,
+        }
+    }
+
+    pub fn with_strategy(mut self, strategy: ChunkStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct IngestReport {
+    pub doc_id: String,
+    pub chunks: usize,
+    pub embedded: usize,
+    pub reused: usize,
+    pub removed: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct RetrievalQuery {
+    pub text: String,
+    /// Chunks returned after reranking.
+    pub top_k: usize,
+    /// Chunks fetched from the vector index; only differs from `top_k` when a
+    /// reranker is configured.
+    pub candidates: usize,
+    /// Upper bound on the assembled context, in characters.
+    pub max_context_chars: usize,
+}
+
+impl RetrievalQuery {
+    pub fn new(text: impl Into<String>, top_k: usize) -> Self {
+        Self {
+            text: text.into(),
+            top_k,
+            candidates: top_k.saturating_mul(4),
+            max_context_chars: 4000,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct Citation {
+    /// 1-based marker used in the assembled context (`[1]`, `[2]`, ...).
+    pub marker: usize,
+    pub doc_id: String,
+    pub chunk_id: String,
+    pub source: Option<String>,
+    pub heading: Option<String>,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetrievedChunk {
+    pub chunk: ChunkRecord,
+    pub distance: f32,
+    pub rerank_score: Option<f32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetrievalResult {
+    pub chunks: Vec<RetrievedChunk>,
+    /// Chunks rendered as `[n] source › heading` blocks, ready for a prompt.
+    pub context: String,
+    pub citations: Vec<Citation>,
+}
+
+struct RerankStage {
+    model: Box<dyn RerankModel>,
+    model_id: String,
+}
+
+/// Ingestion and query pipeline over an `EmbedModel`, a chunk repository and
+/// a `VectorIndex`, optionally followed by a `RerankModel`.
+pub struct Retriever<V> {
+    embed: Box<dyn EmbedModel>,
+    rerank: Option<RerankStage>,
+    repo: Arc<dyn Repository<ChunkRecord>>,
+    vectors: Arc<V>,
+    config: RetrievalConfig,
+}
+
+impl<V: VectorIndex> Retriever<V> {
+    pub fn new(
+        embed: Box<dyn EmbedModel>,
+        repo: Arc<dyn Repository<ChunkRecord>>,
+        vectors: Arc<V>,
+        config: RetrievalConfig,
+    ) -> Self {
+        Self {
+            embed,
+            rerank: None,
+            repo,
+            vectors,
+            config,
+        }
+    }
+
+    pub fn with_rerank(mut self, model: Box<dyn RerankModel>, model_id: impl Into<String>) -> Self {
+        self.rerank = Some(RerankStage {
+            model,
+            model_id: model_id.into(),
+        });
+        self
+    }
+
+    /// Chunks `doc` and syncs its chunks for `tenant`: chunks whose digest is
+    /// already stored keep their vectors, new ones are embedded in batches and
+    /// chunks no longer present are removed.
+    pub async fn ingest(
+        &self,
+        tenant: &TenantId,
+        doc: &Document,
+    ) -> Result<IngestReport, LlmError> {
+        let existing: HashMap<String, ChunkRecord> = self
+            .doc_chunks(tenant, &doc.id)
+            .await?
+            .into_iter()
+            .map(|record| (record.id.clone(), record))
+            .collect();
+
+        let now = now_ms();
+        let mut seen = HashSet::new();
+        let mut wanted = Vec::new();
+        for chunk in chunk_text(&doc.text, &self.config.strategy) {
+            let digest = sha256_hex(chunk.text.as_bytes());
+            let id = chunk_id(tenant, &doc.id, &digest);
+            // Identical text within a document would map to the same record.
+            if !seen.insert(id.clone()) {
+                continue;
+            }
+            wanted.push(ChunkRecord {
+                id,
+                tenant: tenant.0.clone(),
+                doc_id: doc.id.clone(),
+                ordinal: wanted.len() as u32,
+                text: chunk.text,
+                digest,
+                heading: chunk.heading,
+                source: doc.source.clone(),
+                start: chunk.start,
+                end: chunk.end,
+                metadata: doc.metadata.clone(),
+                updated_at: now,
+            });
+        }
+
+        let mut report = IngestReport {
+            doc_id: doc.id.clone(),
+            chunks: wanted.len(),
+            ..IngestReport::default()
+        };
+        let mut fresh = Vec::new();
+        for record in wanted {
+            match existing.get(&record.id) {
+                Some(stored) => {
+                    report.reused += 1;
+                    if !same_placement(stored, &record) {
+                        let patch = json!({
+                            "ordinal": record.ordinal,
+                            "heading": record.heading,
+                            "source": record.source,
+                            "start": record.start,
+                            "end": record.end,
+                            "metadata": record.metadata,
+                            "updated_at": record.updated_at,
+                        });
+                        self.repo
+                            .upsert(tenant, &record.id, patch, None)
+                            .await
+                            .map_err(storage_err)?;
+                    }
+                }
+                None => fresh.push(record),
+            }
+        }
+
+        for batch in fresh.chunks(self.config.batch_size.max(1)) {
+            let items = batch
+                .iter()
+                .map(|record| EmbedItem {
+                    id: record.id.clone(),
+                    text: record.text.clone(),
+                })
+                .collect();
+            let vectors = self.embed_items(items).await?;
+            for (record, vector) in batch.iter().zip(vectors) {
+                self.repo
+                    .create(tenant, record)
+                    .await
+                    .map_err(storage_err)?;
+                self.vectors
+                    .upsert_vec(tenant, &record.id, &vector)
+                    .await
+                    .map_err(storage_err)?;
+                report.embedded += 1;
+            }
+        }
+
+        for id in existing.keys().filter(|id| !seen.contains(*id)) {
+            self.remove_chunk(tenant, id).await?;
+            report.removed += 1;
+        }
+        Ok(report)
+    }
+
+    /// Removes every chunk and vector of `doc_id`, returning how many were dropped.
+    pub async fn remove(&self, tenant: &TenantId, doc_id: &str) -> Result<usize, LlmError> {
+        let chunks = self.doc_chunks(tenant, doc_id).await?;
+        for chunk in &chunks {
+            self.remove_chunk(tenant, &chunk.id).await?;
+        }
+        Ok(chunks.len())
+    }
+
+    /// knn over the tenant's chunks, optional rerank, then context assembly.
+    pub async fn query(
+        &self,
+        tenant: &TenantId,
+        query: &RetrievalQuery,
+    ) -> Result<RetrievalResult, LlmError> {
+        let mut vectors = self
+            .embed_items(vec![EmbedItem {
+                id: "query".into(),
+                text: query.text.clone(),
+            }])
+            .await?;
+        let vector = vectors.pop().unwrap_or_default();
+        let k = if self.rerank.is_some() {
+            query.candidates.max(query.top_k)
+        } else {
+            query.top_k
+        };
+        let hits = self
+            .vectors
+            .knn::<ChunkRecord>(tenant, &vector, k, Some(self.repo.as_ref()))
+            .await
+            .map_err(storage_err)?;
+        let mut chunks: Vec<RetrievedChunk> = hits
+            .into_iter()
+            .map(|(chunk, distance)| RetrievedChunk {
+                chunk,
+                distance,
+                rerank_score: None,
+            })
+            .collect();
+
+        if let Some(stage) = &self.rerank {
+            if !chunks.is_empty() {
+                let resp = stage
+                    .model
+                    .rerank(RerankRequest {
+                        model_id: stage.model_id.clone(),
+                        query: query.text.clone(),
+                        candidates: chunks.iter().map(|c| c.chunk.text.clone()).collect(),
+                    })
+                    .await?;
+                let mut slots: Vec<Option<RetrievedChunk>> = chunks.into_iter().map(Some).collect();
+                chunks = resp
+                    .ordering
+                    .iter()
+                    .zip(resp.scores.iter())
+                    .filter_map(|(&idx, &score)| {
+                        let mut chunk = slots.get_mut(idx)?.take()?;
+                        chunk.rerank_score = Some(score);
+                        Some(chunk)
+                    })
+                    .collect();
+            }
+        }
+        chunks.truncate(query.top_k);
+
+        let (context, citations) = assemble_context(&chunks, query.max_context_chars);
+        Ok(RetrievalResult {
+            chunks,
+            context,
+            citations,
+        })
+    }
+
+    async fn doc_chunks(
+        &self,
+        tenant: &TenantId,
+        doc_id: &str,
+    ) -> Result<Vec<ChunkRecord>, LlmError> {
+        let mut chunks = Vec::new();
+        let mut cursor = None;
+        loop {
+            // The storage cursor resumes after the last id seen, so sort by id.
+            let order = vec![Sort {
+                field: "id".into(),
+                asc: true,
+            }];
+            let page = self
+                .repo
+                .select(
+                    tenant,
+                    json!({ "doc_id": doc_id }),
+                    Some(order),
+                    CHUNK_PAGE_SIZE,
+                    cursor,
+                )
+                .await
+                .map_err(storage_err)?;
+            chunks.extend(page.items);
+            match page.next {
+                Some(next) => cursor = Some(next),
+                None => return Ok(chunks),
+            }
+        }
+    }
+
+    async fn remove_chunk(&self, tenant: &TenantId, id: &str) -> Result<(), LlmError> {
+        self.vectors
+            .remove_vec(tenant, id)
+            .await
+            .map_err(storage_err)?;
+        self.repo.delete(tenant, id).await.map_err(storage_err)
+    }
+
+    async fn embed_items(&self, items: Vec<EmbedItem>) -> Result<Vec<Vec<f32>>, LlmError> {
+        let expected = items.len();
+        let req = EmbedRequest {
+            model_id: self.config.embed_model_id.clone(),
+            items,
+            normalize: self.config.normalize,
+            pooling: None,
+        };
+        let resp = self.embed_with_retry(req).await?;
+        if resp.vectors.len() != expected {
+            return Err(LlmError::provider_unavailable(format!(
+                "embedding provider returned {} vectors for {expected} items",
+                resp.vectors.len()
+            )));
+        }
+        Ok(resp.vectors)
+    }
+
+    async fn embed_with_retry(&self, req: EmbedRequest) -> Result<EmbedResponse, LlmError> {
+        let mut attempt = 0u32;
+        loop {
+            match self.embed.embed(req.clone()).await {
+                Ok(resp) => return Ok(resp),
+                Err(err) if err.is_transient() && attempt < self.config.max_retries => {
+                    // Capped so large `max_retries` cannot overflow the shift.
+                    let factor = 1u64 << attempt.min(20);
+                    let delay = match err.backoff_hint() {
+                        Some(hint) => hint.initial_ms.saturating_mul(factor).min(hint.max_ms),
+                        None => self.config.retry_backoff_ms.saturating_mul(factor),
+                    };
+                    tokio::time::sleep(Duration::from_millis(delay)).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+fn same_placement(stored: &ChunkRecord, wanted: &ChunkRecord) -> bool {
+    stored.ordinal == wanted.ordinal
+        && stored.start == wanted.start
+        && stored.end == wanted.end
+        && stored.heading == wanted.heading
+        && stored.source == wanted.source
+        && stored.metadata == wanted.metadata
+}
+
+fn assemble_context(chunks: &[RetrievedChunk], max_chars: usize) -> (String, Vec<Citation>) {
+    let mut context = String::new();
+    let mut citations = Vec::new();
+    for retrieved in chunks {
+        let chunk = &retrieved.chunk;
+        let marker = citations.len() + 1;
+        let label = match (&chunk.source, &chunk.heading) {
+            (Some(source), Some(heading)) => format!("{source} › {heading}"),
+            (Some(source), None) => source.clone(),
+            (None, Some(heading)) => format!("{} › {heading}", chunk.doc_id),
+            (None, None) => chunk.doc_id.clone(),
+        };
+        let block = format!("[{marker}] {label}\n{}\n\n", chunk.text);
+        if context.chars().count() + block.chars().count() > max_chars && !citations.is_empty() {
+            break;
+        }
+        context.push_str(&block);
+        citations.push(Citation {
+            marker,
+            doc_id: chunk.doc_id.clone(),
+            chunk_id: chunk.id.clone(),
+            source: chunk.source.clone(),
+            heading: chunk.heading.clone(),
+            start: chunk.start,
+            end: chunk.end,
+        });
+    }
+    let trimmed = context.trim_end().len();
+    context.truncate(trimmed);
+    (context, citations)
+}
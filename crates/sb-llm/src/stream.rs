@@ -0,0 +1,337 @@
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use crate::chat::{ChatDelta, ChatRequest, ChatResponse, ResponseFormat, ResponseKind};
+use crate::errors::LlmError;
+use crate::jsonsafe::{enforce_format, repair_json, StructOutPolicy};
+use crate::model::{ContentSegment, FinishReason, Message, Role, ToolCallProposal, Usage};
+use futures_core::Stream;
+use futures_util::task::AtomicWaker;
+use futures_util::StreamExt;
+use sb_types::prelude::Id;
+use serde_json::{json, Value};
+
+/// Handle that aborts an aggregated stream from another task.
+///
+/// Cancelling drops the upstream provider stream on the next poll, which closes
+/// the underlying connection, and the aggregated response finishes with
+/// `FinishReason::Other("cancelled")`. Cancelling a stream that has already
+/// ended changes nothing.
+#[derive(Clone, Default)]
+pub struct CancelHandle {
+    inner: Arc<CancelState>,
+}
+
+#[derive(Default)]
+struct CancelState {
+    cancelled: AtomicBool,
+    waker: AtomicWaker,
+}
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        self.inner.waker.wake();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+struct ToolCallAcc {
+    name: String,
+    call_id: Id,
+    arguments: Value,
+    fragments: String,
+}
+
+/// Accumulates `ChatDelta`s into the pieces of a final `ChatResponse`.
+///
+/// `usage_partial` values are treated as cumulative snapshots, so the largest
+/// value seen per field wins.
+pub struct DeltaAccumulator {
+    started: Instant,
+    text: String,
+    tool_calls: Vec<ToolCallAcc>,
+    usage: Option<Usage>,
+    finish: Option<FinishReason>,
+    first_token_ms: Option<u32>,
+    deltas: u32,
+}
+
+impl Default for DeltaAccumulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeltaAccumulator {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            text: String::new(),
+            tool_calls: Vec::new(),
+            usage: None,
+            finish: None,
+            first_token_ms: None,
+            deltas: 0,
+        }
+    }
+
+    pub fn push(&mut self, delta: &ChatDelta) {
+        self.deltas += 1;
+        let has_token = delta.text_delta.as_deref().is_some_and(|t| !t.is_empty())
+            || delta.tool_call_delta.is_some();
+        if self.first_token_ms.is_none() && has_token {
+            let observed = self.started.elapsed().as_millis().min(u32::MAX as u128) as u32;
+            self.first_token_ms = Some(delta.first_token_ms.unwrap_or(observed));
+        }
+        if let Some(text) = &delta.text_delta {
+            self.text.push_str(text);
+        }
+        if let Some(call) = &delta.tool_call_delta {
+            self.merge_tool_call(call);
+        }
+        if let Some(partial) = &delta.usage_partial {
+            self.usage = Some(match self.usage.take() {
+                Some(acc) => merge_usage(acc, partial),
+                None => partial.clone(),
+            });
+        }
+        if let Some(finish) = &delta.finish {
+            self.finish = Some(finish.clone());
+        }
+    }
+
+    fn merge_tool_call(&mut self, call: &ToolCallProposal) {
+        let idx = match self
+            .tool_calls
+            .iter()
+            .position(|acc| acc.call_id == call.call_id)
+        {
+            Some(idx) => idx,
+            None => {
+                self.tool_calls.push(ToolCallAcc {
+                    name: String::new(),
+                    call_id: call.call_id.clone(),
+                    arguments: Value::Null,
+                    fragments: String::new(),
+                });
+                self.tool_calls.len() - 1
+            }
+        };
+        let acc = &mut self.tool_calls[idx];
+        if !call.name.is_empty() {
+            acc.name = call.name.clone();
+        }
+        match &call.arguments {
+            Value::Null => {}
+            Value::String(fragment) => acc.fragments.push_str(fragment),
+            Value::Object(map) => {
+                if let Value::Object(existing) = &mut acc.arguments {
+                    existing.extend(map.clone());
+                } else {
+                    acc.arguments = Value::Object(map.clone());
+                }
+            }
+            other => acc.arguments = other.clone(),
+        }
+    }
+
+    pub fn first_token_ms(&self) -> Option<u32> {
+        self.first_token_ms
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Builds the final response, applying `policy` when `format` asks for JSON.
+    pub fn finish(
+        self,
+        model_id: String,
+        format: Option<&ResponseFormat>,
+        policy: &StructOutPolicy,
+    ) -> Result<ChatResponse, LlmError> {
+        let mut text = self.text;
+        if let Some(format) = format {
+            if matches!(format.kind, ResponseKind::Json | ResponseKind::JsonSchema) {
+                let value = enforce_format(&text, format, policy)?;
+                if serde_json::from_str::<Value>(&text).is_err() {
+                    text = value.to_string();
+                }
+            }
+        }
+
+        let mut tool_calls = Vec::with_capacity(self.tool_calls.len());
+        for acc in self.tool_calls {
+            let call_id = acc.call_id;
+            let arguments = if acc.fragments.is_empty() {
+                acc.arguments
+            } else {
+                serde_json::from_str(&acc.fragments)
+                    .or_else(|_| serde_json::from_str(&repair_json(&acc.fragments)))
+                    .map_err(|err| {
+                        LlmError::schema(format!(
+                            "tool call {call_id} arguments are not valid json: {err}"
+                        ))
+                    })?
+            };
+            tool_calls.push(ToolCallProposal {
+                name: acc.name,
+                call_id,
+                arguments,
+            });
+        }
+
+        let finish = match self.finish {
+            Some(finish) => finish,
+            None if !tool_calls.is_empty() => FinishReason::Tool,
+            None => FinishReason::Stop,
+        };
+        let segments = if text.is_empty() {
+            Vec::new()
+        } else {
+            vec![ContentSegment::Text { text }]
+        };
+
+        Ok(ChatResponse {
+            model_id,
+            message: Message {
+                role: Role::Assistant,
+                segments,
+                tool_calls,
+            },
+            usage: self.usage.unwrap_or_default(),
+            cost: None,
+            finish,
+            provider_meta: json!({
+                "streamed": true,
+                "deltas": self.deltas,
+                "first_token_ms": self.first_token_ms,
+            }),
+        })
+    }
+}
+
+fn merge_usage(acc: Usage, partial: &Usage) -> Usage {
+    fn max_opt<T: PartialOrd + Copy>(a: Option<T>, b: Option<T>) -> Option<T> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(if b > a { b } else { a }),
+            (a, b) => a.or(b),
+        }
+    }
+    Usage {
+        input_tokens: acc.input_tokens.max(partial.input_tokens),
+        output_tokens: acc.output_tokens.max(partial.output_tokens),
+        cached_tokens: max_opt(acc.cached_tokens, partial.cached_tokens),
+        image_units: max_opt(acc.image_units, partial.image_units),
+        audio_seconds: max_opt(acc.audio_seconds, partial.audio_seconds),
+        requests: acc.requests.max(partial.requests),
+    }
+}
+
+/// Pass-through stream over a provider `ChatStream` that accumulates every
+/// delta it yields, so callers can forward tokens and still obtain a
+/// `ChatResponse` once the stream is drained.
+pub struct AggregatingStream<S> {
+    upstream: Option<S>,
+    acc: DeltaAccumulator,
+    cancel: CancelHandle,
+    cancelled: bool,
+    model_id: String,
+    format: Option<ResponseFormat>,
+    policy: StructOutPolicy,
+}
+
+impl<S> AggregatingStream<S>
+where
+    S: Stream<Item = Result<ChatDelta, LlmError>> + Unpin,
+{
+    pub fn new(upstream: S, req: &ChatRequest, policy: StructOutPolicy) -> Self {
+        Self {
+            upstream: Some(upstream),
+            acc: DeltaAccumulator::new(),
+            cancel: CancelHandle::default(),
+            cancelled: false,
+            model_id: req.model_id.clone(),
+            format: req.response_format.clone(),
+            policy,
+        }
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    pub fn first_token_ms(&self) -> Option<u32> {
+        self.acc.first_token_ms()
+    }
+
+    /// Drives the stream to completion, handing each delta to `on_delta`.
+    pub async fn collect_with<F>(mut self, mut on_delta: F) -> Result<ChatResponse, LlmError>
+    where
+        F: FnMut(&ChatDelta),
+    {
+        while let Some(delta) = self.next().await {
+            on_delta(&delta?);
+        }
+        self.into_response()
+    }
+
+    pub async fn collect(self) -> Result<ChatResponse, LlmError> {
+        self.collect_with(|_| {}).await
+    }
+
+    /// Finalises whatever has been accumulated so far.
+    pub fn into_response(self) -> Result<ChatResponse, LlmError> {
+        // Only a cancel that lands before the stream ends cuts it short.
+        let cancelled = self.cancelled || (self.upstream.is_some() && self.cancel.is_cancelled());
+        let mut acc = self.acc;
+        if cancelled {
+            acc.finish = Some(FinishReason::Other("cancelled".into()));
+            // A cancelled stream is partial by definition; skip JSON enforcement.
+            return acc.finish(self.model_id, None, &StructOutPolicy::Off);
+        }
+        acc.finish(self.model_id, self.format.as_ref(), &self.policy)
+    }
+}
+
+impl<S> Stream for AggregatingStream<S>
+where
+    S: Stream<Item = Result<ChatDelta, LlmError>> + Unpin,
+{
+    type Item = Result<ChatDelta, LlmError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        this.cancel.inner.waker.register(cx.waker());
+        if this.upstream.is_some() && this.cancel.is_cancelled() {
+            this.upstream = None;
+            this.cancelled = true;
+            return Poll::Ready(None);
+        }
+        let Some(upstream) = this.upstream.as_mut() else {
+            return Poll::Ready(None);
+        };
+        match upstream.poll_next_unpin(cx) {
+            Poll::Ready(Some(Ok(delta))) => {
+                this.acc.push(&delta);
+                Poll::Ready(Some(Ok(delta)))
+            }
+            Poll::Ready(Some(Err(err))) => {
+                this.upstream = None;
+                Poll::Ready(Some(Err(err)))
+            }
+            Poll::Ready(None) => {
+                this.upstream = None;
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}